@@ -1,18 +1,36 @@
+use std::rc::Rc;
 use crate::names::*;
+use crate::parser::lexer::Cursor;
 use crate::parser::token::Token;
 
 #[derive(Debug, PartialEq)]
 pub struct Program {
     pub toplevel_defs: Vec<Definition>,
     pub exprs: Vec<AstExpression>,
+    /// `require`s in the order of appearance
+    pub requires: Vec<Require>,
 }
 
-#[derive(Debug, PartialEq)]
+/// `require "path"`
+#[derive(Debug, PartialEq, Clone)]
+pub struct Require {
+    pub path: String,
+    pub locs: LocationSpan,
+}
+
+#[derive(Debug, PartialEq, Clone)]
 pub enum Definition {
     ClassDefinition {
         name: ClassFirstname,
+        /// Interfaces the class implements (`class A : I1, I2`)
+        interfaces: Vec<ClassFirstname>,
         defs: Vec<Definition>,
     },
+    /// `interface I` and the signatures of its methods
+    InterfaceDefinition {
+        name: ClassFirstname,
+        sigs: Vec<AstMethodSignature>,
+    },
     InitializerDefinition {
         sig: InitializerSig,
         body_exprs: Vec<AstExpression>,
@@ -25,46 +43,138 @@ pub enum Definition {
         sig: AstMethodSignature,
         body_exprs: Vec<AstExpression>,
     },
+    /// `@primitive def ...` (only allowed in the prelude).
+    /// The body is implemented in Rust
+    PrimitiveMethodDefinition {
+        sig: AstMethodSignature,
+        is_class_method: bool,
+    },
     ConstDefinition {
         name: ConstFirstname,
         expr: AstExpression,
-    }
+    },
+    /// `extern def c_name(...)` (a C function)
+    ExternDefinition {
+        sig: AstMethodSignature,
+    },
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct AstMethodSignature {
     pub name: MethodFirstname,
+    /// Type parameters (`def foo<T>`)
+    pub typarams: Vec<AstTyParam>,
     pub params: Vec<Param>,
     pub ret_typ: Typ,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct InitializerSig {
     pub params: Vec<IParam>,
     pub ret_typ: Typ,
 }
 
-#[derive(Debug, PartialEq)]
+/// `T` or `T : Bound`
+#[derive(Debug, PartialEq, Clone)]
+pub struct AstTyParam {
+    pub name: String,
+    pub bound: Option<Typ>,
+}
+
+#[derive(Debug, Clone)]
 pub struct Param {
     pub name: String,
     pub typ: Typ,
+    /// `y: Int = 10`
+    pub default_expr: Option<AstExpression>,
+    /// Location of the name
+    pub locs: Option<LocationSpan>,
 }
 
-#[derive(Debug, PartialEq)]
+// Manually implemented because `locs` should not be compared
+impl PartialEq for Param {
+    fn eq(&self, other: &Param) -> bool {
+        self.name == other.name && self.typ == other.typ && self.default_expr == other.default_expr
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
 pub struct IParam {
     pub name: String,
     pub typ: Typ,
 }
 
-#[derive(Debug, PartialEq)]
-pub struct Typ {
-    pub name: String,
+#[derive(Debug, PartialEq, Clone)]
+pub enum Typ {
+    /// eg. `Int`, `T`
+    Name(String),
+    /// eg. `Int?`
+    Opt(Box<Typ>),
+    /// eg. `Int | String`
+    Union(Vec<Typ>),
+    /// eg. `A & B` (binds tighter than `|`)
+    Intersection(Vec<Typ>),
 }
 
-#[derive(Debug, PartialEq, Clone)]
+impl std::fmt::Display for Typ {
+    /// Format as written in the source
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Typ::Name(name) => write!(f, "{}", name),
+            Typ::Opt(typ) => write!(f, "{}?", typ),
+            Typ::Union(members) => {
+                write!(f, "{}", members.iter().map(|t| t.to_string()).collect::<Vec<_>>().join(" | "))
+            },
+            Typ::Intersection(members) => {
+                write!(f, "{}", members.iter().map(|t| t.to_string()).collect::<Vec<_>>().join(" & "))
+            },
+        }
+    }
+}
+
+impl Typ {
+    /// Return true if this is `Void`
+    pub fn is_void(&self) -> bool {
+        *self == typ("Void")
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct AstExpression {
     pub body: AstExpressionBody,
     pub primary: bool,
+    /// Range in the source (None if not created by the parser)
+    pub locs: Option<LocationSpan>,
+}
+
+// Manually implemented because `locs` should not be compared
+impl PartialEq for AstExpression {
+    fn eq(&self, other: &AstExpression) -> bool {
+        self.body == other.body && self.primary == other.primary
+    }
+}
+
+/// Range of a node in the source (`end` is exclusive)
+#[derive(Debug, PartialEq, Clone)]
+pub struct LocationSpan {
+    /// Path of the source file (None if not parsed from a file)
+    pub file: Option<Rc<str>>,
+    pub begin: Cursor,
+    pub end: Cursor,
+}
+
+impl LocationSpan {
+    /// Return true if the position (0-origin) is in this range
+    pub fn contains(&self, line: usize, col: usize) -> bool {
+        (self.begin.line(), self.begin.col()) <= (line, col) &&
+            (line, col) < (self.end.line(), self.end.col())
+    }
+
+    /// Return the beginning as "file:line:col" (1-origin) for messages
+    pub fn begin_str(&self) -> String {
+        let file = self.file.as_ref().map(|f| f.to_string()).unwrap_or_else(|| "-".to_string());
+        format!("{}:{}:{}", file, self.begin.line() + 1, self.begin.col() + 1)
+    }
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -94,6 +204,8 @@ pub enum AstExpressionBody {
         rhs: Box<AstExpression>,
         /// Whether declared with `var`
         is_var: bool,
+        /// Type annotation (`var x: T = ...` or `let x: T = ...`)
+        typ: Option<Typ>,
     },
     ConstAssign {
         names: Vec<String>,
@@ -105,6 +217,11 @@ pub enum AstExpressionBody {
         arg_exprs: Vec<AstExpression>,
         may_have_paren_wo_args: bool,
     },
+    /// `name: expr` in the arguments of a method call
+    KeywordArg {
+        name: String,
+        expr: Box<AstExpression>,
+    },
     // Local variable reference or method call with implicit receiver(self)
     BareName(String),
     ConstRef(Vec<String>),
@@ -114,7 +231,20 @@ pub enum AstExpressionBody {
     },
     DecimalLiteral {
         value: i32,
-    }
+    },
+    StringLiteral {
+        content: String,
+    },
+    /// `expr is Typ`
+    TypeTest {
+        expr: Box<AstExpression>,
+        typ: Typ,
+    },
+    /// `expr as Typ`
+    Cast {
+        expr: Box<AstExpression>,
+        typ: Typ,
+    },
 }
 
 impl AstExpression {
@@ -139,6 +269,14 @@ impl AstExpression {
     }
 }
 
+pub fn typ(name: &str) -> Typ {
+    Typ::Name(name.to_string())
+}
+
+pub fn opt_typ(typ: Typ) -> Typ {
+    Typ::Opt(Box::new(typ))
+}
+
 pub fn logical_not(expr: AstExpression) -> AstExpression {
     non_primary_expression(
         AstExpressionBody::LogicalNot {
@@ -147,6 +285,33 @@ pub fn logical_not(expr: AstExpression) -> AstExpression {
     )
 }
 
+pub fn type_test(expr: AstExpression, typ: Typ) -> AstExpression {
+    non_primary_expression(
+        AstExpressionBody::TypeTest {
+            expr: Box::new(expr),
+            typ,
+        }
+    )
+}
+
+pub fn cast(expr: AstExpression, typ: Typ) -> AstExpression {
+    non_primary_expression(
+        AstExpressionBody::Cast {
+            expr: Box::new(expr),
+            typ,
+        }
+    )
+}
+
+pub fn keyword_arg(name: &str, expr: AstExpression) -> AstExpression {
+    non_primary_expression(
+        AstExpressionBody::KeywordArg {
+            name: name.to_string(),
+            expr: Box::new(expr),
+        }
+    )
+}
+
 pub fn logical_and(left: AstExpression, right: AstExpression) -> AstExpression {
     non_primary_expression(
         AstExpressionBody::LogicalAnd {
@@ -188,7 +353,7 @@ pub fn while_expr(cond_expr: AstExpression, body_exprs: Vec<AstExpression>) -> A
 pub fn assignment(lhs: AstExpression, rhs: AstExpression) -> AstExpression {
     let body = match lhs.body {
         AstExpressionBody::BareName(s) =>  {
-            AstExpressionBody::LVarAssign { name: s.to_string(), rhs: Box::new(rhs), is_var: false } 
+            AstExpressionBody::LVarAssign { name: s.to_string(), rhs: Box::new(rhs), is_var: false, typ: None }
         },
         // ToDo: IVarRef =>
         // ToDo: CVarRef =>
@@ -208,11 +373,23 @@ pub fn assignment(lhs: AstExpression, rhs: AstExpression) -> AstExpression {
     non_primary_expression(body)
 }
 
-pub fn var_decl(name: String, rhs: AstExpression) -> AstExpression {
+pub fn var_decl(name: String, typ: Option<Typ>, rhs: AstExpression) -> AstExpression {
     non_primary_expression(AstExpressionBody::LVarAssign {
         name: name,
         rhs: Box::new(rhs),
         is_var: true,
+        typ,
+    })
+}
+
+/// Create an expression for `let x = ...` (which is the same as `x = ...`
+/// unless annotated)
+pub fn let_decl(name: String, typ: Option<Typ>, rhs: AstExpression) -> AstExpression {
+    non_primary_expression(AstExpressionBody::LVarAssign {
+        name: name,
+        rhs: Box::new(rhs),
+        is_var: false,
+        typ,
     })
 }
 
@@ -228,7 +405,8 @@ pub fn method_call(receiver_expr: Option<AstExpression>,
             method_name: MethodFirstname(method_name.to_string()),
             arg_exprs,
             may_have_paren_wo_args,
-        }
+        },
+        locs: None,
     }
 }
 
@@ -270,12 +448,16 @@ pub fn decimal_literal(value: i32) -> AstExpression {
     primary_expression(AstExpressionBody::DecimalLiteral{ value })
 }
 
+pub fn string_literal(content: String) -> AstExpression {
+    primary_expression(AstExpressionBody::StringLiteral{ content })
+}
+
 pub fn primary_expression(body: AstExpressionBody) -> AstExpression {
-    AstExpression { primary: true, body: body }
+    AstExpression { primary: true, body: body, locs: None }
 }
 
 pub fn non_primary_expression(body: AstExpressionBody) -> AstExpression {
-    AstExpression { primary: false, body: body }
+    AstExpression { primary: false, body: body, locs: None }
 }
 
 /// Extend `foo.bar` to `foo.bar args`
@@ -294,7 +476,8 @@ pub fn set_method_call_args(expr: AstExpression, args: Vec<AstExpression>) -> As
                     method_name,
                     arg_exprs: args,
                     may_have_paren_wo_args: false,
-                }
+                },
+                locs: None,
             }
         },
         AstExpressionBody::BareName(s) => {
@@ -305,7 +488,8 @@ pub fn set_method_call_args(expr: AstExpression, args: Vec<AstExpression>) -> As
                     method_name: MethodFirstname(s.to_string()),
                     arg_exprs: args,
                     may_have_paren_wo_args: false,
-                }
+                },
+                locs: None,
             }
         },
         b => panic!("[BUG] `extend' takes a MethodCall but got {:?}", b)
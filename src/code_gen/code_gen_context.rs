@@ -3,14 +3,17 @@ use std::collections::HashMap;
 #[derive(Debug)]
 pub struct CodeGenContext {
     pub function: inkwell::values::FunctionValue,
+    /// Whether `function` is the main program (where `self` is the Main object)
+    pub is_toplevel: bool,
     /// Ptr of local variables
     pub lvars: HashMap<String, inkwell::values::PointerValue>
 }
 
 impl CodeGenContext {
-    pub fn new(function: inkwell::values::FunctionValue) -> CodeGenContext {
+    pub fn new(function: inkwell::values::FunctionValue, is_toplevel: bool) -> CodeGenContext {
         CodeGenContext {
             function: function,
+            is_toplevel: is_toplevel,
             lvars: HashMap::new(),
         }
     }
@@ -0,0 +1,293 @@
+/// DWARF debug information (`compile -g`)
+///
+/// The llvm7-0 branch of inkwell has no wrapper of DIBuilder, so this
+/// module calls the C API of llvm-sys directly.
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::os::raw::c_char;
+use std::path::Path;
+use inkwell::basic_block::BasicBlock;
+use inkwell::module::Module;
+use inkwell::values::{AsValueRef, FunctionValue, InstructionValue, PointerValue};
+use llvm_sys::core::*;
+use llvm_sys::debuginfo::*;
+use llvm_sys::prelude::*;
+use crate::ast::LocationSpan;
+use crate::ty::*;
+
+const DW_ATE_BOOLEAN: LLVMDWARFTypeEncoding = 0x02;
+const DW_ATE_FLOAT: LLVMDWARFTypeEncoding = 0x04;
+const DW_ATE_SIGNED: LLVMDWARFTypeEncoding = 0x05;
+
+pub struct DebugInfo {
+    builder: LLVMDIBuilderRef,
+    context: LLVMContextRef,
+    compile_unit: LLVMMetadataRef,
+    /// Path of the main source file
+    main_file: String,
+    /// Kind id of `!dbg`
+    dbg_kind: u32,
+    files: RefCell<HashMap<String, LLVMMetadataRef>>,
+    types: RefCell<HashMap<String, LLVMMetadataRef>>,
+}
+
+/// Position of the builder before generating an expression. The
+/// instructions after it are the ones of the expression
+pub struct Mark {
+    block: BasicBlock,
+    last: Option<InstructionValue>,
+}
+
+impl Mark {
+    pub fn new(block: BasicBlock) -> Mark {
+        let last = block.get_last_instruction();
+        Mark { block, last }
+    }
+}
+
+impl DebugInfo {
+    /// Create a compile unit for `main_file`. `module` must have at least
+    /// one function (used to get the raw pointer of the module)
+    pub fn new(module: &Module, main_file: &str) -> DebugInfo {
+        let function = module.get_first_function()
+            .expect("[BUG] DebugInfo::new called for an empty module");
+        unsafe {
+            let module_ref = LLVMGetGlobalParent(function.as_value_ref());
+            let context = LLVMGetModuleContext(module_ref);
+            let builder = LLVMCreateDIBuilder(module_ref);
+            add_module_flag(module_ref, context, "Debug Info Version", LLVMDebugMetadataVersion());
+
+            let (name, dir) = split_path(main_file);
+            let file = LLVMDIBuilderCreateFile(builder,
+                                               cstr(&name), name.len(),
+                                               cstr(&dir), dir.len());
+            let producer = "shiika";
+            let compile_unit = LLVMDIBuilderCreateCompileUnit(
+                builder,
+                // Shiika has no DWARF language code
+                LLVMDWARFSourceLanguage::LLVMDWARFSourceLanguageC,
+                file,
+                cstr(producer), producer.len(),
+                0,                     // isOptimized
+                cstr(""), 0,           // Flags
+                0,                     // RuntimeVer
+                cstr(""), 0,           // SplitName
+                LLVMDWARFEmissionKind::LLVMDWARFEmissionKindFull,
+                0,                     // DWOId
+                0,                     // SplitDebugInlining
+                0);                    // DebugInfoForProfiling
+            let dbg_kind = LLVMGetMDKindIDInContext(context, cstr("dbg"), 3);
+
+            let mut files = HashMap::new();
+            files.insert(main_file.to_string(), file);
+            DebugInfo {
+                builder,
+                context,
+                compile_unit,
+                main_file: main_file.to_string(),
+                dbg_kind,
+                files: RefCell::new(files),
+                types: RefCell::new(HashMap::new()),
+            }
+        }
+    }
+
+    /// Create the DISubprogram of `function`. `locs` is where the
+    /// function begins (None if it is unknown)
+    pub fn add_subprogram(&self, function: &FunctionValue, name: &str, locs: Option<&LocationSpan>) {
+        let file = self.file_of(locs);
+        let line = line_of(locs);
+        let linkage_name = function.get_name().to_str().unwrap().to_string();
+        unsafe {
+            let fn_type = LLVMDIBuilderCreateSubroutineType(self.builder, file, std::ptr::null_mut(), 0, LLVMDIFlagZero);
+            let subprogram = LLVMDIBuilderCreateFunction(self.builder,
+                                                         self.compile_unit,
+                                                         cstr(name), name.len(),
+                                                         cstr(&linkage_name), linkage_name.len(),
+                                                         file,
+                                                         line,
+                                                         fn_type,
+                                                         0,    // IsLocalToUnit
+                                                         1,    // IsDefinition
+                                                         line, // ScopeLine
+                                                         LLVMDIFlagZero,
+                                                         0);   // IsOptimized
+            LLVMSetSubprogram(function.as_value_ref(), subprogram);
+        }
+    }
+
+    /// Set the location of the instructions generated after `mark` which
+    /// do not have one yet (the ones of the subexpressions are already set)
+    pub fn set_location(&self, function: &FunctionValue, mark: Mark, locs: &Option<LocationSpan>) {
+        if let Some(locs) = locs {
+            let loc = self.location(function, Some(locs));
+            let first = match &mark.last {
+                Some(inst) => inst.get_next_instruction(),
+                None => mark.block.get_first_instruction(),
+            };
+            self.attach_from(mark.block, first, loc);
+        }
+    }
+
+    /// Set the location of all the instructions of `function` which do not
+    /// have one to `locs`. Needed because the verifier rejects calls
+    /// without a location in a function with debug info
+    pub fn finish_function(&self, function: &FunctionValue, locs: Option<&LocationSpan>) {
+        if let Some(block) = function.get_first_basic_block() {
+            let loc = self.location(function, locs);
+            let first = block.get_first_instruction();
+            self.attach_from(block, first, loc);
+        }
+    }
+
+    /// Describe the local variable stored in `ptr`
+    pub fn declare_lvar(&self,
+                        function: &FunctionValue,
+                        name: &str,
+                        ty: &TermTy,
+                        ptr: PointerValue,
+                        locs: &Option<LocationSpan>) {
+        let file = self.file_of(locs.as_ref());
+        let scope = self.scope(function, file);
+        let di_type = self.di_type(ty);
+        let loc = self.location(function, locs.as_ref());
+        unsafe {
+            let var = LLVMDIBuilderCreateAutoVariable(self.builder,
+                                                      scope,
+                                                      cstr(name), name.len(),
+                                                      file,
+                                                      line_of(locs.as_ref()),
+                                                      di_type,
+                                                      1, // AlwaysPreserve
+                                                      LLVMDIFlagZero,
+                                                      0); // AlignInBits
+            let expr = LLVMDIBuilderCreateExpression(self.builder, std::ptr::null_mut(), 0);
+            let ptr_ref = ptr.as_value_ref();
+            LLVMDIBuilderInsertDeclareAtEnd(self.builder, ptr_ref, var, expr, loc, LLVMGetInstructionParent(ptr_ref));
+        }
+    }
+
+    /// Resolve the temporary nodes. Must be called before emitting the module
+    pub fn finalize(self) {
+        unsafe { LLVMDIBuilderFinalize(self.builder) }
+    }
+
+    /// Set `loc` to the instructions from `first` to the end of the function
+    fn attach_from(&self, block: BasicBlock, first: Option<InstructionValue>, loc: LLVMMetadataRef) {
+        let mut block = block;
+        let mut inst = first;
+        loop {
+            while let Some(i) = inst {
+                unsafe {
+                    let value = i.as_value_ref();
+                    if LLVMGetMetadata(value, self.dbg_kind).is_null() {
+                        LLVMSetMetadata(value, self.dbg_kind, LLVMMetadataAsValue(self.context, loc));
+                    }
+                }
+                inst = i.get_next_instruction();
+            }
+            match block.get_next_basic_block() {
+                Some(b) => {
+                    inst = b.get_first_instruction();
+                    block = b;
+                },
+                None => break,
+            }
+        }
+    }
+
+    /// Create a DILocation in `function`
+    fn location(&self, function: &FunctionValue, locs: Option<&LocationSpan>) -> LLVMMetadataRef {
+        let scope = self.scope(function, self.file_of(locs));
+        let col = locs.map(|l| l.begin.col() as u32 + 1).unwrap_or(0);
+        unsafe {
+            LLVMDIBuilderCreateDebugLocation(self.context, line_of(locs), col, scope, std::ptr::null_mut())
+        }
+    }
+
+    /// Return the scope for the code of `file` in `function`. A lexical
+    /// block file is used because the toplevel of a required file is
+    /// compiled into `main`
+    fn scope(&self, function: &FunctionValue, file: LLVMMetadataRef) -> LLVMMetadataRef {
+        unsafe {
+            let subprogram = LLVMGetSubprogram(function.as_value_ref());
+            LLVMDIBuilderCreateLexicalBlockFile(self.builder, subprogram, file, 0)
+        }
+    }
+
+    fn file_of(&self, locs: Option<&LocationSpan>) -> LLVMMetadataRef {
+        let path = match locs.and_then(|l| l.file.as_ref()) {
+            Some(file) => file.to_string(),
+            None => self.main_file.clone(),
+        };
+        let mut files = self.files.borrow_mut();
+        *files.entry(path.clone()).or_insert_with(|| {
+            let (name, dir) = split_path(&path);
+            unsafe {
+                LLVMDIBuilderCreateFile(self.builder, cstr(&name), name.len(), cstr(&dir), dir.len())
+            }
+        })
+    }
+
+    fn di_type(&self, ty: &TermTy) -> LLVMMetadataRef {
+        let name = &ty.fullname.0;
+        let mut types = self.types.borrow_mut();
+        *types.entry(name.clone()).or_insert_with(|| unsafe {
+            let basic = match (&ty.body, name.as_str()) {
+                (TyBody::TyRaw, "Bool") => Some((1, DW_ATE_BOOLEAN)),
+                (TyBody::TyRaw, "Int") => Some((32, DW_ATE_SIGNED)),
+                (TyBody::TyRaw, "Float") => Some((64, DW_ATE_FLOAT)),
+                _ => None,
+            };
+            match basic {
+                Some((size, encoding)) => {
+                    LLVMDIBuilderCreateBasicType(self.builder, cstr(name), name.len(), size, encoding)
+                },
+                // Shiika objects have no fields yet
+                None => {
+                    LLVMDIBuilderCreatePointerType(self.builder, std::ptr::null_mut(), 64, 0, 0,
+                                                   cstr(name), name.len())
+                }
+            }
+        })
+    }
+}
+
+impl Drop for DebugInfo {
+    fn drop(&mut self) {
+        unsafe { LLVMDisposeDIBuilder(self.builder) }
+    }
+}
+
+/// Add `!{i32 2, !"name", i32 value}` to `llvm.module.flags`
+/// (2 means "warning on mismatch")
+unsafe fn add_module_flag(module: LLVMModuleRef, context: LLVMContextRef, name: &str, value: u32) {
+    let i32_type = LLVMInt32TypeInContext(context);
+    let mut operands = [
+        LLVMConstInt(i32_type, 2, 0),
+        LLVMMDStringInContext(context, cstr(name), name.len() as u32),
+        LLVMConstInt(i32_type, value as u64, 0),
+    ];
+    let node = LLVMMDNodeInContext(context, operands.as_mut_ptr(), operands.len() as u32);
+    LLVMAddNamedMetadataOperand(module, cstr("llvm.module.flags\0"), node);
+}
+
+/// Line number (1-origin; 0 means unknown)
+fn line_of(locs: Option<&LocationSpan>) -> u32 {
+    locs.map(|l| l.begin.line() as u32 + 1).unwrap_or(0)
+}
+
+/// Split `path` into the file name and the directory
+fn split_path(path: &str) -> (String, String) {
+    let path = Path::new(path);
+    let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    let name = path.file_name().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+    let dir = path.parent().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+    (name, dir)
+}
+
+/// The C API takes the length with the pointer, so the string need not be
+/// null-terminated (except for the name of `LLVMAddNamedMetadataOperand`)
+fn cstr(s: &str) -> *const c_char {
+    s.as_ptr() as *const c_char
+}
@@ -0,0 +1,153 @@
+/// Values of interface types
+///
+/// A value of an interface has the same representation as the one of a
+/// union (`{i32 tag, i8* payload}`) where the tag tells the class of the
+/// payload. Calling a method of an interface calls the function of the
+/// same name (eg. `Drawable#draw`), which dispatches on the tag to the
+/// method of each class implementing the interface. Values of intersection
+/// types (eg. `A & Drawable`) are tagged in the same way.
+use std::collections::HashMap;
+use inkwell::values::*;
+use crate::code_gen::CodeGen;
+use crate::code_gen::code_gen_context::CodeGenContext;
+use crate::code_gen::runtime;
+use crate::error::Error;
+use crate::hir::*;
+use crate::names::*;
+use crate::ty;
+use crate::ty::*;
+
+impl CodeGen {
+    /// Return true if `ty` is an interface type
+    pub(super) fn is_interface(&self, ty: &TermTy) -> bool {
+        ty.body == TyBody::TyRaw && self.sk_interfaces.contains_key(&ty.fullname)
+    }
+
+    /// Declare the dispatcher function for each method of the interfaces
+    pub(super) fn gen_interface_funcs(&self) {
+        for interface in self.sk_interfaces.values() {
+            let self_ty = ty::raw(&interface.fullname.0);
+            for sig in &interface.method_sigs {
+                let func_type = self.llvm_func_type(&self_ty, sig);
+                self.module.add_function(&sig.fullname.full_name, func_type, None);
+            }
+        }
+    }
+
+    /// Define the dispatcher functions
+    pub(super) fn gen_interface_dispatchers(&self, classes: &HashMap<ClassFullname, SkClass>) {
+        let mut class_names = classes.keys().collect::<Vec<_>>();
+        class_names.sort_by(|a, b| a.0.cmp(&b.0));
+        for interface in self.sk_interfaces.values() {
+            let implementors = class_names.iter()
+                .filter(|name| class_implements(classes, name, &interface.fullname))
+                .collect::<Vec<_>>();
+            for sig in &interface.method_sigs {
+                self.gen_dispatcher(classes, &implementors, sig);
+            }
+        }
+    }
+
+    /// Define a function which calls the method `sig` of the class of the
+    /// receiver
+    fn gen_dispatcher(&self,
+                      classes: &HashMap<ClassFullname, SkClass>,
+                      implementors: &[&&ClassFullname],
+                      sig: &MethodSignature) {
+        let function = self.module.get_function(&sig.fullname.full_name)
+            .expect("[BUG] dispatcher not declared");
+        let entry_block = self.context.append_basic_block(&function, "");
+        let else_block = function.append_basic_block("NoMethod");
+        self.builder.position_at_end(&entry_block);
+        let receiver = function.get_first_param().unwrap().into_struct_value();
+        let tag = self.builder.build_extract_value(receiver, 0, "tag").unwrap().into_int_value();
+        let payload = self.builder.build_extract_value(receiver, 1, "payload").unwrap();
+
+        let mut cases = vec![];
+        for class_name in implementors {
+            let method_fullname = find_method_fullname(classes, class_name, sig.first_name())
+                .expect("[BUG] conformance not checked");
+            let method = self.module.get_function(&method_fullname.full_name)
+                .expect("[BUG] method function not declared");
+            let block = function.append_basic_block(&format!("Call_{}", class_name.0));
+            self.builder.position_at_end(&block);
+            // The method may be defined in the superclass
+            let self_type = method.get_type().get_param_types()[0];
+            let mut args = vec![self.builder.build_bitcast(payload, self_type, "self")];
+            args.extend(function.get_param_iter().skip(1));
+            let result = self.builder.build_call(method, &args, "result").try_as_basic_value().left();
+            match result {
+                Some(value) if !sig.ret_ty.is_void_type() => self.builder.build_return(Some(&value)),
+                _ => self.builder.build_return(None),
+            };
+            let tag_value = self.i32_type.const_int(self.type_tag(&ty::raw(&class_name.0)), false);
+            cases.push((tag_value, block));
+        }
+
+        self.builder.position_at_end(&entry_block);
+        let case_refs = cases.iter().map(|(tag, block)| (*tag, block)).collect::<Vec<_>>();
+        self.builder.build_switch(tag, &else_block, &case_refs);
+        self.builder.position_at_end(&else_block);
+        self.gen_panic(&format!("no method for {}", sig.fullname));
+    }
+
+    /// Convert an object to `interface_ty`
+    pub(super) fn gen_interface_wrap(&self,
+                                     ctx: &mut CodeGenContext,
+                                     interface_ty: &TermTy,
+                                     expr: &HirExpression) -> Result<BasicValueEnum, Error> {
+        let value = self.gen_expr(ctx, expr)?;
+        debug_assert!(self.is_interface(interface_ty) || is_intersection(interface_ty));
+        // Already tagged with its class
+        if self.is_interface(&expr.ty) || is_intersection(&expr.ty) {
+            return Ok(value)
+        }
+        let payload = self.builder.build_bitcast(value, runtime::str_type(), "payload");
+        Ok(self.gen_tagged_value(&expr.ty, payload).as_basic_value_enum())
+    }
+
+    /// Take out the value of `member_ty` from a value of an intersection type
+    pub(super) fn gen_intersection_cast(&self,
+                                        ctx: &mut CodeGenContext,
+                                        member_ty: &TermTy,
+                                        expr: &HirExpression) -> Result<BasicValueEnum, Error> {
+        let value = self.gen_expr(ctx, expr)?;
+        if self.is_interface(member_ty) || is_intersection(member_ty) {
+            Ok(value)
+        }
+        else {
+            Ok(self.gen_untag(value.into_struct_value(), member_ty))
+        }
+    }
+
+    /// Make `{tag of ty, payload}`
+    pub(super) fn gen_tagged_value(&self, ty: &TermTy, payload: BasicValueEnum) -> StructValue {
+        self.gen_tagged(self.i32_type.const_int(self.type_tag(ty), false), payload)
+    }
+
+    /// Make `{tag, payload}`
+    pub(super) fn gen_tagged(&self, tag: IntValue, payload: BasicValueEnum) -> StructValue {
+        let value = self.builder.build_insert_value(self.union_type().get_undef(), tag, 0, "tagged").unwrap();
+        let value = self.builder.build_insert_value(value, payload, 1, "tagged").unwrap();
+        value.into_struct_value()
+    }
+}
+
+fn is_intersection(ty: &TermTy) -> bool {
+    match ty.body {
+        TyBody::TyIntersection { .. } => true,
+        _ => false,
+    }
+}
+
+/// Find the method `name` from the class and its ancestors
+fn find_method_fullname<'a>(classes: &'a HashMap<ClassFullname, SkClass>,
+                            class_fullname: &ClassFullname,
+                            name: &MethodFirstname) -> Option<&'a MethodFullname> {
+    let class = classes.get(class_fullname)?;
+    match class.method_sigs.get(name) {
+        Some(sig) => Some(&sig.fullname),
+        None => class.superclass_fullname.as_ref()
+            .and_then(|sup| find_method_fullname(classes, sup, name)),
+    }
+}
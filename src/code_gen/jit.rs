@@ -0,0 +1,197 @@
+/// In-process execution of the generated module
+use std::cell::RefCell;
+use std::ffi::CStr;
+use std::os::raw::c_char;
+use std::path::Path;
+use inkwell::execution_engine::{ExecutionEngine, JitFunction, UnsafeFunctionPointer};
+use inkwell::targets::{InitializationConfig, Target};
+use crate::code_gen::CodeGen;
+use crate::code_gen::runtime::FRAMES_MAX;
+use crate::error;
+use crate::error::Error;
+use crate::linker::GcConfig;
+use crate::ty::*;
+
+/// Candidates of Boehm GC shared library
+const GC_LIBRARY_NAMES: [&str; 4] = ["libgc.so.1", "libgc.so", "libgc.1.dylib", "libgc.dylib"];
+
+impl CodeGen {
+    /// Execute `main` of the generated module with the JIT compiler.
+    /// Returns the exit status of `main`
+    ///
+    /// Note: the module cannot be used for other execution engines after this
+    pub fn run_jit(&self) -> Result<i32, Error> {
+        let engine = self.create_jit_engine()?;
+        unsafe {
+            Ok(get_main::<unsafe extern "C" fn() -> i32>(&engine)?.call())
+        }
+    }
+
+    /// Execute `main` generated by `gen_repl_program` and return the
+    /// string representation of its value (empty if `ty` is Void)
+    pub fn run_jit_repl(&self, ty: &TermTy) -> Result<String, Error> {
+        let engine = self.create_jit_engine()?;
+        PANICKED.with(|p| *p.borrow_mut() = None);
+        let s = unsafe {
+            match (&ty.body, ty.fullname.0.as_str()) {
+                (TyBody::TyRaw, "Void") => {
+                    get_main::<unsafe extern "C" fn() -> i32>(&engine)?.call();
+                    "".to_string()
+                },
+                (TyBody::TyRaw, "Int") => {
+                    get_main::<unsafe extern "C" fn() -> i32>(&engine)?.call().to_string()
+                },
+                (TyBody::TyRaw, "Float") => {
+                    get_main::<unsafe extern "C" fn() -> f64>(&engine)?.call().to_string()
+                },
+                (TyBody::TyRaw, "Bool") => {
+                    // `main` zero-extends the value to i32
+                    (get_main::<unsafe extern "C" fn() -> i32>(&engine)?.call() != 0).to_string()
+                },
+                (TyBody::TyRaw, "String") => {
+                    let ptr = get_main::<unsafe extern "C" fn() -> *const c_char>(&engine)?.call();
+                    // `ptr` is undef if it panicked
+                    if PANICKED.with(|p| p.borrow().is_some()) {
+                        "".to_string()
+                    }
+                    else {
+                        format!("{:?}", CStr::from_ptr(ptr).to_string_lossy())
+                    }
+                },
+                _ => {
+                    let ptr = get_main::<unsafe extern "C" fn() -> *const u8>(&engine)?.call();
+                    // Optional values are null when nil
+                    if ptr.is_null() && ty.is_nilable() {
+                        "nil".to_string()
+                    }
+                    else {
+                        format!("#<{}>", ty.fullname)
+                    }
+                }
+            }
+        };
+        if let Some((msg, trace)) = PANICKED.with(|p| p.borrow_mut().take()) {
+            return Err(error::runtime_panic(&msg, trace))
+        }
+        Ok(s)
+    }
+
+    fn create_jit_engine(&self) -> Result<ExecutionEngine, Error> {
+        Target::initialize_native(&InitializationConfig::default())
+            .map_err(|msg| error::bug(&format!("failed to initialize native target: {}", msg)))?;
+        let engine = self.module.create_jit_execution_engine(self.opt_level)
+            .map_err(|msg| error::bug(&format!("failed to create execution engine: {}", msg)))?;
+        if !load_gc_library() {
+            self.map_fallback_gc(&engine);
+        }
+        if let Some(func) = self.module.get_function("shiika_repl_panic") {
+            engine.add_global_mapping(&func, repl_panic as usize);
+        }
+        Ok(engine)
+    }
+
+    /// Resolve `GC_init` and `GC_malloc` to the functions of this process,
+    /// which allocate memory without collecting it
+    fn map_fallback_gc(&self, engine: &ExecutionEngine) {
+        if let Some(func) = self.module.get_function("GC_init") {
+            engine.add_global_mapping(&func, fallback_gc_init as usize);
+        }
+        if let Some(func) = self.module.get_function("GC_malloc") {
+            engine.add_global_mapping(&func, fallback_gc_malloc as usize);
+        }
+    }
+}
+
+unsafe fn get_main<F: UnsafeFunctionPointer>(engine: &ExecutionEngine) -> Result<JitFunction<F>, Error> {
+    engine.get_function::<F>("main")
+        .map_err(|e| error::bug(&format!("failed to get main: {:?}", e)))
+}
+
+/// Load libgc so that the JIT can resolve `GC_*` symbols.
+/// `SHIIKA_GC_LIB` takes precedence over the directories found by
+/// `GcConfig` and the default search path.
+/// Returns false if none of them could be loaded
+fn load_gc_library() -> bool {
+    if let Ok(path) = std::env::var("SHIIKA_GC_LIB") {
+        if load_library(&path) {
+            return true
+        }
+    }
+    let lib_dirs = GcConfig::discover().lib_dirs();
+    let found = lib_dirs.iter().any(|dir| {
+        GC_LIBRARY_NAMES.iter().any(|name|
+            load_library(&Path::new(dir).join(name).to_string_lossy())
+        )
+    });
+    found || GC_LIBRARY_NAMES.iter().any(|name| load_library(name))
+}
+
+/// Load a shared library so that the JIT can resolve the symbols of
+/// `extern def`. `name` is a path or a library name like `-l` of the linker
+/// (eg. "m" for libm)
+pub fn load_native_library(name: &str) -> Result<(), Error> {
+    let candidates = [
+        name.to_string(),
+        format!("lib{}.so", name),
+        format!("lib{}.dylib", name),
+    ];
+    if candidates.iter().any(|s| load_library(s)) {
+        Ok(())
+    }
+    else {
+        Err(error::program_error(&format!("cannot load library {}", name)))
+    }
+}
+
+/// Tell the GC that `len` bytes from `ptr` may hold pointers to Shiika
+/// objects. Does nothing if libgc is not loaded (the fallback allocator
+/// never frees memory)
+pub fn add_gc_roots(ptr: *const u8, len: usize) {
+    let name = std::ffi::CString::new("GC_add_roots").expect("[BUG] invalid symbol name");
+    let func = unsafe { llvm_sys::support::LLVMSearchForAddressOfSymbol(name.as_ptr()) };
+    if !func.is_null() {
+        let add_roots: extern "C" fn(*const u8, *const u8) = unsafe { std::mem::transmute(func) };
+        add_roots(ptr, unsafe { ptr.add(len) });
+    }
+}
+
+/// Load a shared library into this process. Returns false on failure
+fn load_library(name: &str) -> bool {
+    let c_name = match std::ffi::CString::new(name) {
+        Ok(s) => s,
+        Err(_) => return false,
+    };
+    // Note: LLVMLoadLibraryPermanently returns true on error
+    unsafe { llvm_sys::support::LLVMLoadLibraryPermanently(c_name.as_ptr()) == 0 }
+}
+
+thread_local! {
+    /// The message and the backtrace of the last panic of the REPL
+    static PANICKED: RefCell<Option<(String, Vec<String>)>> = RefCell::new(None);
+}
+
+/// Called by `shiika_panic` of the REPL before it `longjmp`s back to `main`.
+/// `names` and `locs` are the frame arrays of the runtime
+extern "C" fn repl_panic(msg: *const c_char, names: *const *const c_char, locs: *const *const c_char, depth: i32) {
+    let to_string = |ptr: *const c_char| unsafe { CStr::from_ptr(ptr).to_string_lossy().into_owned() };
+    let n_frames = std::cmp::min(depth as u32, FRAMES_MAX) as usize;
+    let mut trace = vec![];
+    if depth as u32 > FRAMES_MAX {
+        trace.push(format!("... {} more frames (not recorded)", depth as u32 - FRAMES_MAX));
+    }
+    for i in (0..n_frames).rev() {
+        let (name, loc) = unsafe { (*names.add(i), *locs.add(i)) };
+        let loc = if loc.is_null() { "?".to_string() } else { to_string(loc) };
+        trace.push(format!("{} ({})", to_string(name), loc));
+    }
+    PANICKED.with(|p| *p.borrow_mut() = Some((to_string(msg), trace)));
+}
+
+extern "C" fn fallback_gc_init() {}
+
+extern "C" fn fallback_gc_malloc(size: u64) -> *mut u8 {
+    // Zero-sized allocation is not allowed for `alloc_zeroed`
+    let layout = std::alloc::Layout::from_size_align(std::cmp::max(size as usize, 1), 16)
+        .expect("[BUG] invalid layout");
+    unsafe { std::alloc::alloc_zeroed(layout) }
+}
@@ -1,13 +1,26 @@
 mod code_gen_context;
+mod debug_info;
+mod interface;
+mod jit;
+mod runtime;
+pub use self::jit::{add_gc_roots, load_native_library};
+use std::cell::RefCell;
 use std::collections::HashMap;
-use inkwell::AddressSpace;
+use std::path::Path;
+use inkwell::{AddressSpace, OptimizationLevel};
+use inkwell::passes::{PassManager, PassManagerBuilder};
+use inkwell::targets::{CodeModel, FileType, InitializationConfig, RelocMode, Target, TargetMachine};
 use inkwell::values::*;
 use inkwell::types::*;
+use crate::ast::LocationSpan;
+use crate::error;
 use crate::error::Error;
 use crate::ty;
 use crate::ty::*;
+use self::debug_info::DebugInfo;
 use crate::hir::*;
 use crate::hir::HirExpressionBase::*;
+use crate::hir::opt::ConstValue;
 use crate::names::*;
 use crate::code_gen::code_gen_context::*;
 
@@ -23,8 +36,26 @@ pub struct CodeGen {
     llvm_struct_types: HashMap<ClassFullname, inkwell::types::StructType>,
     /// Toplevel `self`
     the_main: Option<inkwell::values::BasicValueEnum>,
+    opt_level: OptimizationLevel,
+    /// Passes run on each method as it is generated (None for -O0)
+    function_passes: Option<PassManager<FunctionValue>>,
+    /// Path of the main source file (Some if debug info is enabled)
+    debug_source: Option<String>,
+    debug_info: Option<DebugInfo>,
+    /// Tags of the members of union types (assigned on first use)
+    type_tags: RefCell<HashMap<ClassFullname, u64>>,
+    sk_interfaces: HashMap<ClassFullname, SkInterface>,
+    /// Whether panics return to the host instead of exiting (REPL)
+    recoverable_panic: bool,
+    /// Address of the values kept across the inputs of the REPL
+    repl_slots: Option<u64>,
+    /// Whether to run LLVM's verifier in `gen_program`
+    verify_module: bool,
 }
 
+/// Size of a REPL slot (enough for any value, eg. `{i32, i8*}` of unions)
+pub const REPL_SLOT_SIZE: usize = 16;
+
 impl CodeGen {
     pub fn new() -> CodeGen {
         let context = inkwell::context::Context::create();
@@ -41,19 +72,152 @@ impl CodeGen {
             void_type: inkwell::types::VoidType::void_type(),
             llvm_struct_types: HashMap::new(),
             the_main: None,
+            opt_level: OptimizationLevel::None,
+            function_passes: None,
+            debug_source: None,
+            debug_info: None,
+            type_tags: RefCell::new(HashMap::new()),
+            sk_interfaces: HashMap::new(),
+            recoverable_panic: false,
+            repl_slots: None,
+            verify_module: false,
+        }
+    }
+
+    /// Set the optimization level of the LLVM passes and the machine code
+    /// generation (the default is `OptimizationLevel::None`). The function
+    /// passes run as each method is generated and the interprocedural
+    /// ones on the finished module
+    pub fn set_opt_level(&mut self, level: OptimizationLevel) {
+        self.opt_level = level;
+        self.function_passes = if level == OptimizationLevel::None {
+            None
         }
+        else {
+            let fpm = PassManager::create(&self.module);
+            pass_manager_builder(level).populate_function_pass_manager(&fpm);
+            fpm.initialize();
+            Some(fpm)
+        };
+    }
+
+    /// Run LLVM's verifier on each function before its passes and on the
+    /// generated module before the interprocedural passes (and again after
+    /// them if optimizing)
+    pub fn set_verify(&mut self, verify: bool) {
+        self.verify_module = verify;
+    }
+
+    /// Emit DWARF debug info. `main_file` is the path of the program
+    pub fn enable_debug_info(&mut self, main_file: &str) {
+        self.debug_source = Some(main_file.to_string());
+    }
+
+    /// Run LLVM's verifier on the module
+    pub fn verify(&self) -> Result<(), Error> {
+        self.module.verify().map_err(|msg|
+            error::bug(&format!("LLVM module verification failed: {}", msg.to_string()))
+        )
     }
 
     pub fn gen_program(&mut self, hir: Hir) -> Result<(), Error> {
+        self.gen_program_(hir, false)
+    }
+
+    /// Like `gen_program` but `main` returns the value of the last
+    /// expression instead of the exit status, and panics do not exit the
+    /// process (used by REPL). `slots` is the address of the memory for
+    /// `HirReplLoad` and `HirReplStore` (`REPL_SLOT_SIZE` bytes per slot)
+    pub fn gen_repl_program(&mut self, hir: Hir, slots: u64) -> Result<(), Error> {
+        self.recoverable_panic = true;
+        self.repl_slots = Some(slots);
+        self.gen_program_(hir, true)
+    }
+
+    fn gen_program_(&mut self, hir: Hir, returns_value: bool) -> Result<(), Error> {
         self.gen_declares();
+        if let Some(main_file) = &self.debug_source {
+            self.debug_info = Some(DebugInfo::new(&self.module, main_file));
+        }
+        self.sk_interfaces = hir.sk_interfaces.clone();
         self.gen_class_structs(&hir.sk_classes);
+        self.gen_extern_declares(&hir.extern_funcs)?;
+        self.gen_runtime();
         self.gen_method_funcs(&hir.sk_methods);
+        self.gen_interface_funcs();
+        self.gen_interface_dispatchers(&hir.sk_classes);
         self.gen_methods(&hir.sk_methods)?;
-        self.gen_constant_ptrs(&hir.constants);
-        self.gen_main(&hir.main_exprs)?;
+        self.gen_constant_ptrs(&hir.constants, &hir.const_values);
+        self.gen_main(&hir.main_exprs, returns_value)?;
+        // Dispose the DIBuilder before the context is dropped
+        if let Some(debug_info) = self.debug_info.take() {
+            debug_info.finalize();
+        }
+        if self.verify_module {
+            self.verify()?;
+        }
+        self.run_module_passes();
+        if self.verify_module && self.opt_level != OptimizationLevel::None {
+            self.verify()?;
+        }
+        Ok(())
+    }
+
+    /// Verify the generated `function` (with `--verify`) and run the
+    /// function passes on it
+    fn run_function_passes(&self, function: &FunctionValue) -> Result<(), Error> {
+        if self.verify_module && !function.verify(true) {
+            return Err(error::bug(&format!("LLVM function verification failed: {}",
+                                           function.get_name().to_string_lossy())))
+        }
+        if let Some(fpm) = &self.function_passes {
+            fpm.run_on(function);
+        }
         Ok(())
     }
 
+    /// Run the interprocedural passes (eg. inlining)
+    fn run_module_passes(&self) {
+        if self.opt_level == OptimizationLevel::None {
+            return
+        }
+        let mpm = PassManager::create(());
+        pass_manager_builder(self.opt_level).populate_module_pass_manager(&mpm);
+        mpm.run_on(&self.module);
+    }
+
+    /// Write LLVM bitcode to `path`
+    pub fn write_bitcode(&self, path: &Path) -> Result<(), Error> {
+        if self.module.write_bitcode_to_path(path) {
+            Ok(())
+        }
+        else {
+            Err(error::bug(&format!("failed to write bitcode to {:?}", path)))
+        }
+    }
+
+    /// Write assembly or object file for the host machine to `path`
+    pub fn write_native_file(&self, path: &Path, file_type: FileType) -> Result<(), Error> {
+        let target_machine = self.native_target_machine()?;
+        target_machine.write_to_file(&self.module, file_type, path)
+            .map_err(|msg| error::bug(&format!("failed to write {:?}: {}", path, msg)))
+    }
+
+    fn native_target_machine(&self) -> Result<TargetMachine, Error> {
+        Target::initialize_native(&InitializationConfig::default())
+            .map_err(|msg| error::bug(&format!("failed to initialize native target: {}", msg)))?;
+        let triple = TargetMachine::get_default_triple().to_string();
+        let target = Target::from_triple(&triple)
+            .map_err(|msg| error::bug(&format!("unknown target {}: {}", triple, msg)))?;
+        target.create_target_machine(&triple,
+                                     "generic",
+                                     "",
+                                     self.opt_level,
+                                     RelocMode::PIC,
+                                     CodeModel::Default)
+            .ok_or_else(|| error::bug(&format!("failed to create target machine for {}", triple)))
+    }
+
     fn gen_declares(&self) {
         let fn_type = self.i32_type.fn_type(&[self.i32_type.into()], false);
         self.module.add_function("putchar", fn_type, None);
@@ -73,25 +237,88 @@ impl CodeGen {
         self.module.add_function("sqrt", fn_type, None);
         let fn_type = self.f64_type.fn_type(&[self.f64_type.into()], false);
         self.module.add_function("fabs", fn_type, None);
+
+        // For panics
+        let str_type = IntType::i8_type().ptr_type(AddressSpace::Generic);
+        let fn_type = self.i32_type.fn_type(&[self.i32_type.into(), str_type.into()], true);
+        self.module.add_function("dprintf", fn_type, None);
+        let fn_type = self.void_type.fn_type(&[self.i32_type.into()], false);
+        self.module.add_function("exit", fn_type, None);
+
+        // Arithmetic with overflow checking (returns {i32 result, i1 overflowed})
+        let result_type = StructType::struct_type(&[self.i32_type.into(), self.i1_type.into()], false);
+        let fn_type = result_type.fn_type(&[self.i32_type.into(), self.i32_type.into()], false);
+        self.module.add_function("llvm.sadd.with.overflow.i32", fn_type, None);
+        self.module.add_function("llvm.ssub.with.overflow.i32", fn_type, None);
+        self.module.add_function("llvm.smul.with.overflow.i32", fn_type, None);
+    }
+
+    /// Declare the C functions of `extern def`
+    fn gen_extern_declares(&self, extern_funcs: &[MethodSignature]) -> Result<(), Error> {
+        for sig in extern_funcs {
+            let name = &sig.fullname.first_name.0;
+            let arg_types = sig.params.iter().map(|param| self.llvm_type(&param.ty)).collect::<Vec<_>>();
+            let fn_type = if sig.ret_ty.is_void_type() {
+                self.void_type.fn_type(&arg_types, false)
+            }
+            else {
+                self.llvm_type(&sig.ret_ty).fn_type(&arg_types, false)
+            };
+            // eg. `putchar` is already declared by the runtime
+            match self.module.get_function(name) {
+                Some(func) if func.get_type() == fn_type => (),
+                Some(_) => {
+                    return Err(error::program_error(&format!(
+                        "extern def {} does not match the declaration used by the runtime",
+                        name)))
+                },
+                None => {
+                    self.module.add_function(name, fn_type, None);
+                }
+            }
+        }
+        Ok(())
     }
 
-    fn gen_constant_ptrs(&self, constants: &HashMap<ConstFullname, TermTy>) {
+    /// Create globals for the constants. Constants evaluated at compile
+    /// time are initialized with their values
+    fn gen_constant_ptrs(&self,
+                         constants: &HashMap<ConstFullname, TermTy>,
+                         const_values: &HashMap<ConstFullname, ConstValue>) {
         for (fullname, ty) in constants {
             let name = &fullname.0;
             let global = self.module.add_global(self.llvm_type(&ty), None, name);
             global.set_linkage(inkwell::module::Linkage::Internal);
             let null = self.i32_type.ptr_type(AddressSpace::Generic).const_null();
-            match self.llvm_zero_value(ty) {
-                Some(zero) => global.set_initializer(&zero),
+            let value = const_values.get(fullname).map(|value| match value {
+                ConstValue::Int(i) => self.gen_decimal_literal(*i),
+                ConstValue::Float(f) => self.gen_float_literal(*f),
+                ConstValue::Bool(b) => self.gen_boolean_literal(*b),
+            });
+            match value.or_else(|| self.llvm_zero_value(ty)) {
+                Some(init) => global.set_initializer(&init),
                 None       => global.set_initializer(&null),
             }
         }
     }
 
-    fn gen_main(&mut self, main_exprs: &HirExpressions) -> Result<(), Error> {
+    fn gen_main(&mut self, main_exprs: &HirExpressions, returns_value: bool) -> Result<(), Error> {
+        let returns_value = returns_value && !main_exprs.ty.is_void_type();
+        // Bool is returned as i32 since the upper bits of an i1 in the
+        // return register are undefined
+        let returns_bool = returns_value && main_exprs.ty == ty::raw("Bool");
         // define i32 @main() {
-        let main_type = self.i32_type.fn_type(&[], false);
-        let function = self.module.add_function("main", main_type, None);
+        let main_type = if returns_value && !returns_bool {
+            self.llvm_type(&main_exprs.ty).fn_type(&[], false)
+        }
+        else {
+            self.i32_type.fn_type(&[], false)
+        };
+        let name = if self.recoverable_panic { "shiika_main" } else { "main" };
+        let function = self.module.add_function(name, main_type, None);
+        if let Some(debug_info) = &self.debug_info {
+            debug_info.add_subprogram(&function, "main", None);
+        }
         let basic_block = self.context.append_basic_block(&function, "");
         self.builder.position_at_end(&basic_block);
 
@@ -101,13 +328,33 @@ impl CodeGen {
 
         // Create the Main object
         self.the_main = Some(self.allocate_sk_obj(&ClassFullname("Object".to_string())));
+        self.gen_push_frame("main");
 
         // Generate main exprs
-        let mut ctx = CodeGenContext::new(function);
-        self.gen_exprs(&mut ctx, &main_exprs)?;
+        let mut ctx = CodeGenContext::new(function, true);
+        let last_value_opt = self.gen_exprs(&mut ctx, &main_exprs)?;
+        self.gen_pop_frame();
 
-        // ret i32 0
-        self.builder.build_return(Some(&self.i32_type.const_int(0, false)));
+        match last_value_opt {
+            Some(value) if returns_bool => {
+                let value = self.builder.build_int_z_extend(value.into_int_value(), self.i32_type, "bool");
+                self.builder.build_return(Some(&value));
+            },
+            Some(value) if returns_value => {
+                self.builder.build_return(Some(&value));
+            },
+            _ => {
+                // ret i32 0
+                self.builder.build_return(Some(&self.i32_type.const_int(0, false)));
+            }
+        }
+        if let Some(debug_info) = &self.debug_info {
+            debug_info.finish_function(&function, None);
+        }
+        self.run_function_passes(&function)?;
+        if self.recoverable_panic {
+            self.gen_recoverable_main(function);
+        }
         Ok(())
     }
 
@@ -146,15 +393,31 @@ impl CodeGen {
             .expect(&format!("[BUG] get_function not found: {:?}", method.signature));
 
         // Set param names
+        let n_tags = method.signature.typarams.iter().filter(|t| t.typaram_bound().is_some()).count();
         for (i, param) in function.get_param_iter().enumerate() {
             if i == 0 {
                 inkwell_set_name(param, "self")
             }
             else {
-                inkwell_set_name(param, &method.signature.params[i-1].name)
+                match method.signature.params.get(i-1) {
+                    Some(sk_param) => inkwell_set_name(param, &sk_param.name),
+                    // Type tag of a bounded type parameter
+                    None if i-1 < method.signature.params.len() + n_tags => inkwell_set_name(param, "tag"),
+                    // Whether the argument for a default value is given
+                    None => inkwell_set_name(param, "given"),
+                }
             }
         }
 
+        // The location of a method is the one of its first expression
+        let locs = match &method.body {
+            SkMethodBody::ShiikaMethodBody { exprs } => exprs.exprs.first().and_then(|expr| expr.locs.as_ref()),
+            _ => None,
+        };
+        if let Some(debug_info) = &self.debug_info {
+            debug_info.add_subprogram(&function, &method.signature.fullname.full_name, locs);
+        }
+
         // Main basic block
         let basic_block = self.context.append_basic_block(&function, "");
         self.builder.position_at_end(&basic_block);
@@ -173,15 +436,20 @@ impl CodeGen {
                                             &exprs)?
             }
         }
-        Ok(())
+        if let Some(debug_info) = &self.debug_info {
+            debug_info.finish_function(&function, locs);
+        }
+        self.run_function_passes(&function)
     }
 
     fn gen_shiika_method_body(&self,
                               function: inkwell::values::FunctionValue,
                               void_method: bool,
                               exprs: &HirExpressions) -> Result<(), Error> {
-        let mut ctx = CodeGenContext::new(function);
+        let mut ctx = CodeGenContext::new(function, false);
+        self.gen_push_frame(function.get_name().to_str().unwrap());
         let last_value_opt = self.gen_exprs(&mut ctx, exprs)?;
+        self.gen_pop_frame();
         if void_method {
             self.builder.build_return(None);
         }
@@ -210,6 +478,20 @@ impl CodeGen {
     fn gen_expr(&self,
                 ctx: &mut CodeGenContext,
                 expr: &HirExpression) -> Result<inkwell::values::BasicValueEnum, Error> {
+        match &self.debug_info {
+            Some(debug_info) => {
+                let mark = debug_info::Mark::new(self.builder.get_insert_block().unwrap());
+                let value = self.gen_expr_(ctx, expr)?;
+                debug_info.set_location(&ctx.function, mark, &expr.locs);
+                Ok(value)
+            },
+            None => self.gen_expr_(ctx, expr),
+        }
+    }
+
+    fn gen_expr_(&self,
+                 ctx: &mut CodeGenContext,
+                 expr: &HirExpression) -> Result<inkwell::values::BasicValueEnum, Error> {
         match &expr.node {
             HirIfExpression { cond_expr, then_expr, else_expr } => {
                 self.gen_if_expr(ctx, &expr.ty, &cond_expr, &then_expr, &else_expr)
@@ -224,7 +506,7 @@ impl CodeGen {
                 self.gen_const_assign(ctx, fullname, rhs)
             },
             HirMethodCall { receiver_expr, method_fullname, arg_exprs } => {
-                self.gen_method_call(ctx, method_fullname, receiver_expr, arg_exprs)
+                self.gen_method_call(ctx, method_fullname, receiver_expr, arg_exprs, &expr.locs)
             },
             HirArgRef { idx } => {
                 Ok(ctx.function.get_nth_param((*idx as u32) + 1).unwrap()) // +1 for the first %self 
@@ -239,7 +521,7 @@ impl CodeGen {
                 Ok(self.builder.build_load(ptr, &fullname.0))
             },
             HirSelfExpression => {
-                if ctx.function.get_name().to_str().unwrap() == "main" {
+                if ctx.is_toplevel {
                     Ok(self.the_main.unwrap())
                 }
                 else {
@@ -256,6 +538,75 @@ impl CodeGen {
             HirBooleanLiteral { value } => {
                 Ok(self.gen_boolean_literal(*value))
             },
+            HirStringLiteral { value } => {
+                Ok(self.builder.build_global_string_ptr(value, "str").as_pointer_value().as_basic_value_enum())
+            },
+            HirNilLiteral => {
+                Ok(self.llvm_type(&expr.ty).into_pointer_type().const_null().as_basic_value_enum())
+            },
+            HirOptWrap { expr: inner } => {
+                self.gen_opt_wrap(ctx, &expr.ty, inner)
+            },
+            HirOptUnwrap { expr: inner } => {
+                self.gen_opt_unwrap(ctx, &expr.ty, inner)
+            },
+            HirIsNil { expr: inner, negated } => {
+                let ptr = self.gen_expr(ctx, inner)?.into_pointer_value();
+                let result = if *negated {
+                    self.builder.build_is_not_null(ptr, "is_not_nil")
+                }
+                else {
+                    self.builder.build_is_null(ptr, "is_nil")
+                };
+                Ok(result.as_basic_value_enum())
+            },
+            HirUnionWrap { expr: inner } => {
+                self.gen_union_wrap(ctx, &expr.ty, inner)
+            },
+            HirUnionCast { expr: inner } => {
+                self.gen_union_cast(ctx, &expr.ty, inner)
+            },
+            HirTypeTest { expr: inner, tested_ty } => {
+                self.gen_type_test(ctx, inner, tested_ty)
+            },
+            HirInterfaceWrap { expr: inner } => {
+                self.gen_interface_wrap(ctx, &expr.ty, inner)
+            },
+            HirIntersectionCast { expr: inner } => {
+                self.gen_intersection_cast(ctx, &expr.ty, inner)
+            },
+            HirGenericWrap { expr: inner } => {
+                self.gen_generic_wrap(ctx, inner)
+            },
+            HirGenericUnwrap { expr: inner } => {
+                self.gen_generic_unwrap(ctx, &expr.ty, inner)
+            },
+            HirTypeTag { ty } => {
+                Ok(self.i32_type.const_int(self.type_tag(ty), false).as_basic_value_enum())
+            },
+            HirOmittedArg => {
+                Ok(self.undef_value(&expr.ty))
+            },
+            HirReplLoad { slot } => {
+                Ok(self.builder.build_load(self.repl_slot_ptr(*slot, &expr.ty), "slot_value"))
+            },
+            HirReplStore { slot, expr: inner } => {
+                let value = self.gen_expr(ctx, inner)?;
+                self.builder.build_store(self.repl_slot_ptr(*slot, &inner.ty), value);
+                Ok(value)
+            },
+            HirTyParamUpcast { expr: inner, tag } => {
+                let value = self.gen_expr(ctx, inner)?;
+                let tag = self.gen_expr(ctx, tag)?.into_int_value();
+                Ok(self.gen_tagged(tag, value).as_basic_value_enum())
+            },
+            HirObjectUpcast { expr: inner } => {
+                let ptr = self.gen_generic_wrap(ctx, inner)?;
+                Ok(self.builder.build_bitcast(ptr, self.llvm_type(&expr.ty), "object"))
+            },
+            HirDowncast { expr: inner } => {
+                self.gen_downcast(ctx, &expr.ty, inner)
+            },
             HirClassLiteral { fullname } => {
                 Ok(self.gen_class_literal(fullname))
             }
@@ -272,6 +623,9 @@ impl CodeGen {
                    then_expr: &HirExpression,
                    else_expr: &HirExpression) -> Result<inkwell::values::BasicValueEnum, Error> {
         let cond_value = self.gen_expr(ctx, cond_expr)?.into_int_value();
+        if ty.is_void_type() {
+            return self.gen_void_if(ctx, cond_value, then_expr, else_expr)
+        }
         let then_block = ctx.function.append_basic_block(&"IfThen");
         let else_block = ctx.function.append_basic_block(&"IfElse");
         let merge_block = ctx.function.append_basic_block(&"IfEnd");
@@ -291,6 +645,29 @@ impl CodeGen {
         Ok(phi_node.as_basic_value())
     }
 
+    /// `if` whose value is Void (no `else` or a branch is Void). The values
+    /// of the branches are discarded
+    fn gen_void_if(&self,
+                   ctx: &mut CodeGenContext,
+                   cond_value: inkwell::values::IntValue,
+                   then_expr: &HirExpression,
+                   else_expr: &HirExpression) -> Result<inkwell::values::BasicValueEnum, Error> {
+        let then_block = ctx.function.append_basic_block(&"IfThen");
+        let else_block = ctx.function.append_basic_block(&"IfElse");
+        let merge_block = ctx.function.append_basic_block(&"IfEnd");
+        self.builder.build_conditional_branch(cond_value, &then_block, &else_block);
+        self.builder.position_at_end(&then_block);
+        self.gen_expr(ctx, then_expr)?;
+        self.builder.build_unconditional_branch(&merge_block);
+        self.builder.position_at_end(&else_block);
+        if else_expr.node != HirNop {
+            self.gen_expr(ctx, else_expr)?;
+        }
+        self.builder.build_unconditional_branch(&merge_block);
+        self.builder.position_at_end(&merge_block);
+        Ok(self.i32_type.const_int(0, false).as_basic_value_enum()) // return Void
+    }
+
     fn gen_while_expr(&self, 
                       ctx: &mut CodeGenContext,
                       cond_expr: &HirExpression,
@@ -326,6 +703,9 @@ impl CodeGen {
             },
             None => {
                 let ptr = self.builder.build_alloca(self.llvm_type(&rhs.ty), name);
+                if let Some(debug_info) = &self.debug_info {
+                    debug_info.declare_lvar(&ctx.function, name, &rhs.ty, ptr, &rhs.locs);
+                }
                 self.builder.build_store(ptr, value);
                 ctx.lvars.insert(name.to_string(), ptr);
             }
@@ -349,7 +729,8 @@ impl CodeGen {
                        ctx: &mut CodeGenContext,
                        method_fullname: &MethodFullname,
                        receiver_expr: &HirExpression,
-                       arg_exprs: &Vec<HirExpression>) -> Result<inkwell::values::BasicValueEnum, Error> {
+                       arg_exprs: &Vec<HirExpression>,
+                       locs: &Option<LocationSpan>) -> Result<inkwell::values::BasicValueEnum, Error> {
         let receiver_value = self.gen_expr(ctx, receiver_expr)?;
         let mut arg_values = arg_exprs.iter().map(|arg_expr|
           self.gen_expr(ctx, arg_expr)
@@ -359,6 +740,7 @@ impl CodeGen {
             .expect(&format!("[BUG] get_function not found: {:?}", method_fullname));
         let mut llvm_args = vec!(receiver_value);
         llvm_args.append(&mut arg_values);
+        self.gen_set_frame_location(locs);
         match self.builder.build_call(function, &llvm_args, "result").try_as_basic_value().left() {
             Some(result_value) => Ok(result_value),
             None => {
@@ -389,6 +771,235 @@ impl CodeGen {
         self.i1_type.const_int(i, false).as_basic_value_enum()
     }
 
+    /// Convert a value of `T` (or nil) to `opt_ty` (`T?`)
+    fn gen_opt_wrap(&self,
+                    ctx: &mut CodeGenContext,
+                    opt_ty: &TermTy,
+                    expr: &HirExpression) -> Result<inkwell::values::BasicValueEnum, Error> {
+        let value = self.gen_expr(ctx, expr)?;
+        let opt_type = self.llvm_type(opt_ty);
+        if expr.ty == ty::nil() {
+            return Ok(opt_type.into_pointer_type().const_null().as_basic_value_enum())
+        }
+        let base = opt_ty.opt_base().expect("[BUG] gen_opt_wrap: not an optional");
+        if self.is_boxed_in_opt(base) {
+            Ok(self.gen_box(value, base).as_basic_value_enum())
+        }
+        else {
+            Ok(self.builder.build_bitcast(value, opt_type, "opt"))
+        }
+    }
+
+    /// Copy `value` of `ty` to the heap and return the pointer to it
+    fn gen_box(&self, value: BasicValueEnum, ty: &TermTy) -> PointerValue {
+        let llvm_type = self.llvm_type(ty);
+        let size = match llvm_type {
+            BasicTypeEnum::IntType(t) => t.size_of(),
+            BasicTypeEnum::FloatType(t) => t.size_of(),
+            BasicTypeEnum::StructType(t) => t.size_of().expect("[BUG] gen_box: unsized struct"),
+            _ => panic!("[BUG] gen_box: unexpected type {:?}", llvm_type),
+        };
+        // %box = call i8* @GC_malloc(i64 %size)
+        let func = self.module.get_function("GC_malloc").unwrap();
+        let raw_addr = self.builder.build_call(func, &[size.as_basic_value_enum()], "raw_addr").try_as_basic_value().left().unwrap();
+        let ptr = self.builder.build_bitcast(raw_addr, llvm_type.ptr_type(AddressSpace::Generic), "box").into_pointer_value();
+        self.builder.build_store(ptr, value);
+        ptr
+    }
+
+    /// Pointer to the `slot`-th REPL slot as a `ty*`
+    fn repl_slot_ptr(&self, slot: usize, ty: &TermTy) -> PointerValue {
+        let addr = self.repl_slots.expect("[BUG] REPL slots are not set") + (slot * REPL_SLOT_SIZE) as u64;
+        let addr = self.i64_type.const_int(addr, false);
+        self.builder.build_int_to_ptr(addr, self.llvm_type(ty).ptr_type(AddressSpace::Generic), "slot")
+    }
+
+    /// A value of `ty` which is never read (for omitted arguments)
+    fn undef_value(&self, ty: &TermTy) -> BasicValueEnum {
+        runtime::undef_of(self.llvm_type(ty))
+    }
+
+    /// Convert a value to `union_ty`. A value of a union is `{i32, i8*}`
+    /// where the first is the tag of the member and the second is the value
+    /// (boxed if it is not a pointer)
+    fn gen_union_wrap(&self,
+                      ctx: &mut CodeGenContext,
+                      union_ty: &TermTy,
+                      expr: &HirExpression) -> Result<inkwell::values::BasicValueEnum, Error> {
+        let value = self.gen_expr(ctx, expr)?;
+        // Unions have the same representation
+        if expr.ty.union_members().is_some() {
+            return Ok(value)
+        }
+        let members = union_ty.union_members().expect("[BUG] gen_union_wrap: not a union");
+        let member = members.iter().find(|m| expr.ty.conforms_to(m))
+            .expect("[BUG] gen_union_wrap: no member matches");
+        let payload = if self.is_boxed_in_opt(&expr.ty) {
+            self.gen_box(value, &expr.ty).as_basic_value_enum()
+        }
+        else {
+            value
+        };
+        let payload = self.builder.build_bitcast(payload, runtime::str_type(), "payload");
+        Ok(self.gen_tagged_value(member, payload).as_basic_value_enum())
+    }
+
+    /// Take out the value of `ty` from a union. Panics if it is of another member
+    fn gen_union_cast(&self,
+                      ctx: &mut CodeGenContext,
+                      ty: &TermTy,
+                      expr: &HirExpression) -> Result<inkwell::values::BasicValueEnum, Error> {
+        let value = self.gen_expr(ctx, expr)?;
+        if ty.union_members().is_some() {
+            return Ok(value)
+        }
+        let union_value = value.into_struct_value();
+        let matches = self.gen_tag_test(union_value, ty);
+        let mismatch = self.builder.build_not(matches, "mismatch");
+        self.gen_panic_if(&ctx.function, mismatch, "invalid cast");
+        Ok(self.gen_untag(union_value, ty))
+    }
+
+    /// `expr as ty` where `expr` is a union or an interface. Panics if
+    /// the tag is not of `ty` (or its members)
+    fn gen_downcast(&self,
+                    ctx: &mut CodeGenContext,
+                    ty: &TermTy,
+                    expr: &HirExpression) -> Result<inkwell::values::BasicValueEnum, Error> {
+        let tagged = self.gen_expr(ctx, expr)?.into_struct_value();
+        let matches = self.gen_tag_test(tagged, ty);
+        let mismatch = self.builder.build_not(matches, "mismatch");
+        self.gen_panic_if(&ctx.function, mismatch, "invalid cast");
+        if ty.union_members().is_some() {
+            Ok(tagged.as_basic_value_enum())
+        }
+        else {
+            Ok(self.gen_untag(tagged, ty))
+        }
+    }
+
+    /// Take out the payload of a tagged value as `ty`
+    fn gen_untag(&self, tagged: StructValue, ty: &TermTy) -> inkwell::values::BasicValueEnum {
+        let payload = self.builder.build_extract_value(tagged, 1, "payload").unwrap();
+        let llvm_type = self.llvm_type(ty);
+        if self.is_boxed_in_opt(ty) {
+            let ptr = self.builder.build_bitcast(payload, llvm_type.ptr_type(AddressSpace::Generic), "box");
+            self.builder.build_load(ptr.into_pointer_value(), "value")
+        }
+        else {
+            self.builder.build_bitcast(payload, llvm_type, "value")
+        }
+    }
+
+    /// `expr is tested_ty`
+    fn gen_type_test(&self,
+                     ctx: &mut CodeGenContext,
+                     expr: &HirExpression,
+                     tested_ty: &TermTy) -> Result<inkwell::values::BasicValueEnum, Error> {
+        let value = self.gen_expr(ctx, expr)?;
+        let result = if expr.ty.union_members().is_some() {
+            self.gen_tag_test(value.into_struct_value(), tested_ty)
+        }
+        else {
+            match expr.ty.opt_base() {
+                Some(base) if !tested_ty.is_nilable() && base.conforms_to(tested_ty) => {
+                    self.builder.build_is_not_null(value.into_pointer_value(), "is_not_nil")
+                },
+                // Known statically
+                _ => {
+                    let b = expr.ty.conforms_to(tested_ty) || (expr.ty.is_nilable() && tested_ty.is_nilable());
+                    self.i1_type.const_int(b as u64, false)
+                },
+            }
+        };
+        Ok(result.as_basic_value_enum())
+    }
+
+    /// Return true if the tag of `union_value` is the one of `ty` (or
+    /// one of its members if `ty` is a union)
+    fn gen_tag_test(&self, union_value: StructValue, ty: &TermTy) -> IntValue {
+        let tag = self.builder.build_extract_value(union_value, 0, "tag").unwrap().into_int_value();
+        let tys = match ty.union_members() {
+            Some(members) => members.to_vec(),
+            None => vec![ty.clone()],
+        };
+        tys.iter().fold(self.i1_type.const_int(0, false), |acc, t| {
+            let expected = self.i32_type.const_int(self.type_tag(t), false);
+            let eq = self.builder.build_int_compare(inkwell::IntPredicate::EQ, tag, expected, "is_member");
+            self.builder.build_or(acc, eq, "matches")
+        })
+    }
+
+    /// Tags assigned so far. Passing them to `set_type_tags` of the next
+    /// `CodeGen` keeps the tags of the values consistent (used by REPL)
+    pub fn type_tags(&self) -> HashMap<ClassFullname, u64> {
+        self.type_tags.borrow().clone()
+    }
+
+    pub fn set_type_tags(&mut self, tags: HashMap<ClassFullname, u64>) {
+        self.type_tags = RefCell::new(tags);
+    }
+
+    /// Return the tag of `ty` in union values
+    fn type_tag(&self, ty: &TermTy) -> u64 {
+        let mut tags = self.type_tags.borrow_mut();
+        let next = tags.len() as u64;
+        *tags.entry(ty.fullname.clone()).or_insert(next)
+    }
+
+    /// LLVM type of union values
+    fn union_type(&self) -> StructType {
+        self.context.struct_type(&[self.i32_type.as_basic_type_enum(), runtime::str_type().as_basic_type_enum()], false)
+    }
+
+    /// Take out the value of `T?`. Panics if it is nil
+    fn gen_opt_unwrap(&self,
+                      ctx: &mut CodeGenContext,
+                      ty: &TermTy,
+                      expr: &HirExpression) -> Result<inkwell::values::BasicValueEnum, Error> {
+        let ptr = self.gen_expr(ctx, expr)?.into_pointer_value();
+        let is_nil = self.builder.build_is_null(ptr, "is_nil");
+        self.gen_panic_if(&ctx.function, is_nil, "unwrapped nil");
+        if self.is_boxed_in_opt(ty) {
+            Ok(self.builder.build_load(ptr, "value"))
+        }
+        else {
+            Ok(ptr.as_basic_value_enum())
+        }
+    }
+
+    /// Convert a value to `i8*`, the representation of the values of type
+    /// parameters. Values which are not pointers are boxed (so that a value
+    /// of `T` has the same representation as the one of `T?`)
+    fn gen_generic_wrap(&self,
+                        ctx: &mut CodeGenContext,
+                        expr: &HirExpression) -> Result<inkwell::values::BasicValueEnum, Error> {
+        let value = self.gen_expr(ctx, expr)?;
+        let ptr = if !expr.ty.is_nilable() && self.is_boxed_in_opt(&expr.ty) {
+            self.gen_box(value, &expr.ty).as_basic_value_enum()
+        }
+        else {
+            value
+        };
+        Ok(self.builder.build_bitcast(ptr, runtime::str_type(), "generic"))
+    }
+
+    /// Take out the value of `ty` from `i8*` (the reverse of `gen_generic_wrap`)
+    fn gen_generic_unwrap(&self,
+                          ctx: &mut CodeGenContext,
+                          ty: &TermTy,
+                          expr: &HirExpression) -> Result<inkwell::values::BasicValueEnum, Error> {
+        let value = self.gen_expr(ctx, expr)?;
+        let llvm_type = self.llvm_type(ty);
+        if !ty.is_nilable() && self.is_boxed_in_opt(ty) {
+            let ptr = self.builder.build_bitcast(value, llvm_type.ptr_type(AddressSpace::Generic), "box");
+            Ok(self.builder.build_load(ptr.into_pointer_value(), "value"))
+        }
+        else {
+            Ok(self.builder.build_bitcast(value, llvm_type, "value"))
+        }
+    }
+
     fn gen_class_literal(&self, fullname: &ClassFullname) -> inkwell::values::BasicValueEnum {
         self.allocate_sk_obj(&ty::meta(&fullname.0).fullname)
     }
@@ -418,6 +1029,14 @@ impl CodeGen {
         let self_type = self.llvm_type(self_ty);
         let mut arg_types = signature.params.iter().map(|param| self.llvm_type(&param.ty)).collect::<Vec<_>>();
         arg_types.insert(0, self_type);
+        // Type tags of the bounded type parameters
+        for _ in signature.typarams.iter().filter(|t| t.typaram_bound().is_some()) {
+            arg_types.push(self.i32_type.as_basic_type_enum());
+        }
+        // Whether the arguments for the params with default values are given
+        for _ in signature.params.iter().filter(|p| p.has_default) {
+            arg_types.push(self.i1_type.as_basic_type_enum());
+        }
 
         if signature.ret_ty.is_void_type() {
             self.void_type.fn_type(&arg_types, false)
@@ -435,15 +1054,38 @@ impl CodeGen {
                     "Bool" => self.i1_type.as_basic_type_enum(),
                     "Int" => self.i32_type.as_basic_type_enum(),
                     "Float" => self.f64_type.as_basic_type_enum(),
+                    "String" => IntType::i8_type().ptr_type(AddressSpace::Generic).as_basic_type_enum(),
                     // TODO: replace with special value?
                     "Void" => self.i32_type.as_basic_type_enum(),
+                    _ if self.is_interface(ty) => self.union_type().as_basic_type_enum(),
                     _ => self.sk_obj_llvm_type(ty)
                 }
             },
+            TyBody::TyOpt { ref base } => {
+                let base_type = self.llvm_type(base);
+                if self.is_boxed_in_opt(base) {
+                    base_type.ptr_type(AddressSpace::Generic).as_basic_type_enum()
+                }
+                else {
+                    base_type
+                }
+            },
+            // Values of type parameters are passed as `i8*` (see `gen_generic_wrap`)
+            TyBody::TyNil | TyBody::TyParam { .. } => runtime::str_type().as_basic_type_enum(),
+            TyBody::TyUnion { .. } => self.union_type().as_basic_type_enum(),
+            // Tagged with the class of the value like interfaces
+            TyBody::TyIntersection { .. } => self.union_type().as_basic_type_enum(),
             _ => self.sk_obj_llvm_type(ty)
         }
     }
 
+    /// Values of `T?` are pointers so that nil can be the null pointer.
+    /// Returns true if `T` is not a pointer and must be boxed (this is
+    /// also used for the values in unions)
+    fn is_boxed_in_opt(&self, base: &TermTy) -> bool {
+        self.llvm_zero_value(base).is_some()
+    }
+
     /// Return zero value in LLVM. None if it is a pointer
     fn llvm_zero_value(&self, ty: &TermTy) -> Option<inkwell::values::BasicValueEnum> {
         match ty.body {
@@ -452,13 +1094,21 @@ impl CodeGen {
                     "Bool" => Some(self.i1_type.const_int(0, false).as_basic_value_enum()),
                     "Int" => Some(self.i32_type.const_int(0, false).as_basic_value_enum()),
                     "Float" => Some(self.f64_type.const_float(0.0).as_basic_value_enum()),
+                    _ if self.is_interface(ty) => Some(self.union_zero_value()),
                     _ => None,
                 }
             },
+            TyBody::TyUnion { .. } => Some(self.union_zero_value()),
             _ => None,
         }
     }
 
+    fn union_zero_value(&self) -> inkwell::values::BasicValueEnum {
+        let zero = [self.i32_type.const_int(0, false).as_basic_value_enum(),
+                    runtime::str_type().const_null().as_basic_value_enum()];
+        self.union_type().const_named_struct(&zero).as_basic_value_enum()
+    }
+
     fn sk_obj_llvm_type(&self, ty: &TermTy) -> inkwell::types::BasicTypeEnum {
         let struct_type = self.llvm_struct_types.get(&ty.fullname)
             .expect(&format!("[BUG] struct_type not found: {:?}", ty.fullname));
@@ -466,6 +1116,12 @@ impl CodeGen {
     }
 }
 
+fn pass_manager_builder(level: OptimizationLevel) -> PassManagerBuilder {
+    let builder = PassManagerBuilder::create();
+    builder.set_optimization_level(level);
+    builder
+}
+
 // Question: is there a better way to do this?
 fn inkwell_set_name(val: BasicValueEnum, name: &str) {
     match val {
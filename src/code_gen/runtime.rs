@@ -0,0 +1,276 @@
+/// Runtime support for panics
+///
+/// The generated code keeps a stack of the Shiika methods being executed
+/// (`shiika_frame_names`) and the location of the method call each of them
+/// is making (`shiika_frame_locs`). `shiika_panic` prints them as the
+/// backtrace and exits with `PANIC_EXIT_STATUS`.
+///
+/// When the panic is recoverable (REPL), `shiika_panic` passes the message
+/// and the frames to the host (`shiika_repl_panic`, provided by the JIT)
+/// and `longjmp`s back to `main` instead of exiting the process.
+use inkwell::AddressSpace;
+use inkwell::attributes::{Attribute, AttributeLoc};
+use inkwell::types::*;
+use inkwell::values::*;
+use crate::ast::LocationSpan;
+use crate::code_gen::CodeGen;
+use crate::error::PANIC_EXIT_STATUS;
+
+/// Max number of frames recorded. Deeper frames are not recorded (they
+/// are written to the extra slot at the end, which is never read)
+pub(super) const FRAMES_MAX: u32 = 1024;
+
+/// Size of the buffer for `setjmp` (larger than `jmp_buf` of the
+/// supported platforms)
+const JMP_BUF_SIZE: u32 = 512;
+
+impl CodeGen {
+    /// Declare the globals for the backtrace and define `shiika_panic`
+    pub(super) fn gen_runtime(&self) {
+        let str_type = str_type();
+        let frames_type = str_type.array_type(FRAMES_MAX + 1);
+        let nulls = vec![str_type.const_null(); FRAMES_MAX as usize + 1];
+        for name in &["shiika_frame_names", "shiika_frame_locs"] {
+            let global = self.module.add_global(frames_type, None, name);
+            global.set_linkage(inkwell::module::Linkage::Internal);
+            global.set_initializer(&str_type.const_array(&nulls));
+        }
+        let depth = self.module.add_global(self.i32_type, None, "shiika_frame_depth");
+        depth.set_linkage(inkwell::module::Linkage::Internal);
+        depth.set_initializer(&self.i32_type.const_int(0, false));
+
+        if self.recoverable_panic {
+            self.gen_recovery_declares();
+        }
+        self.gen_panic_func();
+    }
+
+    /// Declare `setjmp`, `longjmp`, the host callback and the buffer
+    fn gen_recovery_declares(&self) {
+        let i8_type = IntType::i8_type();
+        let buf = self.module.add_global(i8_type.array_type(JMP_BUF_SIZE), None, "shiika_jmp_buf");
+        buf.set_linkage(inkwell::module::Linkage::Internal);
+        buf.set_initializer(&i8_type.const_array(&vec![i8_type.const_int(0, false); JMP_BUF_SIZE as usize]));
+        buf.set_alignment(16);
+
+        let fn_type = self.i32_type.fn_type(&[str_type().into()], false);
+        let setjmp = self.module.add_function("setjmp", fn_type, None);
+        // Tell LLVM not to keep values in registers across the second return
+        setjmp.add_attribute(AttributeLoc::Function, self.enum_attribute("returns_twice"));
+        let fn_type = self.void_type.fn_type(&[str_type().into(), self.i32_type.into()], false);
+        self.module.add_function("longjmp", fn_type, None);
+        // void shiika_repl_panic(i8* msg, i8** names, i8** locs, i32 depth)
+        let frames_type = str_type().ptr_type(AddressSpace::Generic);
+        let fn_type = self.void_type.fn_type(&[str_type().into(), frames_type.into(), frames_type.into(),
+                                               self.i32_type.into()], false);
+        self.module.add_function("shiika_repl_panic", fn_type, None);
+    }
+
+    /// Define `main` which calls `body` (the main program) and returns
+    /// undef if it panicked. Nothing in `main` is live across `setjmp`,
+    /// so it does not matter that `longjmp` does not restore the registers.
+    /// `body` is not inlined into `main` so that this stays true
+    pub(super) fn gen_recoverable_main(&self, body: FunctionValue) {
+        body.add_attribute(AttributeLoc::Function, self.enum_attribute("noinline"));
+        let fn_type = body.get_type();
+        let function = self.module.add_function("main", fn_type, None);
+        let entry_block = self.context.append_basic_block(&function, "");
+        let run_block = function.append_basic_block("Run");
+        let panicked_block = function.append_basic_block("Panicked");
+
+        self.builder.position_at_end(&entry_block);
+        let result = self.builder.build_call(self.module.get_function("setjmp").unwrap(),
+                                             &[self.jmp_buf_ptr().into()], "jumped")
+            .try_as_basic_value().left().unwrap().into_int_value();
+        let zero = self.i32_type.const_int(0, false);
+        let is_first = self.builder.build_int_compare(inkwell::IntPredicate::EQ, result, zero, "is_first");
+        self.builder.build_conditional_branch(is_first, &run_block, &panicked_block);
+
+        self.builder.position_at_end(&run_block);
+        let value = self.builder.build_call(body, &[], "value").try_as_basic_value().left()
+            .expect("[BUG] main returns void");
+        self.builder.build_return(Some(&value));
+
+        self.builder.position_at_end(&panicked_block);
+        let ret_type = fn_type.get_return_type().expect("[BUG] main returns void");
+        self.builder.build_return(Some(&undef_of(ret_type)));
+    }
+
+    /// define void @shiika_panic(i8* %msg)
+    fn gen_panic_func(&self) {
+        let fn_type = self.void_type.fn_type(&[str_type().into()], false);
+        let function = self.module.add_function("shiika_panic", fn_type, None);
+        let entry_block = self.context.append_basic_block(&function, "");
+        self.builder.position_at_end(&entry_block);
+        let msg = function.get_first_param().unwrap();
+        if self.recoverable_panic {
+            let frames_type = str_type().ptr_type(AddressSpace::Generic);
+            let names = self.module.get_global("shiika_frame_names").unwrap().as_pointer_value();
+            let names = self.builder.build_bitcast(names, frames_type, "names");
+            let locs = self.module.get_global("shiika_frame_locs").unwrap().as_pointer_value();
+            let locs = self.builder.build_bitcast(locs, frames_type, "locs");
+            let depth = self.builder.build_load(self.frame_depth_ptr(), "depth");
+            let callback = self.module.get_function("shiika_repl_panic").unwrap();
+            self.builder.build_call(callback, &[msg, names, locs, depth], "");
+            let longjmp = self.module.get_function("longjmp").unwrap();
+            let one = self.i32_type.const_int(1, false);
+            self.builder.build_call(longjmp, &[self.jmp_buf_ptr().into(), one.into()], "");
+            self.builder.build_unreachable();
+            return
+        }
+        let cond_block = function.append_basic_block("LoopCond");
+        let body_block = function.append_basic_block("LoopBody");
+        let end_block = function.append_basic_block("LoopEnd");
+
+        // panic: <msg>
+        let fmt = self.builder.build_global_string_ptr("panic: %s\n", "panic_fmt").as_pointer_value();
+        self.gen_dprintf(&[fmt.into(), msg]);
+        // Iterate the frames from the innermost one
+        let depth = self.builder.build_load(self.frame_depth_ptr(), "depth").into_int_value();
+        let max = self.i32_type.const_int(FRAMES_MAX as u64, false);
+        let in_range = self.builder.build_int_compare(inkwell::IntPredicate::SLE, depth, max, "in_range");
+        let n_frames = self.builder.build_select(in_range, depth, max, "n_frames");
+        //   ... <n> more frames (not recorded)
+        let n_omitted = self.builder.build_int_sub(depth, n_frames.into_int_value(), "n_omitted");
+        let omitted_block = function.append_basic_block("Omitted");
+        let frames_block = function.append_basic_block("Frames");
+        self.builder.build_conditional_branch(in_range, &frames_block, &omitted_block);
+        self.builder.position_at_end(&omitted_block);
+        let fmt = self.builder.build_global_string_ptr("  ... %d more frames (not recorded)\n", "omitted_fmt").as_pointer_value();
+        self.gen_dprintf(&[fmt.into(), n_omitted.into()]);
+        self.builder.build_unconditional_branch(&frames_block);
+        self.builder.position_at_end(&frames_block);
+        let i_ptr = self.builder.build_alloca(self.i32_type, "i");
+        self.builder.build_store(i_ptr, n_frames);
+        self.builder.build_unconditional_branch(&cond_block);
+
+        self.builder.position_at_end(&cond_block);
+        let i = self.builder.build_load(i_ptr, "i").into_int_value();
+        let zero = self.i32_type.const_int(0, false);
+        let has_frame = self.builder.build_int_compare(inkwell::IntPredicate::SGT, i, zero, "has_frame");
+        self.builder.build_conditional_branch(has_frame, &body_block, &end_block);
+
+        //   at <name> (<loc>)
+        self.builder.position_at_end(&body_block);
+        let idx = self.builder.build_int_sub(i, self.i32_type.const_int(1, false), "idx");
+        let name = self.builder.build_load(self.frame_slot("shiika_frame_names", idx), "name");
+        let loc = self.builder.build_load(self.frame_slot("shiika_frame_locs", idx), "loc").into_pointer_value();
+        let unknown = self.builder.build_global_string_ptr("?", "unknown_loc").as_pointer_value();
+        let is_null = self.builder.build_is_null(loc, "is_null");
+        let loc = self.builder.build_select(is_null, unknown, loc, "loc");
+        let fmt = self.builder.build_global_string_ptr("  at %s (%s)\n", "frame_fmt").as_pointer_value();
+        self.gen_dprintf(&[fmt.into(), name, loc]);
+        self.builder.build_store(i_ptr, idx);
+        self.builder.build_unconditional_branch(&cond_block);
+
+        self.builder.position_at_end(&end_block);
+        let exit = self.module.get_function("exit").unwrap();
+        let status = self.i32_type.const_int(PANIC_EXIT_STATUS as u64, false);
+        self.builder.build_call(exit, &[status.into()], "");
+        self.builder.build_unreachable();
+    }
+
+    /// Abort the program with `msg`
+    pub fn gen_panic(&self, msg: &str) {
+        let func = self.module.get_function("shiika_panic").unwrap();
+        let msg = self.builder.build_global_string_ptr(msg, "panic_msg").as_pointer_value();
+        self.builder.build_call(func, &[msg.into()], "");
+        self.builder.build_unreachable();
+    }
+
+    /// Abort the program with `msg` if `cond` is true
+    pub fn gen_panic_if(&self, function: &FunctionValue, cond: IntValue, msg: &str) {
+        let panic_block = function.append_basic_block("Panic");
+        let ok_block = function.append_basic_block("NoPanic");
+        self.builder.build_conditional_branch(cond, &panic_block, &ok_block);
+        self.builder.position_at_end(&panic_block);
+        self.gen_panic(msg);
+        self.builder.position_at_end(&ok_block);
+    }
+
+    /// Push a frame for the Shiika method `name` (called on entering it)
+    pub(super) fn gen_push_frame(&self, name: &str) {
+        let depth_ptr = self.frame_depth_ptr();
+        let depth = self.builder.build_load(depth_ptr, "depth").into_int_value();
+        let idx = self.frame_index(depth);
+        let name = self.builder.build_global_string_ptr(name, "frame_name").as_pointer_value();
+        self.builder.build_store(self.frame_slot("shiika_frame_names", idx), name);
+        self.builder.build_store(self.frame_slot("shiika_frame_locs", idx), str_type().const_null());
+        let depth = self.builder.build_int_add(depth, self.i32_type.const_int(1, false), "depth");
+        self.builder.build_store(depth_ptr, depth);
+    }
+
+    /// Pop the frame (called before returning from a Shiika method)
+    pub(super) fn gen_pop_frame(&self) {
+        let depth_ptr = self.frame_depth_ptr();
+        let depth = self.builder.build_load(depth_ptr, "depth").into_int_value();
+        let depth = self.builder.build_int_sub(depth, self.i32_type.const_int(1, false), "depth");
+        self.builder.build_store(depth_ptr, depth);
+    }
+
+    /// Record `locs` as the location of the current frame (called before
+    /// a method call)
+    pub(super) fn gen_set_frame_location(&self, locs: &Option<LocationSpan>) {
+        let loc = match locs {
+            Some(locs) => self.builder.build_global_string_ptr(&locs.begin_str(), "loc").as_pointer_value(),
+            None => str_type().const_null(),
+        };
+        let depth = self.builder.build_load(self.frame_depth_ptr(), "depth").into_int_value();
+        let top = self.builder.build_int_sub(depth, self.i32_type.const_int(1, false), "top");
+        let idx = self.frame_index(top);
+        self.builder.build_store(self.frame_slot("shiika_frame_locs", idx), loc);
+    }
+
+    fn gen_dprintf(&self, args: &[BasicValueEnum]) {
+        let func = self.module.get_function("dprintf").unwrap();
+        let mut llvm_args = vec![self.i32_type.const_int(2, false).into()]; // stderr
+        llvm_args.extend_from_slice(args);
+        self.builder.build_call(func, &llvm_args, "");
+    }
+
+    fn frame_depth_ptr(&self) -> PointerValue {
+        self.module.get_global("shiika_frame_depth").unwrap().as_pointer_value()
+    }
+
+    /// Pointer to the `idx`th element of the global array `name`
+    fn frame_slot(&self, name: &str, idx: IntValue) -> PointerValue {
+        let array = self.module.get_global(name).unwrap().as_pointer_value();
+        let zero = self.i32_type.const_int(0, false);
+        unsafe { self.builder.build_in_bounds_gep(array, &[zero, idx], "slot") }
+    }
+
+    /// Return `i` if it is less than `FRAMES_MAX`, or the extra slot
+    /// otherwise (so that the recorded frames are not overwritten)
+    fn frame_index(&self, i: IntValue) -> IntValue {
+        let max = self.i32_type.const_int(FRAMES_MAX as u64, false);
+        let in_range = self.builder.build_int_compare(inkwell::IntPredicate::SLT, i, max, "in_range");
+        self.builder.build_select(in_range, i, max, "idx").into_int_value()
+    }
+
+    /// Function attribute like `noinline`
+    fn enum_attribute(&self, name: &str) -> Attribute {
+        self.context.create_enum_attribute(Attribute::get_named_enum_kind_id(name), 0)
+    }
+
+    fn jmp_buf_ptr(&self) -> PointerValue {
+        let buf = self.module.get_global("shiika_jmp_buf").unwrap().as_pointer_value();
+        self.builder.build_bitcast(buf, str_type(), "jmp_buf").into_pointer_value()
+    }
+}
+
+/// A value of `llvm_type` which is never read
+pub(super) fn undef_of(llvm_type: BasicTypeEnum) -> BasicValueEnum {
+    match llvm_type {
+        BasicTypeEnum::ArrayType(t) => t.get_undef().as_basic_value_enum(),
+        BasicTypeEnum::FloatType(t) => t.get_undef().as_basic_value_enum(),
+        BasicTypeEnum::IntType(t) => t.get_undef().as_basic_value_enum(),
+        BasicTypeEnum::PointerType(t) => t.get_undef().as_basic_value_enum(),
+        BasicTypeEnum::StructType(t) => t.get_undef().as_basic_value_enum(),
+        BasicTypeEnum::VectorType(t) => t.get_undef().as_basic_value_enum(),
+    }
+}
+
+/// Type of C strings (i8*)
+pub(super) fn str_type() -> PointerType {
+    IntType::i8_type().ptr_type(AddressSpace::Generic)
+}
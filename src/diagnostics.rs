@@ -0,0 +1,139 @@
+/// Diagnostics for tools (eg. `shiika check`)
+use std::path::Path;
+use serde_json::{json, Value};
+use crate::error::{Error, ErrorDetails};
+use crate::hir::Hir;
+use crate::hir::lint::{self, Lint, Warning};
+use crate::loader;
+use crate::stdlib::Stdlib;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Severity {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Diagnostic {
+    /// Kind of the problem (eg. "type_error", or the name of the lint
+    /// for warnings)
+    pub kind: &'static str,
+    pub severity: Severity,
+    pub msg: String,
+    /// The file where the problem is, if it is known and not the main file
+    pub file: Option<String>,
+    /// Line and column (0-origin), if known
+    pub location: Option<(usize, usize)>,
+    /// End of the range (0-origin, exclusive), if known
+    pub end: Option<(usize, usize)>,
+}
+
+impl Diagnostic {
+    pub fn from_error(err: &Error) -> Diagnostic {
+        let kind = match &err.details {
+            ErrorDetails::ParseError { .. } => "parse_error",
+            ErrorDetails::SyntaxError => "syntax_error",
+            ErrorDetails::TypeError => "type_error",
+            ErrorDetails::ProgramError => "program_error",
+            ErrorDetails::ToolError => "tool_error",
+            ErrorDetails::Bug => "bug",
+            ErrorDetails::Panic { .. } => "panic",
+        };
+        let (location, end) = match (&err.details, &err.locs) {
+            (ErrorDetails::ParseError { location }, _) => {
+                (Some((location.line(), location.col())), None)
+            },
+            (_, Some(locs)) => {
+                (Some((locs.begin.line(), locs.begin.col())), Some((locs.end.line(), locs.end.col())))
+            },
+            _ => (None, None),
+        };
+        let file = err.locs.as_ref().and_then(|locs| locs.file.as_ref()).map(|f| f.to_string());
+        Diagnostic { kind, severity: Severity::Error, msg: err.msg.clone(), file, location, end }
+    }
+
+    /// Create a diagnostic of a lint warning. It is an error if the lint
+    /// is in `deny`
+    pub fn from_warning(warning: &Warning, deny: &[Lint]) -> Diagnostic {
+        let severity = if deny.contains(&warning.lint) { Severity::Error } else { Severity::Warning };
+        let (location, end) = match &warning.locs {
+            Some(locs) => (Some((locs.begin.line(), locs.begin.col())), Some((locs.end.line(), locs.end.col()))),
+            None => (None, None),
+        };
+        let file = warning.locs.as_ref().and_then(|locs| locs.file.as_ref()).map(|f| f.to_string());
+        Diagnostic { kind: warning.lint.name(), severity, msg: warning.msg.clone(), file, location, end }
+    }
+
+    /// Format as `file:line:col: kind: msg` (line and column are 1-origin).
+    /// Warnings are formatted as `file:line:col: warning: msg [kind]`
+    pub fn to_text(&self, filepath: &str) -> String {
+        let filepath = self.file.as_ref().map(|s| s.as_str()).unwrap_or(filepath);
+        let body = match self.severity {
+            Severity::Error => format!("{}: {}", self.kind, self.msg),
+            Severity::Warning => format!("warning: {} [{}]", self.msg, self.kind),
+        };
+        match self.location {
+            Some((line, col)) => format!("{}:{}:{}: {}", filepath, line + 1, col + 1, body),
+            None => format!("{}: {}", filepath, body),
+        }
+    }
+
+    /// Format as a JSON object (line and column are 1-origin)
+    pub fn to_json(&self, filepath: &str) -> String {
+        self.to_json_value(filepath).to_string()
+    }
+
+    fn to_json_value(&self, filepath: &str) -> Value {
+        let filepath = self.file.as_ref().map(|s| s.as_str()).unwrap_or(filepath);
+        json!({
+            "file": filepath,
+            "line": self.location.map(|(line, _)| line + 1),
+            "column": self.location.map(|(_, col)| col + 1),
+            "kind": self.kind,
+            "severity": self.severity.name(),
+            "message": self.msg
+        })
+    }
+}
+
+/// Parse and type-check `src` without generating code, and run the lints
+pub fn check(src: &str) -> Vec<Diagnostic> {
+    check_(src, None, &[])
+}
+
+/// Same as `check` but `require`s are resolved from `filepath`. The
+/// warnings of the lints in `deny` are reported as errors
+pub fn check_file(src: &str, filepath: &Path, deny: &[Lint]) -> Vec<Diagnostic> {
+    check_(src, Some(filepath), deny)
+}
+
+fn check_(src: &str, filepath: Option<&Path>, deny: &[Lint]) -> Vec<Diagnostic> {
+    match loader::load_str(src, filepath).and_then(|ast| Hir::from_ast(ast, Stdlib::create())) {
+        Ok(hir) => lint(&hir, deny),
+        Err(err) => vec![Diagnostic::from_error(&err)],
+    }
+}
+
+/// Run the lints on `hir`
+pub fn lint(hir: &Hir, deny: &[Lint]) -> Vec<Diagnostic> {
+    lint::check(hir).iter().map(|w| Diagnostic::from_warning(w, deny)).collect()
+}
+
+/// Return true if any of the diagnostics is an error
+pub fn has_error(diagnostics: &[Diagnostic]) -> bool {
+    diagnostics.iter().any(|d| d.severity == Severity::Error)
+}
+
+/// Format diagnostics as a JSON array
+pub fn to_json_array(diagnostics: &[Diagnostic], filepath: &str) -> String {
+    Value::Array(diagnostics.iter().map(|d| d.to_json_value(filepath)).collect()).to_string()
+}
@@ -5,6 +5,8 @@ pub struct Error {
     pub msg: String,
     pub backtrace: Backtrace,
     pub details: ErrorDetails,
+    /// Range of the expression which caused this error (if known)
+    pub locs: Option<crate::ast::LocationSpan>,
 }
 #[derive(Debug)]
 pub enum ErrorDetails {
@@ -18,22 +20,49 @@ pub enum ErrorDetails {
     TypeError,
     // Syntactically correct but not a valid program (eg. "no such method")
     ProgramError,
+    // An external tool (eg. cc) is missing or failed
+    ToolError,
     // Not an user-error
     Bug,
+    // Runtime error of the program (in the interpreter). `trace` is the
+    // Shiika-level backtrace (innermost first)
+    Panic {
+        trace: Vec<String>,
+    },
 }
 
+/// Exit status of a program aborted by a panic
+pub const PANIC_EXIT_STATUS: i32 = 101;
+
 impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "{}", self.msg)
+        match &self.locs {
+            // Prefix the file name (the line and column are 1-origin)
+            Some(crate::ast::LocationSpan { file: Some(file), begin, .. }) => {
+                write!(f, "{}:{}:{}: {}", file, begin.line() + 1, begin.col() + 1, self.msg)
+            },
+            _ => write!(f, "{}", self.msg),
+        }
     }
 }
 impl std::error::Error for Error {}
 
+impl Error {
+    /// Set the range of the error unless it is already set
+    pub fn with_locs(mut self, locs: &Option<crate::ast::LocationSpan>) -> Error {
+        if self.locs.is_none() {
+            self.locs = locs.clone();
+        }
+        self
+    }
+}
+
 pub fn syntax_error(msg: &str) -> Error {
     Error {
         msg: msg.to_string(),
         backtrace: backtrace::Backtrace::new(),
         details: ErrorDetails::SyntaxError,
+        locs: None,
     }
 }
 
@@ -42,6 +71,7 @@ pub fn type_error(msg: &str) -> Error {
         msg: msg.to_string(),
         backtrace: backtrace::Backtrace::new(),
         details: ErrorDetails::TypeError,
+        locs: None,
     }
 }
 
@@ -50,5 +80,33 @@ pub fn program_error(msg: &str) -> Error {
         msg: msg.to_string(),
         backtrace: backtrace::Backtrace::new(),
         details: ErrorDetails::ProgramError,
+        locs: None,
+    }
+}
+
+pub fn tool_error(msg: &str) -> Error {
+    Error {
+        msg: msg.to_string(),
+        backtrace: backtrace::Backtrace::new(),
+        details: ErrorDetails::ToolError,
+        locs: None,
+    }
+}
+
+pub fn runtime_panic(msg: &str, trace: Vec<String>) -> Error {
+    Error {
+        msg: msg.to_string(),
+        backtrace: backtrace::Backtrace::new(),
+        details: ErrorDetails::Panic { trace },
+        locs: None,
+    }
+}
+
+pub fn bug(msg: &str) -> Error {
+    Error {
+        msg: msg.to_string(),
+        backtrace: backtrace::Backtrace::new(),
+        details: ErrorDetails::Bug,
+        locs: None,
     }
 }
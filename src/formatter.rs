@@ -0,0 +1,519 @@
+/// Source code formatter (`shiika fmt`)
+///
+/// Prints `ast::Program` back to the source in the canonical style.
+/// Comments are taken from the lexer and printed before the item which
+/// follows them (or at the end of the line if they are on the same line).
+use std::collections::HashMap;
+use crate::ast::*;
+use crate::error::Error;
+use crate::parser::Parser;
+use crate::parser::lexer::{Comment, Lexer};
+use crate::parser::token::Token;
+
+const INDENT: &str = "  ";
+
+// Operator precedences (larger binds tighter)
+const PREC_VAR: usize = 0;
+const PREC_ASSIGN: usize = 1;
+const PREC_OR: usize = 2;
+const PREC_AND: usize = 3;
+const PREC_EQUALITY: usize = 4;
+const PREC_RELATIONAL: usize = 5;
+const PREC_BITWISE_OR: usize = 6;
+const PREC_BITWISE_AND: usize = 7;
+const PREC_SHIFT: usize = 8;
+const PREC_ADDITIVE: usize = 9;
+const PREC_MULTIPLICATIVE: usize = 10;
+const PREC_UNARY: usize = 11;
+const PREC_SECONDARY: usize = 12;
+const PREC_PRIMARY: usize = 13;
+
+/// Return the formatted source
+pub fn format(src: &str) -> Result<String, Error> {
+    let (program, comments) = Parser::parse_with_comments(src)?;
+    let mut def_lines = HashMap::new();
+    assign_lines(&program.toplevel_defs, &mut scan_blocks(src).into_iter(), &mut def_lines);
+    let mut formatter = Formatter {
+        out: String::new(),
+        comments,
+        next_comment: 0,
+        def_lines,
+        last_line: None,
+    };
+    formatter.print_program(&program);
+    Ok(formatter.out)
+}
+
+/// Something printed in a block
+enum Item<'a> {
+    Require(&'a Require),
+    Def(&'a Definition),
+    Expr(&'a AstExpression),
+}
+
+struct Formatter {
+    out: String,
+    comments: Vec<Comment>,
+    /// Index of the first comment not printed yet
+    next_comment: usize,
+    /// Lines of the `class`/`def` .. `end` of each definition
+    def_lines: HashMap<*const Definition, (usize, usize)>,
+    /// Source line where the last printed item ends (None at the
+    /// beginning of a block)
+    last_line: Option<usize>,
+}
+
+impl Formatter {
+    fn print_program(&mut self, program: &Program) {
+        // Definitions and expressions are stored separately in the AST;
+        // print them in the order of the source
+        let mut items = program.requires.iter().map(Item::Require)
+            .chain(program.toplevel_defs.iter().map(Item::Def))
+            .chain(program.exprs.iter().map(Item::Expr))
+            .collect::<Vec<_>>();
+        items.sort_by_key(|item| self.lines_of(item).0);
+        self.print_items(&items, 0);
+        self.print_comments_before(usize::MAX, 0);
+    }
+
+    /// Print items of a block and the comments between them
+    fn print_items(&mut self, items: &[Item], indent: usize) {
+        let mut prev_is_def = false;
+        for item in items {
+            let (begin, end) = self.lines_of(item);
+            let is_def = match item {
+                Item::Require(_) | Item::Expr(_) => false,
+                Item::Def(Definition::ConstDefinition { .. }) => false,
+                Item::Def(Definition::PrimitiveMethodDefinition { .. }) => false,
+                Item::Def(Definition::ExternDefinition { .. }) => false,
+                Item::Def(_) => true,
+            };
+            // Always put a blank line around classes and methods
+            let force_blank = self.last_line.is_some() && (is_def || prev_is_def);
+            self.print_comments_before_(begin, indent, force_blank);
+            self.blank_line_if_needed(begin, false);
+            match item {
+                Item::Require(req) => {
+                    self.print_line(indent, &format!("require {}", str_literal(&req.path)));
+                    self.print_trailing_comment(begin);
+                },
+                Item::Def(def) => self.print_def(def, indent),
+                Item::Expr(expr) => {
+                    self.print_stmt(expr, indent);
+                    if begin == end {
+                        self.print_trailing_comment(begin);
+                    }
+                },
+            }
+            self.last_line = Some(end);
+            prev_is_def = is_def;
+        }
+    }
+
+    fn print_def(&mut self, def: &Definition, indent: usize) {
+        let (begin, end) = self.lines_of(&Item::Def(def));
+        match def {
+            Definition::ClassDefinition { name, interfaces, defs } => {
+                if interfaces.is_empty() {
+                    self.print_line(indent, &format!("class {}", name.0));
+                }
+                else {
+                    let names = interfaces.iter().map(|i| i.0.as_str()).collect::<Vec<_>>();
+                    self.print_line(indent, &format!("class {} : {}", name.0, names.join(", ")));
+                }
+                self.print_trailing_comment(begin);
+                let items = defs.iter().map(Item::Def).collect::<Vec<_>>();
+                self.print_block(&items, indent, end);
+            },
+            Definition::InterfaceDefinition { name, sigs } => {
+                self.print_line(indent, &format!("interface {}", name.0));
+                self.print_trailing_comment(begin);
+                for sig in sigs {
+                    self.print_line(indent + 1, &format!("def {}", signature_str(sig)));
+                }
+                self.close_block(indent, end);
+            },
+            Definition::InitializerDefinition { sig, body_exprs } => {
+                let params = sig.params.iter().map(|p| format!("{}: {}", p.name, p.typ)).collect::<Vec<_>>();
+                self.print_line(indent, &format!("def initialize{}", params_str(&params)));
+                self.print_trailing_comment(begin);
+                let items = body_exprs.iter().map(Item::Expr).collect::<Vec<_>>();
+                self.print_block(&items, indent, end);
+            },
+            Definition::InstanceMethodDefinition { sig, body_exprs } => {
+                self.print_line(indent, &format!("def {}", signature_str(sig)));
+                self.print_trailing_comment(begin);
+                let items = body_exprs.iter().map(Item::Expr).collect::<Vec<_>>();
+                self.print_block(&items, indent, end);
+            },
+            Definition::ClassMethodDefinition { sig, body_exprs } => {
+                self.print_line(indent, &format!("def self.{}", signature_str(sig)));
+                self.print_trailing_comment(begin);
+                let items = body_exprs.iter().map(Item::Expr).collect::<Vec<_>>();
+                self.print_block(&items, indent, end);
+            },
+            Definition::PrimitiveMethodDefinition { sig, is_class_method } => {
+                let prefix = if *is_class_method { "self." } else { "" };
+                self.print_line(indent, &format!("@primitive def {}{}", prefix, signature_str(sig)));
+                self.print_trailing_comment(begin);
+            },
+            Definition::ExternDefinition { sig } => {
+                self.print_line(indent, &format!("extern def {}", signature_str(sig)));
+                self.print_trailing_comment(begin);
+            },
+            Definition::ConstDefinition { name, expr } => {
+                self.print_line(indent, &format!("{} = {}", name.0, expr_str(expr, PREC_ASSIGN)));
+                if begin == end {
+                    self.print_trailing_comment(begin);
+                }
+            },
+        }
+    }
+
+    /// Print a statement. `if` and `while` are printed in the block form
+    fn print_stmt(&mut self, expr: &AstExpression, indent: usize) {
+        let end = expr.locs.as_ref().map(|l| l.end.line()).unwrap_or(0);
+        match &expr.body {
+            AstExpressionBody::If { cond_expr, then_expr, else_expr } => {
+                self.print_line(indent, &format!("if {}", expr_str(cond_expr, PREC_VAR)));
+                self.print_block_items(&[Item::Expr(then_expr)], indent);
+                if let Some(else_expr) = else_expr {
+                    self.print_line(indent, "else");
+                    self.print_block_items(&[Item::Expr(else_expr)], indent);
+                }
+                self.close_block(indent, end);
+            },
+            AstExpressionBody::While { cond_expr, body_exprs } => {
+                self.print_line(indent, &format!("while {}", expr_str(cond_expr, PREC_VAR)));
+                let items = body_exprs.iter().map(Item::Expr).collect::<Vec<_>>();
+                self.print_block(&items, indent, end);
+            },
+            _ => self.print_line(indent, &expr_str(expr, PREC_VAR)),
+        }
+    }
+
+    /// Print the inside of a block and its `end`
+    fn print_block(&mut self, items: &[Item], indent: usize, end_line: usize) {
+        self.print_block_items(items, indent);
+        self.close_block(indent, end_line);
+    }
+
+    fn print_block_items(&mut self, items: &[Item], indent: usize) {
+        self.last_line = None;
+        self.print_items(items, indent + 1);
+    }
+
+    /// Print the comments at the end of the block and `end`
+    fn close_block(&mut self, indent: usize, end_line: usize) {
+        self.print_comments_before(end_line, indent + 1);
+        self.print_line(indent, "end");
+        self.print_trailing_comment(end_line);
+    }
+
+    fn print_comments_before(&mut self, line: usize, indent: usize) {
+        self.print_comments_before_(line, indent, false)
+    }
+
+    /// Print the comments which appear before `line`. If `force_blank` is
+    /// true, a blank line is put before them
+    fn print_comments_before_(&mut self, line: usize, indent: usize, force_blank: bool) {
+        let mut force_blank = force_blank;
+        while let Some(comment) = self.comments.get(self.next_comment) {
+            let comment_line = comment.location.line();
+            if comment_line >= line {
+                break
+            }
+            let text = comment.text.clone();
+            self.blank_line_if_needed(comment_line, force_blank);
+            force_blank = false;
+            self.print_line(indent, &text);
+            self.last_line = Some(comment_line);
+            self.next_comment += 1;
+        }
+        if force_blank {
+            self.blank_line_if_needed(line, true);
+        }
+    }
+
+    /// Append the comment on `line` (if any) to the last printed line
+    fn print_trailing_comment(&mut self, line: usize) {
+        if let Some(comment) = self.comments.get(self.next_comment) {
+            if comment.location.line() == line {
+                self.out.pop(); // Remove the newline
+                self.out.push_str("  ");
+                self.out.push_str(&comment.text);
+                self.out.push('\n');
+                self.next_comment += 1;
+            }
+        }
+    }
+
+    /// Put a blank line if there is one in the source (only one blank
+    /// line is kept)
+    fn blank_line_if_needed(&mut self, line: usize, force: bool) {
+        if let Some(last_line) = self.last_line {
+            if (force || line > last_line + 1) && !self.out.ends_with("\n\n") {
+                self.out.push('\n');
+            }
+        }
+    }
+
+    fn print_line(&mut self, indent: usize, s: &str) {
+        self.out.push_str(&INDENT.repeat(indent));
+        self.out.push_str(s);
+        self.out.push('\n');
+    }
+
+    /// Return the first and the last line of the item in the source
+    fn lines_of(&self, item: &Item) -> (usize, usize) {
+        match item {
+            Item::Require(req) => (req.locs.begin.line(), req.locs.end.line()),
+            Item::Def(Definition::ConstDefinition { expr, .. }) => expr_lines(expr),
+            Item::Def(def) => {
+                self.def_lines.get(&(*def as *const Definition)).cloned().unwrap_or((0, 0))
+            },
+            Item::Expr(expr) => expr_lines(expr),
+        }
+    }
+}
+
+fn expr_lines(expr: &AstExpression) -> (usize, usize) {
+    match &expr.locs {
+        Some(locs) => (locs.begin.line(), locs.end.line()),
+        None => (0, 0),
+    }
+}
+
+/// Format an expression in a line. Parentheses are added if its
+/// precedence is lower than `prec`
+fn expr_str(expr: &AstExpression, prec: usize) -> String {
+    let (s, expr_prec) = match &expr.body {
+        AstExpressionBody::LogicalNot { expr: inner } => {
+            match &inner.body {
+                // `a != b` is parsed into `!(a == b)`
+                AstExpressionBody::MethodCall { receiver_expr: Some(left), method_name, arg_exprs, .. }
+                    if method_name.0 == "==" && arg_exprs.len() == 1 && !inner.primary => {
+                    (format!("{} != {}", expr_str(left, PREC_EQUALITY + 1), expr_str(&arg_exprs[0], PREC_EQUALITY + 1)),
+                     PREC_EQUALITY)
+                },
+                _ => (format!("!{}", expr_str(inner, PREC_PRIMARY)), PREC_UNARY),
+            }
+        },
+        AstExpressionBody::LogicalAnd { left, right } => {
+            (format!("{} && {}", expr_str(left, PREC_AND), expr_str(right, PREC_AND + 1)), PREC_AND)
+        },
+        AstExpressionBody::LogicalOr { left, right } => {
+            (format!("{} || {}", expr_str(left, PREC_OR), expr_str(right, PREC_OR + 1)), PREC_OR)
+        },
+        AstExpressionBody::If { cond_expr, then_expr, else_expr } => {
+            let s = match else_expr {
+                Some(else_expr) => format!("if {} then {} else {} end", expr_str(cond_expr, PREC_VAR),
+                                           expr_str(then_expr, PREC_VAR), expr_str(else_expr, PREC_VAR)),
+                None => format!("if {} then {} end", expr_str(cond_expr, PREC_VAR), expr_str(then_expr, PREC_VAR)),
+            };
+            (s, PREC_SECONDARY)
+        },
+        AstExpressionBody::While { cond_expr, body_exprs } => {
+            let body = body_exprs.iter().map(|e| expr_str(e, PREC_VAR) + "; ").collect::<String>();
+            (format!("while {}; {}end", expr_str(cond_expr, PREC_VAR), body), PREC_SECONDARY)
+        },
+        AstExpressionBody::LVarAssign { name, rhs, is_var, typ } => {
+            let annotation = match typ {
+                Some(t) => format!(": {}", t),
+                None => "".to_string(),
+            };
+            if *is_var {
+                (format!("var {}{} = {}", name, annotation, expr_str(rhs, PREC_ASSIGN)), PREC_VAR)
+            }
+            else if typ.is_some() {
+                (format!("let {}{} = {}", name, annotation, expr_str(rhs, PREC_ASSIGN)), PREC_VAR)
+            }
+            else {
+                (format!("{} = {}", name, expr_str(rhs, PREC_ASSIGN)), PREC_ASSIGN)
+            }
+        },
+        AstExpressionBody::ConstAssign { names, rhs } => {
+            (format!("{} = {}", names.join("::"), expr_str(rhs, PREC_ASSIGN)), PREC_ASSIGN)
+        },
+        AstExpressionBody::MethodCall { receiver_expr, method_name, arg_exprs, .. } => {
+            method_call_str(expr, receiver_expr, &method_name.0, arg_exprs)
+        },
+        AstExpressionBody::BareName(name) => (name.to_string(), PREC_PRIMARY),
+        AstExpressionBody::ConstRef(names) => (names.join("::"), PREC_PRIMARY),
+        AstExpressionBody::PseudoVariable(token) => {
+            let s = match token {
+                Token::KwSelf => "self",
+                Token::KwTrue => "true",
+                Token::KwFalse => "false",
+                Token::KwNil => "nil",
+                _ => panic!("[BUG] unexpected pseudo variable: {:?}", token),
+            };
+            (s.to_string(), PREC_PRIMARY)
+        },
+        AstExpressionBody::FloatLiteral { value } => {
+            let s = value.to_string();
+            (if s.contains('.') { s } else { s + ".0" }, PREC_PRIMARY)
+        },
+        AstExpressionBody::DecimalLiteral { value } => (value.to_string(), PREC_PRIMARY),
+        AstExpressionBody::StringLiteral { content } => (str_literal(content), PREC_PRIMARY),
+        AstExpressionBody::TypeTest { expr: inner, typ } => {
+            (format!("{} is {}", expr_str(inner, PREC_EQUALITY + 1), typ), PREC_EQUALITY)
+        },
+        AstExpressionBody::Cast { expr: inner, typ } => {
+            (format!("{} as {}", expr_str(inner, PREC_EQUALITY + 1), typ), PREC_EQUALITY)
+        },
+        AstExpressionBody::KeywordArg { name, expr: inner } => {
+            (format!("{}: {}", name, expr_str(inner, PREC_ASSIGN)), PREC_ASSIGN)
+        },
+    };
+    if expr_prec < prec {
+        format!("({})", s)
+    }
+    else {
+        s
+    }
+}
+
+/// Format a string as a literal
+fn str_literal(s: &str) -> String {
+    let mut lit = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => lit.push_str("\\\""),
+            '\\' => lit.push_str("\\\\"),
+            '\n' => lit.push_str("\\n"),
+            '\t' => lit.push_str("\\t"),
+            _ => lit.push(c),
+        }
+    }
+    lit.push('"');
+    lit
+}
+
+fn method_call_str(expr: &AstExpression,
+                   receiver_expr: &Option<Box<AstExpression>>,
+                   method_name: &str,
+                   arg_exprs: &[AstExpression]) -> (String, usize) {
+    if let Some(receiver) = receiver_expr {
+        // Operators (eg. `a + b`)
+        if !expr.primary {
+            if method_name == "-@" && arg_exprs.is_empty() {
+                return (format!("-{}", expr_str(receiver, PREC_SECONDARY)), PREC_UNARY)
+            }
+            if let (Some(op_prec), [arg]) = (binary_operator_prec(method_name), arg_exprs) {
+                // Operands must be of the higher precedence because
+                // the parser does not accept `a + b + c` for now
+                return (format!("{} {} {}", expr_str(receiver, op_prec + 1), method_name, expr_str(arg, op_prec + 1)),
+                        op_prec)
+            }
+        }
+    }
+    let receiver = match receiver_expr {
+        Some(receiver) => expr_str(receiver, PREC_PRIMARY) + ".",
+        None => "".to_string(),
+    };
+    let args = arg_exprs.iter().map(|arg| expr_str(arg, PREC_ASSIGN)).collect::<Vec<_>>();
+    let args = if args.is_empty() && receiver_expr.is_some() { "".to_string() }
+               else { format!("({})", args.join(", ")) };
+    (format!("{}{}{}", receiver, method_name, args), PREC_PRIMARY)
+}
+
+fn binary_operator_prec(op: &str) -> Option<usize> {
+    match op {
+        "==" => Some(PREC_EQUALITY),
+        "<" | ">" | "<=" | ">=" => Some(PREC_RELATIONAL),
+        "|" | "^" => Some(PREC_BITWISE_OR),
+        "&" => Some(PREC_BITWISE_AND),
+        "<<" | ">>" => Some(PREC_SHIFT),
+        "+" | "-" => Some(PREC_ADDITIVE),
+        "*" | "/" | "%" => Some(PREC_MULTIPLICATIVE),
+        _ => None,
+    }
+}
+
+/// eg. `foo(a: Int, b: Int = 1) -> Int`
+fn signature_str(sig: &AstMethodSignature) -> String {
+    let params = sig.params.iter().map(|p| match &p.default_expr {
+        Some(default_expr) => format!("{}: {} = {}", p.name, p.typ, expr_str(default_expr, PREC_ASSIGN)),
+        None => format!("{}: {}", p.name, p.typ),
+    }).collect::<Vec<_>>();
+    let ret = if sig.ret_typ.is_void() { "".to_string() } else { format!(" -> {}", sig.ret_typ) };
+    let typarams = sig.typarams.iter().map(|t| match &t.bound {
+        Some(bound) => format!("{} : {}", t.name, bound),
+        None => t.name.clone(),
+    }).collect::<Vec<_>>();
+    let typarams = if typarams.is_empty() { "".to_string() } else { format!("<{}>", typarams.join(", ")) };
+    format!("{}{}{}{}", sig.name.0, typarams, params_str(&params), ret)
+}
+
+fn params_str(params: &[String]) -> String {
+    if params.is_empty() { "".to_string() } else { format!("({})", params.join(", ")) }
+}
+
+/// Return the first and the last line of each `class`/`def` .. `end`
+/// in the order of appearance
+fn scan_blocks(src: &str) -> Vec<(usize, usize)> {
+    let mut lexer = Lexer::new(src);
+    let mut blocks = vec![];
+    // Index of `blocks` for each nesting (None for `if`, etc.)
+    let mut stack: Vec<Option<usize>> = vec![];
+    // True after `@primitive` or `extern`
+    let mut attributed = false;
+    // Length of `stack` in an `interface` (whose `def`s have no `end`)
+    let mut interface_depth = None;
+    loop {
+        let line = lexer.token_begin().line();
+        let token = lexer.consume_token();
+        match token {
+            Token::Eof => break,
+            // `@primitive def` and `extern def` have no `end`
+            Token::KwDef if attributed => blocks.push((line, line)),
+            Token::KwDef if interface_depth == Some(stack.len()) => (),
+            Token::KwInterface => {
+                stack.push(Some(blocks.len()));
+                blocks.push((line, line));
+                interface_depth = Some(stack.len());
+            },
+            Token::KwClass | Token::KwDef => {
+                stack.push(Some(blocks.len()));
+                blocks.push((line, line));
+            },
+            Token::KwIf | Token::KwUnless | Token::KwWhile => stack.push(None),
+            Token::KwEnd => {
+                if interface_depth == Some(stack.len()) {
+                    interface_depth = None;
+                }
+                if let Some(Some(i)) = stack.pop() {
+                    blocks[i].1 = line;
+                }
+            },
+            _ => (),
+        }
+        attributed = match token {
+            Token::At | Token::KwExtern => true,
+            Token::LowerWord(_) | Token::Space | Token::Separator => attributed,
+            _ => false,
+        };
+    }
+    blocks
+}
+
+/// Associate the lines found by `scan_blocks` to the definitions.
+/// Definitions are visited in the order of the source
+fn assign_lines(defs: &[Definition],
+                blocks: &mut dyn Iterator<Item=(usize, usize)>,
+                def_lines: &mut HashMap<*const Definition, (usize, usize)>) {
+    for def in defs {
+        match def {
+            Definition::ConstDefinition { .. } => (),
+            _ => {
+                if let Some(lines) = blocks.next() {
+                    def_lines.insert(def as *const Definition, lines);
+                }
+            }
+        }
+        if let Definition::ClassDefinition { defs: inner_defs, .. } = def {
+            assign_lines(inner_defs, blocks, def_lines);
+        }
+    }
+}
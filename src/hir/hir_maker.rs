@@ -13,6 +13,8 @@ pub struct HirMaker<'a> {
     // List of constants found so far
     pub constants: HashMap<ConstFullname, TermTy>,
     pub const_inits: Vec<HirExpression>,
+    // C functions declared with `extern def`
+    pub extern_funcs: Vec<MethodSignature>,
 }
 
 impl<'a> HirMaker<'a> {
@@ -21,24 +23,33 @@ impl<'a> HirMaker<'a> {
             index: index,
             constants: HashMap::new(),
             const_inits: vec![],
+            extern_funcs: vec![],
         }
     }
 
-    pub fn convert_program(index: index::Index, prog: ast::Program) -> Result<Hir, Error> {
+    pub fn convert_program(index: index::Index,
+                           prog: ast::Program,
+                           prelude_defs: &[ast::Definition]) -> Result<Hir, Error> {
         let mut hir_maker = HirMaker::new(&index);
 
-        let sk_methods =
+        let mut sk_methods =
             hir_maker.convert_toplevel_defs(&prog.toplevel_defs)?;
+        for (class_fullname, methods) in hir_maker.convert_prelude_defs(prelude_defs)? {
+            sk_methods.entry(class_fullname).or_insert_with(Vec::new).extend(methods);
+        }
         let mut main_exprs =
             hir_maker.convert_exprs(&mut HirMakerContext::toplevel(), &prog.exprs)?;
         match hir_maker {
-            HirMaker { index, constants, mut const_inits } => {
+            HirMaker { index, constants, mut const_inits, extern_funcs } => {
                 const_inits.append(&mut main_exprs.exprs);
                 Ok(Hir {
                     // PERF: how to avoid this clone??
                     sk_classes: index.sk_classes.clone(),
+                    sk_interfaces: index.sk_interfaces.clone(),
                     sk_methods,
                     constants,
+                    extern_funcs,
+                    const_values: HashMap::new(),
                     main_exprs:  HirExpressions {
                         ty: main_exprs.ty,
                         exprs: const_inits,
@@ -56,7 +67,7 @@ impl<'a> HirMaker<'a> {
         toplevel_defs.iter().try_for_each(|def|
             match def {
                 // Extract instance/class methods
-                ast::Definition::ClassDefinition { name, defs } => {
+                ast::Definition::ClassDefinition { name, defs, .. } => {
                     match self.convert_class_def(&name, &defs) {
                         Ok((fullname, instance_methods, meta_name, class_methods)) => {
                             sk_methods.insert(fullname, instance_methods);
@@ -70,6 +81,17 @@ impl<'a> HirMaker<'a> {
                     self.register_const(&mut ctx, name, expr)?;
                     Ok(())
                 }
+                ast::Definition::ExternDefinition { sig } => {
+                    let method = self.convert_extern_def(&sig.name)?;
+                    sk_methods.entry(ClassFullname("Object".to_string())).or_insert_with(Vec::new).push(method);
+                    Ok(())
+                }
+                // Interfaces have no method bodies (checked in hir::index)
+                ast::Definition::InterfaceDefinition { name, .. } => {
+                    let interface = self.index.find_interface(&name.to_class_fullname())
+                        .expect("[BUG] interface not indexed");
+                    interface.method_sigs.iter().try_for_each(|sig| check_signature_types(sig, self.index))
+                },
                 _ => panic!("should be checked in hir::index")
             }
         )?;
@@ -77,6 +99,35 @@ impl<'a> HirMaker<'a> {
         Ok(sk_methods)
     }
 
+    /// Convert the methods of the stdlib written in Shiika
+    fn convert_prelude_defs(&mut self, prelude_defs: &[ast::Definition])
+                           -> Result<HashMap<ClassFullname, Vec<SkMethod>>, Error> {
+        let mut sk_methods = HashMap::new();
+        for def in prelude_defs {
+            if let ast::Definition::ClassDefinition { name, defs, .. } = def {
+                let fullname = name.to_class_fullname();
+                let meta_name = ty::raw(&fullname.0).meta_ty().fullname;
+                let ctx = HirMakerContext::class_ctx(&fullname);
+                let mut instance_methods = vec![];
+                let mut class_methods = vec![];
+                for def in defs {
+                    match def {
+                        ast::Definition::InstanceMethodDefinition { sig, body_exprs, .. } => {
+                            instance_methods.push(self.convert_method_def(&ctx, &fullname, sig, &body_exprs)?);
+                        },
+                        ast::Definition::ClassMethodDefinition { sig, body_exprs, .. } => {
+                            class_methods.push(self.convert_method_def(&ctx, &meta_name, sig, &body_exprs)?);
+                        },
+                        _ => panic!("[BUG] unexpected definition in the prelude: {:?}", def),
+                    }
+                }
+                sk_methods.insert(fullname, instance_methods);
+                sk_methods.insert(meta_name, class_methods);
+            }
+        }
+        Ok(sk_methods)
+    }
+
     /// Extract instance/class methods and constants
     fn convert_class_def(&mut self, name: &ClassFirstname, defs: &Vec<ast::Definition>)
                         -> Result<(ClassFullname, Vec<SkMethod>,
@@ -96,13 +147,13 @@ impl<'a> HirMaker<'a> {
         defs.iter().try_for_each(|def| {
             match def {
                 ast::Definition::InstanceMethodDefinition { sig, body_exprs, .. } => {
-                    match self.convert_method_def(&mut ctx, &fullname, &sig.name, &body_exprs) {
+                    match self.convert_method_def(&mut ctx, &fullname, sig, &body_exprs) {
                         Ok(method) => { instance_methods.push(method); Ok(()) },
                         Err(err) => Err(err)
                     }
                 },
                 ast::Definition::ClassMethodDefinition { sig, body_exprs, .. } => {
-                    match self.convert_method_def(&mut ctx, &meta_name, &sig.name, &body_exprs) {
+                    match self.convert_method_def(&mut ctx, &meta_name, sig, &body_exprs) {
                         Ok(method) => { class_methods.push(method); Ok(()) },
                         Err(err) => Err(err)
                     }
@@ -111,6 +162,9 @@ impl<'a> HirMaker<'a> {
                     self.register_const(&mut ctx, name, expr)?;
                     Ok(())
                 }
+                ast::Definition::PrimitiveMethodDefinition { sig, .. } => {
+                    Err(error::program_error(&format!("@primitive is only allowed in the stdlib (method `{}')", sig.name)))
+                },
                 _ => Ok(()),
             }
         })?;
@@ -142,6 +196,7 @@ impl<'a> HirMaker<'a> {
         SkMethod {
             signature: signature_of_new(&meta_name, &instance_ty),
             body: SkMethodBody::RustClosureMethodBody {
+                #[cfg(feature = "codegen")]
                 boxed_gen: Box::new(move |code_gen, _| {
                     let addr = code_gen.allocate_sk_obj(&class_fullname);
                     code_gen.builder.build_return(Some(&addr));
@@ -151,6 +206,40 @@ impl<'a> HirMaker<'a> {
         }
     }
 
+    /// Create a method of Object which calls the C function
+    fn convert_extern_def(&mut self, name: &MethodFirstname) -> Result<SkMethod, Error> {
+        let signature = self.index.find_method(&ClassFullname("Object".to_string()), name)
+            .expect("[BUG] extern function not indexed").clone();
+        check_signature_types(&signature, self.index)?;
+        self.extern_funcs.push(signature.clone());
+        #[cfg(feature = "codegen")]
+        let c_name = name.0.clone();
+        #[cfg(feature = "codegen")]
+        let returns_void = signature.ret_ty.is_void_type();
+        Ok(SkMethod {
+            signature,
+            body: SkMethodBody::RustClosureMethodBody {
+                #[cfg(feature = "codegen")]
+                boxed_gen: Box::new(move |code_gen, function| {
+                    let func = code_gen.module.get_function(&c_name)
+                        .unwrap_or_else(|| panic!("[BUG] extern function {} not declared", c_name));
+                    // Skip `self`
+                    let args = function.get_params()[1..].to_vec();
+                    let result = code_gen.builder.build_call(func, &args, "result");
+                    if returns_void {
+                        code_gen.builder.build_return(None);
+                    }
+                    else {
+                        let value = result.try_as_basic_value().left()
+                            .unwrap_or_else(|| panic!("[BUG] extern function {} returned void", c_name));
+                        code_gen.builder.build_return(Some(&value));
+                    }
+                    Ok(())
+                })
+            }
+        })
+    }
+
     /// Register a constant
     fn register_const(&mut self,
                       ctx: &mut HirMakerContext,
@@ -169,21 +258,93 @@ impl<'a> HirMaker<'a> {
     fn convert_method_def(&mut self,
                           ctx: &HirMakerContext,
                           class_fullname: &ClassFullname,
-                          name: &MethodFirstname,
+                          sig: &ast::AstMethodSignature,
                           body_exprs: &Vec<AstExpression>) -> Result<SkMethod, Error> {
         // MethodSignature is built beforehand by index::new
-        let err = format!("[BUG] signature not found ({}/{}/{:?})", class_fullname, name, self.index);
-        let signature = self.index.find_method(class_fullname, name).expect(&err).clone();
+        let err = format!("[BUG] signature not found ({}/{}/{:?})", class_fullname, sig.name, self.index);
+        let signature = self.index.find_method(class_fullname, &sig.name).expect(&err).clone();
+        type_checking::check_typaram_usage(&signature)?;
+        for typaram in &signature.typarams {
+            if let Some(bound) = typaram.typaram_bound() {
+                if self.index.find_interface(&bound.fullname).is_none() {
+                    return Err(error::type_error(&format!(
+                        "bound of {} must be an interface but got {}", typaram.fullname, bound.fullname)))
+                }
+            }
+        }
+        check_signature_types(&signature, self.index)?;
 
         let mut method_ctx = HirMakerContext::method_ctx(ctx, &signature);
-        let body_exprs = self.convert_exprs(&mut method_ctx, body_exprs)?;
+        let mut prologue = vec![];
+        for (idx, param) in sig.params.iter().enumerate() {
+            if let Some(default_expr) = &param.default_expr {
+                prologue.push(self.convert_default_arg(&mut method_ctx, &signature, idx, default_expr)?);
+            }
+        }
+        let mut body_exprs = self.convert_exprs(&mut method_ctx, body_exprs)?;
+        if let Some(last) = body_exprs.exprs.pop() {
+            let last = self.upcast(&method_ctx, last, &signature.ret_ty);
+            body_exprs.ty = last.ty.clone();
+            body_exprs.exprs.push(last);
+        }
         type_checking::check_return_value(&signature, &body_exprs.ty)?;
+        if let Some(last) = body_exprs.exprs.pop() {
+            body_exprs.exprs.push(Hir::coerce(last, &signature.ret_ty));
+            if signature.ret_ty.is_nilable() {
+                body_exprs.ty = signature.ret_ty.clone();
+            }
+        }
+
+        prologue.append(&mut body_exprs.exprs);
+        body_exprs.exprs = prologue;
 
         let body = SkMethodBody::ShiikaMethodBody { exprs: body_exprs };
 
         Ok(SkMethod { signature, body })
     }
 
+    /// Convert the default value of the `idx`-th param into
+    /// `x = if <given> then <the argument> else <the default value> end`,
+    /// which is put at the beginning of the method (`x` in the body refers
+    /// to this variable)
+    fn convert_default_arg(&mut self,
+                           ctx: &mut HirMakerContext,
+                           signature: &MethodSignature,
+                           idx: usize,
+                           default_expr: &AstExpression) -> Result<HirExpression, Error> {
+        let param = &signature.params[idx];
+        if param.ty.contains_typaram() {
+            return Err(error::type_error(&format!(
+                "param `{}' of {} cannot have a default value because its type has a type parameter",
+                param.name, signature.fullname)))
+        }
+        let default_hir = self.convert_expr(ctx, default_expr)?;
+        // The arguments for the later params may be omitted
+        if let Some(later) = signature.params[idx+1..].iter().enumerate()
+            .find(|(i, later)| later.has_default && refers_arg(&default_hir, idx + 1 + i))
+            .map(|(_, later)| later) {
+            return Err(error::program_error(&format!(
+                "default value of `{}' cannot refer to `{}' which comes after it", param.name, later.name)))
+        }
+        let default_hir = self.upcast(ctx, default_hir, &param.ty);
+        type_checking::check_default_value(signature, param, &default_hir.ty)?;
+        let default_hir = Hir::coerce(default_hir, &param.ty);
+
+        let given_idx = signature.given_flag_idx(idx).expect("[BUG] param has no default value");
+        let value = Hir::if_expression(param.ty.clone(),
+                                       Hir::hir_arg_ref(ty::raw("Bool"), given_idx),
+                                       Hir::hir_arg_ref(param.ty.clone(), idx),
+                                       default_hir);
+        ctx.lvars.insert(param.name.clone(), CtxLVar {
+            name: param.name.clone(),
+            ty: param.ty.clone(),
+            readonly: true,
+        });
+        let mut assign = Hir::assign_lvar(&param.name, value);
+        assign.locs = default_expr.locs.clone();
+        Ok(assign)
+    }
+
     fn convert_exprs(&mut self,
                      ctx: &mut HirMakerContext,
                      exprs: &Vec<AstExpression>) -> Result<HirExpressions, Error> {
@@ -202,6 +363,15 @@ impl<'a> HirMaker<'a> {
     fn convert_expr(&mut self,
                     ctx: &mut HirMakerContext,
                     expr: &AstExpression) -> Result<HirExpression, Error> {
+        let mut hir_expr = self.convert_expr_body(ctx, expr)
+            .map_err(|err| err.with_locs(&expr.locs))?;
+        hir_expr.locs = expr.locs.clone();
+        Ok(hir_expr)
+    }
+
+    fn convert_expr_body(&mut self,
+                         ctx: &mut HirMakerContext,
+                         expr: &AstExpression) -> Result<HirExpression, Error> {
         match &expr.body {
             AstExpressionBody::If { cond_expr, then_expr, else_expr } => {
                 self.convert_if_expr(ctx, cond_expr, then_expr, else_expr)
@@ -211,8 +381,8 @@ impl<'a> HirMaker<'a> {
                 self.convert_while_expr(ctx, cond_expr, body_exprs)
             },
 
-            AstExpressionBody::LVarAssign { name, rhs, is_var } => {
-                self.convert_lvar_assign(ctx, name, &*rhs, is_var)
+            AstExpressionBody::LVarAssign { name, rhs, is_var, typ } => {
+                self.convert_lvar_assign(ctx, name, &*rhs, is_var, typ)
             }
 
             AstExpressionBody::ConstAssign { names, rhs } => {
@@ -243,6 +413,26 @@ impl<'a> HirMaker<'a> {
                 Ok(Hir::decimal_literal(*value))
             },
 
+            AstExpressionBody::StringLiteral {content} => {
+                Ok(Hir::string_literal(content.clone()))
+            },
+
+            AstExpressionBody::TypeTest { expr, typ } => {
+                self.convert_type_test(ctx, expr, typ)
+            },
+
+            AstExpressionBody::Cast { expr, typ } => {
+                self.convert_cast(ctx, expr, typ)
+            },
+
+            AstExpressionBody::LogicalNot { expr } => {
+                self.convert_logical_not(ctx, expr)
+            },
+
+            AstExpressionBody::KeywordArg { name, .. } => {
+                Err(error::syntax_error(&format!("unexpected keyword argument `{}'", name)))
+            },
+
             x => panic!("TODO: {:?}", x)
         }
     }
@@ -255,19 +445,120 @@ impl<'a> HirMaker<'a> {
         let cond_hir = self.convert_expr(ctx, cond_expr)?;
         type_checking::check_condition_ty(&cond_hir.ty, "if")?;
 
-        let then_hir = self.convert_expr(ctx, then_expr)?;
+        let (then_narrowed, else_narrowed) = narrowings(ctx, &cond_hir);
+        let then_hir = self.convert_narrowed_expr(ctx, then_expr, then_narrowed)?;
         let else_hir = match else_expr {
-            Some(expr) => self.convert_expr(ctx, expr)?,
+            Some(expr) => self.convert_narrowed_expr(ctx, expr, else_narrowed)?,
             None => Hir::nop(),
         };
-        // TODO: then and else must have conpatible type
+        // An `if` without `else` has no value
+        let ty = match else_expr {
+            Some(_) => if_expr_ty(&then_hir.ty, &else_hir.ty),
+            None => ty::raw("Void"),
+        };
+        let (then_hir, else_hir) = match else_expr {
+            Some(_) => (Hir::coerce(then_hir, &ty), Hir::coerce(else_hir, &ty)),
+            None => (then_hir, else_hir),
+        };
         Ok(Hir::if_expression(
-                then_hir.ty.clone(),
+                ty,
                 cond_hir,
                 then_hir,
                 else_hir))
     }
 
+    /// Convert `expr` where the type of a variable is narrowed (eg. to `Int`
+    /// from `Int?`)
+    fn convert_narrowed_expr(&mut self,
+                             ctx: &mut HirMakerContext,
+                             expr: &AstExpression,
+                             narrowed: Option<(String, TermTy)>) -> Result<HirExpression, Error> {
+        let n_narrowed = ctx.narrowed.len();
+        if let Some(narrowed) = narrowed {
+            ctx.narrowed.push(narrowed);
+        }
+        let result = self.convert_expr(ctx, expr);
+        ctx.narrowed.truncate(n_narrowed);
+        result
+    }
+
+    /// `!expr` (and `a != b`, which is parsed as `!(a == b)`).
+    /// Nil checks are negated in place so that they still narrow the type
+    fn convert_logical_not(&mut self,
+                           ctx: &mut HirMakerContext,
+                           expr: &AstExpression) -> Result<HirExpression, Error> {
+        let hir_expr = self.convert_expr(ctx, expr)?;
+        type_checking::check_logical_operator_ty(&hir_expr.ty, "!")?;
+        match hir_expr.node {
+            HirExpressionBase::HirIsNil { expr, negated } => Ok(Hir::is_nil(*expr, !negated)),
+            // if expr then false else true
+            _ => Ok(Hir::if_expression(ty::raw("Bool"),
+                                       hir_expr,
+                                       Hir::boolean_literal(false),
+                                       Hir::boolean_literal(true))),
+        }
+    }
+
+    fn convert_type_test(&mut self,
+                         ctx: &mut HirMakerContext,
+                         expr: &AstExpression,
+                         typ: &Typ) -> Result<HirExpression, Error> {
+        let hir_expr = self.convert_expr(ctx, expr)?;
+        let tested_ty = convert_typ(typ, self.index)?;
+        type_checking::check_type_test(&hir_expr.ty, &tested_ty)?;
+        Ok(Hir::type_test(hir_expr, tested_ty))
+    }
+
+    /// `expr as T`. Upcasts are done statically and downcasts are checked
+    /// at runtime
+    fn convert_cast(&mut self,
+                    ctx: &mut HirMakerContext,
+                    expr: &AstExpression,
+                    typ: &Typ) -> Result<HirExpression, Error> {
+        let hir_expr = self.convert_expr(ctx, expr)?;
+        let target_ty = convert_typ(typ, self.index)?;
+        let hir_expr = self.upcast(ctx, hir_expr, &target_ty);
+        if hir_expr.ty.conforms_to(&target_ty) {
+            Ok(Hir::coerce(hir_expr, &target_ty))
+        }
+        else {
+            self.make_downcast(hir_expr, &target_ty)
+        }
+    }
+
+    /// Take out the value of `target_ty` from an optional, a union or
+    /// an interface. Values of other types (eg. `Object`) do not know
+    /// their class at runtime and cannot be downcasted
+    fn make_downcast(&self, expr: HirExpression, target_ty: &TermTy) -> Result<HirExpression, Error> {
+        let expr_ty = expr.ty.clone();
+        if let Some(base) = expr_ty.opt_base() {
+            if !target_ty.is_nilable() {
+                let unwrapped = Hir::opt_unwrap(expr);
+                return if base.equals_to(target_ty) {
+                    Ok(unwrapped)
+                }
+                else {
+                    self.make_downcast(unwrapped, target_ty)
+                }
+            }
+        }
+        let castable = if expr_ty.union_members().is_some() {
+            !target_ty.is_nilable() && target_ty.conforms_to(&expr_ty)
+        }
+        else if expr_ty.body == TyBody::TyRaw && self.index.find_interface(&expr_ty.fullname).is_some() {
+            self.implements(target_ty, &expr_ty.fullname)
+        }
+        else {
+            false
+        };
+        if castable {
+            Ok(Hir::downcast(expr, target_ty.clone()))
+        }
+        else {
+            Err(error::type_error(&format!("cannot cast {} to {}", expr_ty.fullname, target_ty.fullname)))
+        }
+    }
+
     fn convert_while_expr(&mut self,
                           ctx: &mut HirMakerContext,
                           cond_expr: &AstExpression,
@@ -283,26 +574,37 @@ impl<'a> HirMaker<'a> {
                             ctx: &mut HirMakerContext,
                             name: &str,
                             rhs: &AstExpression,
-                            is_var: &bool) -> Result<HirExpression, Error> {
-        let expr = self.convert_expr(ctx, rhs)?;
+                            is_var: &bool,
+                            typ: &Option<Typ>) -> Result<HirExpression, Error> {
+        let mut expr = self.convert_expr(ctx, rhs)?;
         match ctx.lvars.get(name) {
             Some(lvar) => {
                 // Reassigning
-                if lvar.readonly {
+                if *is_var || typ.is_some() {
+                    return Err(error::program_error(&format!("variable `{}' already exists", name)))
+                }
+                else if lvar.readonly {
                     return Err(error::program_error(&format!(
                       "cannot reassign to {} (Hint: declare it with `var')", name)))
                 }
                 else {
-                    if *is_var {
-                        return Err(error::program_error(&format!("variable `{}' already exists", name)))
-                    }
-                    else {
-                        type_checking::check_reassign_var(&lvar.ty, &expr.ty, name)?;
-                    }
+                    let expr = self.upcast(ctx, expr, &lvar.ty);
+                    type_checking::check_reassign_var(&lvar.ty, &expr.ty, name)?;
+                    let lvar_ty = lvar.ty.clone();
+                    return Ok(Hir::assign_lvar(name, Hir::coerce(expr, &lvar_ty)))
                 }
             },
             None => {
-                // Newly introduced lvar
+                // The annotated type (if any) is the type of the variable
+                if let Some(t) = typ {
+                    let declared_ty = convert_typ(t, self.index)?;
+                    let upcasted = self.upcast(ctx, expr, &declared_ty);
+                    type_checking::check_lvar_annotation(&declared_ty, &upcasted.ty, name)?;
+                    expr = Hir::coerce(upcasted, &declared_ty);
+                    expr.ty = declared_ty;
+                }
+                // Newly introduced lvar (which may shadow a narrowed parameter)
+                ctx.narrowed.retain(|(x, _)| x != name);
                 ctx.lvars.insert(name.to_string(), CtxLVar {
                     name: name.to_string(),
                     ty: expr.ty.clone(),
@@ -334,20 +636,267 @@ impl<'a> HirMaker<'a> {
                 // Implicit self
                 _ => self.convert_self_expr(ctx)?,
             };
-        // TODO: arg types must match with method signature
-        let arg_hirs = arg_exprs.iter().map(|arg_expr| self.convert_expr(ctx, arg_expr)).collect::<Result<Vec<_>,_>>()?;
+        let mut arg_hirs = vec![];
+        let mut kwarg_hirs = vec![];
+        for arg_expr in arg_exprs {
+            match &arg_expr.body {
+                AstExpressionBody::KeywordArg { name, expr } => {
+                    kwarg_hirs.push((name.clone(), self.convert_expr(ctx, expr)?));
+                },
+                _ => {
+                    if !kwarg_hirs.is_empty() {
+                        return Err(error::syntax_error("positional argument after keyword arguments")
+                                   .with_locs(&arg_expr.locs))
+                    }
+                    arg_hirs.push(self.convert_expr(ctx, arg_expr)?);
+                },
+            }
+        }
 
-        self.make_method_call(receiver_hir, &method_name, arg_hirs)
+        self.make_method_call(ctx, receiver_hir, &method_name, arg_hirs, kwarg_hirs)
     }
 
-    fn make_method_call(&self, receiver_hir: HirExpression, method_name: &MethodFirstname, arg_hirs: Vec<HirExpression>) -> Result<HirExpression, Error> {
+    fn make_method_call(&self,
+                        ctx: &HirMakerContext,
+                        receiver_hir: HirExpression,
+                        method_name: &MethodFirstname,
+                        arg_hirs: Vec<HirExpression>,
+                        kwarg_hirs: Vec<(String, HirExpression)>) -> Result<HirExpression, Error> {
+        if receiver_hir.ty.is_nilable() {
+            return self.make_nil_check(receiver_hir, method_name, arg_hirs, kwarg_hirs)
+        }
+        // Methods of the bound can be called on a bounded type parameter
+        let receiver_hir = match receiver_hir.ty.typaram_bound().cloned() {
+            Some(bound) => self.typaram_upcast(ctx, receiver_hir, &bound),
+            None => receiver_hir,
+        };
+        // Methods of any of the members can be called on an intersection
+        let receiver_hir = match &receiver_hir.ty.body {
+            TyBody::TyIntersection { members } => {
+                let member = members.iter()
+                    .find(|m| self.has_method(m, method_name))
+                    .ok_or_else(|| error::program_error(&format!(
+                        "method {:?} not found on {:?}", method_name, receiver_hir.ty.fullname)))?
+                    .clone();
+                Hir::intersection_cast(receiver_hir, &member)
+            },
+            _ => receiver_hir,
+        };
         let class_fullname = &receiver_hir.ty.fullname;
-        let sig = self.lookup_method(class_fullname, class_fullname, method_name)?;
+        let sig = match &receiver_hir.ty.body {
+            TyBody::TyUnion { .. } => {
+                return Err(error::type_error(&format!(
+                    "cannot call `{}' on {} (narrow it with `is' first)",
+                    method_name, class_fullname)))
+            },
+            TyBody::TyParam { .. } => {
+                return Err(error::type_error(&format!(
+                    "cannot call `{}' on type parameter {}", method_name, class_fullname)))
+            },
+            _ => match self.index.find_interface(class_fullname) {
+                Some(interface) => interface.find_method(method_name).ok_or_else(||
+                    error::program_error(&format!(
+                        "method {:?} not found on {:?}", method_name, class_fullname)))?,
+                None => self.lookup_method(class_fullname, class_fullname, method_name)?,
+            },
+        };
 
-        let param_tys = arg_hirs.iter().map(|expr| &expr.ty).collect();
+        let arg_hirs = self.arrange_args(sig, arg_hirs, kwarg_hirs)?;
+        let given = arg_hirs.iter().map(|arg_hir| arg_hir.is_some()).collect::<Vec<_>>();
+        let arg_hirs = arg_hirs.into_iter().zip(sig.params.iter()).map(|(arg_hir, param)|
+            match arg_hir {
+                Some(arg_hir) => self.upcast(ctx, arg_hir, &param.ty),
+                None => Hir::omitted_arg(&param.ty),
+            }
+        ).collect::<Vec<_>>();
+        let param_tys = arg_hirs.iter().map(|expr| &expr.ty).collect::<Vec<_>>();
+        // Replace the type parameters with the inferred types
+        let generic_sig = sig;
+        let (sig, inferred) = if generic_sig.typarams.is_empty() {
+            (generic_sig.clone(), HashMap::new())
+        }
+        else {
+            type_checking::instantiate(generic_sig, &param_tys)?
+        };
         type_checking::check_method_args(&sig, &param_tys)?;
 
-        Ok(Hir::method_call(sig.ret_ty.clone(), receiver_hir, sig.fullname.clone(), arg_hirs))
+        let mut arg_hirs = arg_hirs.into_iter().zip(sig.params.iter().zip(generic_sig.params.iter()))
+            .map(|(arg_hir, (param, generic_param))| {
+                let arg_hir = Hir::coerce(arg_hir, &param.ty);
+                if generic_param.ty.contains_typaram() {
+                    Hir::generic_wrap(arg_hir, &generic_param.ty)
+                }
+                else {
+                    arg_hir
+                }
+            })
+            .collect::<Vec<_>>();
+        // Pass the type tags of the bounded type parameters
+        for typaram in &generic_sig.typarams {
+            if let Some(bound) = typaram.typaram_bound() {
+                let inferred_ty = inferred.get(&typaram.fullname.0).ok_or_else(|| error::type_error(&format!(
+                    "cannot infer type parameter {} of {}", typaram.fullname, generic_sig.fullname)))?;
+                arg_hirs.push(self.bound_tag(ctx, generic_sig, typaram, bound, inferred_ty)?);
+            }
+        }
+        // Tell whether the arguments for the default values are given
+        for (param, given) in generic_sig.params.iter().zip(given.iter()) {
+            if param.has_default {
+                arg_hirs.push(Hir::boolean_literal(*given));
+            }
+        }
+        let call = Hir::method_call(generic_sig.ret_ty.clone(), receiver_hir, sig.fullname.clone(), arg_hirs);
+        if generic_sig.ret_ty.contains_typaram() {
+            Ok(Hir::generic_unwrap(call, &sig.ret_ty))
+        }
+        else {
+            Ok(call)
+        }
+    }
+
+    /// Put the positional and keyword arguments in the order of the
+    /// params. None for the omitted ones (which have default values)
+    fn arrange_args(&self,
+                    sig: &MethodSignature,
+                    arg_hirs: Vec<HirExpression>,
+                    kwarg_hirs: Vec<(String, HirExpression)>) -> Result<Vec<Option<HirExpression>>, Error> {
+        if arg_hirs.len() > sig.params.len() {
+            return Err(error::type_error(&format!(
+                "{} takes {} args but got {}", sig.fullname, sig.params.len(), arg_hirs.len() + kwarg_hirs.len())))
+        }
+        let n_positional = arg_hirs.len();
+        let mut args = arg_hirs.into_iter().map(Some).collect::<Vec<_>>();
+        args.extend(sig.params[n_positional..].iter().map(|_| None));
+        for (name, kwarg_hir) in kwarg_hirs {
+            let (idx, _) = sig.find_param(&name).ok_or_else(|| error::type_error(&format!(
+                "{} has no parameter named `{}'", sig.fullname, name)))?;
+            if args[idx].is_some() {
+                return Err(error::type_error(&format!(
+                    "argument `{}' of {} is given more than once", name, sig.fullname)))
+            }
+            args[idx] = Some(kwarg_hir);
+        }
+        if let Some(param) = sig.params.iter().zip(args.iter())
+            .find(|(param, arg)| arg.is_none() && !param.has_default).map(|(param, _)| param) {
+            return Err(error::type_error(&format!(
+                "missing argument `{}' of {}", param.name, sig.fullname)))
+        }
+        Ok(args)
+    }
+
+    /// Check `inferred_ty` satisfies the bound of `typaram` and return
+    /// its type tag
+    fn bound_tag(&self,
+                 ctx: &HirMakerContext,
+                 sig: &MethodSignature,
+                 typaram: &TermTy,
+                 bound: &TermTy,
+                 inferred_ty: &TermTy) -> Result<HirExpression, Error> {
+        match inferred_ty.typaram_bound() {
+            // A type parameter of the caller with the same bound
+            Some(b) if b.equals_to(bound) => Ok(self.typaram_tag(ctx, inferred_ty)),
+            _ if self.implements(inferred_ty, &bound.fullname) => Ok(Hir::type_tag(inferred_ty)),
+            _ => Err(error::type_error(&format!(
+                "{} of {} must implement {} but got {}",
+                typaram.fullname, sig.fullname, bound.fullname, inferred_ty.fullname))),
+        }
+    }
+
+    /// Convert a value of a bounded type parameter to its bound
+    fn typaram_upcast(&self, ctx: &HirMakerContext, expr: HirExpression, bound: &TermTy) -> HirExpression {
+        let tag = self.typaram_tag(ctx, &expr.ty);
+        Hir::typaram_upcast(expr, tag, bound)
+    }
+
+    /// Reference to the hidden parameter which has the type tag of the
+    /// bounded type parameter `typaram`
+    fn typaram_tag(&self, ctx: &HirMakerContext, typaram: &TermTy) -> HirExpression {
+        let sig = ctx.method_sig.as_ref().expect("[BUG] type parameter out of a method");
+        let idx = sig.tag_param_idx(&typaram.fullname.0).expect("[BUG] not a bounded type parameter");
+        Hir::hir_arg_ref(ty::raw("Int"), idx)
+    }
+
+    /// Convert `expr` to the interface type `ty` if its class implements it
+    /// (or it is a type parameter bounded by `ty`)
+    fn upcast(&self, ctx: &HirMakerContext, expr: HirExpression, ty: &TermTy) -> HirExpression {
+        if expr.ty.typaram_bound().map(|b| b.equals_to(ty)).unwrap_or(false) {
+            return self.typaram_upcast(ctx, expr, ty)
+        }
+        if expr.ty.equals_to(ty) {
+            return expr
+        }
+        // Pass `A & B` as the member which conforms to `ty`
+        if let TyBody::TyIntersection { members } = &expr.ty.body {
+            if let TyBody::TyRaw = ty.body {
+                if let Some(member) = members.iter().find(|m| m.conforms_to(ty)).cloned() {
+                    return self.upcast(ctx, Hir::intersection_cast(expr, &member), ty)
+                }
+            }
+        }
+        if let TyBody::TyIntersection { .. } = ty.body {
+            if expr.ty.conforms_to(ty) {
+                return Hir::interface_wrap(expr, ty)
+            }
+        }
+        if ty.body == TyBody::TyRaw && !expr.ty.equals_to(ty) &&
+           self.index.find_interface(&ty.fullname).is_some() &&
+           self.implements(&expr.ty, &ty.fullname) {
+            Hir::interface_wrap(expr, ty)
+        }
+        else if *ty == ty::raw("Object") && !expr.ty.equals_to(ty) && is_object(&expr.ty) {
+            Hir::object_upcast(expr, ty)
+        }
+        else {
+            expr
+        }
+    }
+
+    /// Return true if the method `name` can be called on `ty` (a class or
+    /// an interface)
+    fn has_method(&self, ty: &TermTy, name: &MethodFirstname) -> bool {
+        match self.index.find_interface(&ty.fullname) {
+            Some(interface) => interface.find_method(name).is_some(),
+            None => {
+                let mut class_fullname = Some(&ty.fullname);
+                while let Some(fullname) = class_fullname {
+                    let class = match self.index.find_class(fullname) {
+                        Some(class) => class,
+                        None => return false,
+                    };
+                    if class.method_sigs.contains_key(name) {
+                        return true
+                    }
+                    class_fullname = class.superclass_fullname.as_ref();
+                }
+                false
+            }
+        }
+    }
+
+    /// Return true if the class of `ty` (or its superclass) implements the interface
+    fn implements(&self, ty: &TermTy, interface_fullname: &ClassFullname) -> bool {
+        ty.body == TyBody::TyRaw && self.index.implements(&ty.fullname, interface_fullname)
+    }
+
+    /// `x == nil` and `x != nil` are the only methods callable on a value
+    /// which may be nil
+    fn make_nil_check(&self,
+                      receiver_hir: HirExpression,
+                      method_name: &MethodFirstname,
+                      arg_hirs: Vec<HirExpression>,
+                      kwarg_hirs: Vec<(String, HirExpression)>) -> Result<HirExpression, Error> {
+        let negated = match method_name.0.as_str() {
+            "==" => false,
+            "!=" => true,
+            _ => return Err(error::type_error(&format!(
+                "cannot call `{}' on {} which may be nil (check it with `!= nil' first)",
+                method_name, receiver_hir.ty.fullname))),
+        };
+        if arg_hirs.len() != 1 || !kwarg_hirs.is_empty() || arg_hirs[0].ty != ty::nil() {
+            return Err(error::type_error(&format!(
+                "{} can only be compared with nil", receiver_hir.ty.fullname)))
+        }
+        Ok(Hir::is_nil(receiver_hir, negated))
     }
 
     fn lookup_method(&self, 
@@ -377,7 +926,8 @@ impl<'a> HirMaker<'a> {
                          name: &str) -> Result<HirExpression, Error> {
         // It is a local variable
         if let Some(lvar) = ctx.lvars.get(name) {
-            return Ok(Hir::lvar_ref(lvar.ty.clone(), name.to_string()))
+            let lvar_ref = Hir::lvar_ref(lvar.ty.clone(), name.to_string());
+            return Ok(narrow(ctx, name, lvar_ref, lvar.readonly))
         }
         // It is a method parameter
         let method_sig = match &ctx.method_sig {
@@ -386,7 +936,7 @@ impl<'a> HirMaker<'a> {
         };
         match &method_sig.find_param(name) {
             Some((idx, param)) => {
-                Ok(Hir::hir_arg_ref(param.ty.clone(), *idx))
+                Ok(narrow(ctx, name, Hir::hir_arg_ref(param.ty.clone(), *idx), true))
             },
             None => {
                 Err(error::program_error(&format!("variable `{}' was not found", name)))
@@ -424,6 +974,9 @@ impl<'a> HirMaker<'a> {
             Token::KwFalse => {
                 Ok(Hir::boolean_literal(false))
             },
+            Token::KwNil => {
+                Ok(Hir::nil_literal())
+            },
             _ => panic!("[BUG] not a pseudo variable token: {:?}", token)
         }
     }
@@ -432,3 +985,106 @@ impl<'a> HirMaker<'a> {
         Ok(Hir::self_expression(ctx.self_ty.clone()))
     }
 }
+
+/// Returns the variables narrowed in the then-clause and the else-clause
+/// of `if cond_hir` (eg. `x` is `Int` in the then-clause of `if x != nil`)
+fn narrowings(ctx: &HirMakerContext, cond_hir: &HirExpression)
+              -> (Option<(String, TermTy)>, Option<(String, TermTy)>) {
+    match &cond_hir.node {
+        HirExpressionBase::HirIsNil { expr, negated } => {
+            match (var_name(ctx, expr), expr.ty.opt_base()) {
+                (Some(name), Some(base)) => {
+                    let narrowed = Some((name, base.clone()));
+                    if *negated { (narrowed, None) } else { (None, narrowed) }
+                },
+                _ => (None, None),
+            }
+        },
+        HirExpressionBase::HirTypeTest { expr, tested_ty } => {
+            match (var_name(ctx, expr), expr.ty.union_members()) {
+                (Some(name), Some(members)) => {
+                    let then_narrowed = if tested_ty.conforms_to(&expr.ty) {
+                        Some((name.clone(), tested_ty.clone()))
+                    }
+                    else {
+                        None
+                    };
+                    let rest = members.iter().filter(|m| !m.conforms_to(tested_ty)).cloned().collect::<Vec<_>>();
+                    let else_narrowed = if rest.is_empty() { None } else { Some((name, ty::union(rest))) };
+                    (then_narrowed, else_narrowed)
+                },
+                _ => (None, None),
+            }
+        },
+        _ => (None, None),
+    }
+}
+
+/// Return true if a value of `ty` is an instance of some class (and
+/// therefore can be upcasted to `Object`)
+fn is_object(ty: &TermTy) -> bool {
+    match ty.body {
+        TyBody::TyRaw => !ty.is_void_type(),
+        TyBody::TyMeta { .. } | TyBody::TyClass => true,
+        _ => false,
+    }
+}
+
+/// Return the name of the variable if `expr` is a reference to it
+/// (which may be narrowed already)
+fn var_name(ctx: &HirMakerContext, expr: &HirExpression) -> Option<String> {
+    match &expr.node {
+        HirExpressionBase::HirLVarRef { name } => Some(name.clone()),
+        HirExpressionBase::HirArgRef { idx } => {
+            ctx.method_sig.as_ref().map(|sig| sig.params[*idx].name.clone())
+        },
+        HirExpressionBase::HirOptUnwrap { expr } |
+        HirExpressionBase::HirUnionCast { expr } => var_name(ctx, expr),
+        _ => None,
+    }
+}
+
+/// Convert the reference to a variable into its narrowed type.
+/// Variables which may be reassigned are never narrowed
+fn narrow(ctx: &HirMakerContext, name: &str, var_ref: HirExpression, readonly: bool) -> HirExpression {
+    if !readonly {
+        return var_ref
+    }
+    match ctx.narrowed.iter().rev().find(|(x, _)| x == name) {
+        Some(_) if var_ref.ty.opt_base().is_some() => Hir::opt_unwrap(var_ref),
+        Some((_, ty)) if var_ref.ty.union_members().is_some() => Hir::union_cast(var_ref, ty.clone()),
+        _ => var_ref,
+    }
+}
+
+/// Type of `if` with both clauses (eg. `Int?` for `Int` and nil,
+/// `Int | String` for `Int` and `String`, Void if either is Void)
+fn if_expr_ty(then_ty: &TermTy, else_ty: &TermTy) -> TermTy {
+    // The value of the other branch is discarded
+    if then_ty.is_void_type() || else_ty.is_void_type() {
+        ty::raw("Void")
+    }
+    else if *then_ty == ty::nil() {
+        ty::opt(else_ty.clone())
+    }
+    else if *else_ty == ty::nil() {
+        ty::opt(then_ty.clone())
+    }
+    else if else_ty.conforms_to(then_ty) {
+        then_ty.clone()
+    }
+    else if then_ty.conforms_to(else_ty) {
+        else_ty.clone()
+    }
+    else {
+        ty::union(vec![then_ty.clone(), else_ty.clone()])
+    }
+}
+
+/// Return true if `expr` reads the `idx`-th argument
+fn refers_arg(expr: &HirExpression, idx: usize) -> bool {
+    match expr.node {
+        HirExpressionBase::HirArgRef { idx: i } if i == idx => true,
+        _ => expr.children().into_iter().any(|child| refers_arg(child, idx)),
+    }
+}
@@ -15,6 +15,9 @@ pub struct HirMakerContext {
     pub namespace: ClassFullname,
     /// Current local variables
     pub lvars: HashMap<String, CtxLVar>,
+    /// Variables whose types are narrowed here and the types
+    /// (eg. `x` in the then-clause of `if x != nil` or `if x is Int`)
+    pub narrowed: Vec<(String, TermTy)>,
 //    // List of instance variables of the current `self`
 //    //self_ivars: HashMap<IVarName, TermTy>,
 }
@@ -27,6 +30,7 @@ impl HirMakerContext {
             self_ty: ty::raw("Object"),
             namespace: ClassFullname("".to_string()),
             lvars: HashMap::new(),
+            narrowed: vec![],
         }
     }
 
@@ -37,6 +41,7 @@ impl HirMakerContext {
             self_ty: ty::raw("Object"),
             namespace: fullname.clone(),
             lvars: HashMap::new(),
+            narrowed: vec![],
         }
     }
 
@@ -47,6 +52,7 @@ impl HirMakerContext {
             self_ty: ty::raw(&class_ctx.namespace.0),
             namespace: class_ctx.namespace.clone(),
             lvars: HashMap::new(),
+            narrowed: vec![],
         }
     }
 }
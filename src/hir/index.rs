@@ -13,17 +13,20 @@ use crate::names::*;
 
 #[derive(Debug, PartialEq)]
 pub struct Index {
-    pub sk_classes: HashMap<ClassFullname, SkClass>
+    pub sk_classes: HashMap<ClassFullname, SkClass>,
+    pub sk_interfaces: HashMap<ClassFullname, SkInterface>,
 }
 
 impl Index {
     pub fn new(stdlib_classes: HashMap<ClassFullname, SkClass>,
                toplevel_defs: &Vec<ast::Definition>) -> Result<Index, Error> {
         let mut index = Index {
-            sk_classes: HashMap::new()
+            sk_classes: HashMap::new(),
+            sk_interfaces: HashMap::new(),
         };
         index.index_stdlib(stdlib_classes);
         index.index_program(toplevel_defs)?;
+        index.check_conformance()?;
         Ok(index)
     }
 
@@ -37,6 +40,27 @@ impl Index {
         self.sk_classes.get(class_fullname)
     }
 
+    /// Find an interface
+    pub fn find_interface(&self, fullname: &ClassFullname) -> Option<&SkInterface> {
+        self.sk_interfaces.get(fullname)
+    }
+
+    /// Return true if the class or its ancestor declares to implement the
+    /// interface
+    pub fn implements(&self, class_fullname: &ClassFullname, interface_fullname: &ClassFullname) -> bool {
+        crate::hir::class_implements(&self.sk_classes, class_fullname, interface_fullname)
+    }
+
+    /// Find a method from the class and its ancestors
+    fn find_inherited_method(&self, class_fullname: &ClassFullname, method_name: &MethodFirstname) -> Option<&MethodSignature> {
+        let class = self.sk_classes.get(class_fullname)?;
+        match class.method_sigs.get(method_name) {
+            Some(sig) => Some(sig),
+            None => class.superclass_fullname.as_ref()
+                .and_then(|sup| self.find_inherited_method(sup, method_name)),
+        }
+    }
+
 //    /// Return true if there is a class of the name
 //    pub fn class_exists(&self, class_fullname: &str) -> bool {
 //        self.sk_classes.contains_key(&ClassFullname(class_fullname.to_string()))
@@ -56,11 +80,12 @@ impl Index {
     fn index_program(&mut self, toplevel_defs: &Vec<ast::Definition>) -> Result<(), Error> {
         toplevel_defs.iter().try_for_each(|def| {
             match def {
-                ast::Definition::ClassDefinition { name, defs } => {
-                    self.index_class(&name, &defs);
-                    Ok(())
+                ast::Definition::ClassDefinition { name, interfaces, defs } => {
+                    self.index_class(&name, &interfaces, &defs)
                 },
+                ast::Definition::InterfaceDefinition { name, sigs } => self.index_interface(name, sigs),
                 ast::Definition::ConstDefinition { .. } => Ok(()),
+                ast::Definition::ExternDefinition { sig } => self.index_extern(sig),
                 _ => {
                     Err(error::syntax_error(&format!("must not be toplevel: {:?}", def)))
                 }
@@ -68,8 +93,48 @@ impl Index {
         })
     }
 
-    fn index_class(&mut self, name: &ClassFirstname, defs: &Vec<ast::Definition>) {
+    /// Register a C function as a method of Object so that it can be
+    /// called from anywhere
+    fn index_extern(&mut self, sig: &ast::AstMethodSignature) -> Result<(), Error> {
+        let object = self.sk_classes.get_mut(&ClassFullname("Object".to_string()))
+            .ok_or_else(|| error::bug("class Object not found"))?;
+        if object.method_sigs.contains_key(&sig.name) {
+            return Err(error::program_error(&format!("extern function `{}' is already defined", sig.name)))
+        }
+        if let Some(param) = sig.params.iter().find(|p| p.default_expr.is_some()) {
+            return Err(error::program_error(&format!(
+                "param `{}' of extern function `{}' cannot have a default value", param.name, sig.name)))
+        }
+        let hir_sig = crate::hir::create_signature("Object".to_string(), sig);
+        object.method_sigs.insert(sig.name.clone(), hir_sig);
+        Ok(())
+    }
+
+    fn index_interface(&mut self, name: &ClassFirstname, sigs: &[ast::AstMethodSignature]) -> Result<(), Error> {
+        let fullname = name.to_class_fullname();
+        if self.sk_classes.contains_key(&fullname) || self.sk_interfaces.contains_key(&fullname) {
+            return Err(error::program_error(&format!("`{}' is already defined", fullname)))
+        }
+        let mut method_sigs: Vec<MethodSignature> = vec![];
+        for sig in sigs {
+            if method_sigs.iter().any(|s| s.first_name() == &sig.name) {
+                return Err(error::program_error(&format!("method `{}' is declared twice in {}", sig.name, fullname)))
+            }
+            if let Some(param) = sig.params.iter().find(|p| p.default_expr.is_some()) {
+                return Err(error::program_error(&format!(
+                    "param `{}' of {}#{} cannot have a default value", param.name, fullname, sig.name)))
+            }
+            method_sigs.push(crate::hir::create_signature(fullname.to_string(), sig));
+        }
+        self.sk_interfaces.insert(fullname.clone(), SkInterface { fullname, method_sigs });
+        Ok(())
+    }
+
+    fn index_class(&mut self, name: &ClassFirstname, interfaces: &[ClassFirstname], defs: &Vec<ast::Definition>) -> Result<(), Error> {
         let class_fullname = name.to_class_fullname(); // TODO: nested class
+        if self.sk_interfaces.contains_key(&class_fullname) {
+            return Err(error::program_error(&format!("`{}' is already defined", class_fullname)))
+        }
         let instance_ty = ty::raw(&class_fullname.0);
         let class_ty = instance_ty.meta_ty();
 
@@ -87,6 +152,8 @@ impl Index {
                     let hir_sig = crate::hir::create_signature(metaclass_fullname.to_string(), sig);
                     class_methods.insert(sig.name.clone(), hir_sig);
                 },
+                // Rejected in HirMaker
+                ast::Definition::PrimitiveMethodDefinition { .. } => (),
                 ast::Definition::ConstDefinition { .. } => (),
                 _ => panic!("TODO")
             }
@@ -102,12 +169,58 @@ impl Index {
                                  else { Some(ClassFullname("Object".to_string())) },
             instance_ty: instance_ty,
             method_sigs: instance_methods,
+            interfaces: interfaces.iter().map(|i| i.to_class_fullname()).collect(),
         });
         self.add_class(SkClass {
             fullname: metaclass_fullname,
             superclass_fullname: Some(ClassFullname("Object".to_string())),
             instance_ty: class_ty,
             method_sigs: class_methods,
+            interfaces: vec![],
         });
+        Ok(())
+    }
+
+    /// Check each class has all the methods of the interfaces it implements
+    fn check_conformance(&self) -> Result<(), Error> {
+        let mut class_names = self.sk_classes.keys().collect::<Vec<_>>();
+        class_names.sort_by(|a, b| a.0.cmp(&b.0));
+        for class_name in class_names {
+            let class = &self.sk_classes[class_name];
+            for interface_name in &class.interfaces {
+                let interface = self.sk_interfaces.get(interface_name).ok_or_else(||
+                    error::program_error(&format!("{} implements unknown interface `{}'",
+                                                  class.fullname, interface_name)))?;
+                for isig in &interface.method_sigs {
+                    self.check_implemented(class, isig)?;
+                }
+            }
+        }
+        Ok(())
     }
+
+    /// Check `class` has a method compatible with `isig`
+    fn check_implemented(&self, class: &SkClass, isig: &MethodSignature) -> Result<(), Error> {
+        let sig = match self.find_inherited_method(&class.fullname, isig.first_name()) {
+            Some(sig) => sig,
+            None => return Err(error::type_error(&format!(
+                "{} does not implement {}", class.fullname, isig.fullname)))
+        };
+        let params_match = sig.params.len() == isig.params.len() &&
+            sig.params.iter().zip(isig.params.iter()).all(|(p, ip)| p.ty.equals_to(&ip.ty) && !p.has_default);
+        if !params_match || !sig.ret_ty.equals_to(&isig.ret_ty) {
+            return Err(error::type_error(&format!(
+                "{} does not match {} (expected {}, got {})",
+                sig.fullname, isig.fullname, sig_str(isig), sig_str(sig))))
+        }
+        Ok(())
+    }
+}
+
+/// eg. `(Int, Int) -> Bool`, `(Int = ..) -> Bool`
+fn sig_str(sig: &MethodSignature) -> String {
+    let params = sig.params.iter().map(|p|
+        if p.has_default { format!("{} = ..", p.ty.fullname) } else { p.ty.fullname.to_string() }
+    ).collect::<Vec<_>>();
+    format!("({}) -> {}", params.join(", "), sig.ret_ty.fullname)
 }
@@ -0,0 +1,206 @@
+/// Lint passes on HIR
+///
+/// - `unused_variable`: a local variable which is never read
+/// - `unused_param`: a method parameter which is never read
+/// - `unreachable_code`: expressions after `while true` (which never
+///   finishes since there is no `break`)
+/// - `while_false`: a `while` whose body never runs
+/// - `shadowed_class`: a constant which has the same name as a class
+///
+/// Variables and parameters whose names start with `_` are not reported.
+/// Methods of the prelude are not checked.
+use std::collections::HashSet;
+use crate::ast::LocationSpan;
+use crate::hir::*;
+use crate::hir::HirExpressionBase::*;
+use crate::names::*;
+use crate::stdlib::PRELUDE_PATH;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Lint {
+    UnusedVariable,
+    UnusedParam,
+    UnreachableCode,
+    WhileFalse,
+    ShadowedClass,
+}
+
+impl Lint {
+    pub const ALL: [Lint; 5] = [
+        Lint::UnusedVariable,
+        Lint::UnusedParam,
+        Lint::UnreachableCode,
+        Lint::WhileFalse,
+        Lint::ShadowedClass,
+    ];
+
+    /// Name used in the diagnostics and the command line options
+    pub fn name(&self) -> &'static str {
+        match self {
+            Lint::UnusedVariable => "unused_variable",
+            Lint::UnusedParam => "unused_param",
+            Lint::UnreachableCode => "unreachable_code",
+            Lint::WhileFalse => "while_false",
+            Lint::ShadowedClass => "shadowed_class",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Lint> {
+        Lint::ALL.iter().find(|lint| lint.name() == name).cloned()
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Warning {
+    pub lint: Lint,
+    pub msg: String,
+    pub locs: Option<LocationSpan>,
+}
+
+/// Run all the lints on `hir`
+pub fn check(hir: &Hir) -> Vec<Warning> {
+    let mut linter = Linter { warnings: vec![], shadowed: HashSet::new() };
+    linter.check_body(hir, &hir.main_exprs, None);
+
+    let mut methods = hir.sk_methods.values().flatten().collect::<Vec<_>>();
+    methods.sort_by(|a, b| a.signature.fullname.full_name.cmp(&b.signature.fullname.full_name));
+    for method in methods {
+        if let SkMethodBody::ShiikaMethodBody { exprs } = &method.body {
+            if !exprs.exprs.iter().any(is_prelude) {
+                linter.check_body(hir, exprs, Some(&method.signature));
+            }
+        }
+    }
+    linter.warnings
+}
+
+struct Linter {
+    warnings: Vec<Warning>,
+    /// Constants already reported as `shadowed_class`
+    shadowed: HashSet<ConstFullname>,
+}
+
+impl Linter {
+    /// Check the toplevel (`sig` is None) or a method body
+    fn check_body(&mut self, hir: &Hir, body: &HirExpressions, sig: Option<&MethodSignature>) {
+        self.check_exprs(hir, body);
+
+        let mut assigned = vec![];
+        let mut read = HashSet::new();
+        let mut args = HashSet::new();
+        // Defaulted params are copied to the local variables of the same
+        // names at the beginning (see `HirMaker::convert_default_arg`).
+        // Only the default values are checked there
+        let n_prologue = sig.map(|s| s.params.iter().filter(|p| p.has_default).count()).unwrap_or(0);
+        for (i, expr) in body.exprs.iter().enumerate() {
+            match (&expr.node, i < n_prologue) {
+                (HirLVarAssign { rhs, .. }, true) => {
+                    if let HirIfExpression { else_expr, .. } = &rhs.node {
+                        collect_vars(else_expr, &mut assigned, &mut read, &mut args);
+                    }
+                },
+                _ => collect_vars(expr, &mut assigned, &mut read, &mut args),
+            }
+        }
+        let mut reported = HashSet::new();
+        for (name, locs) in assigned {
+            if !name.starts_with('_') && !read.contains(&name) && reported.insert(name.clone()) {
+                self.warn(Lint::UnusedVariable, format!("variable `{}' is never used", name), locs);
+            }
+        }
+        if let Some(sig) = sig {
+            for (idx, param) in sig.params.iter().enumerate() {
+                let used = args.contains(&idx) || (param.has_default && read.contains(&param.name));
+                if !param.name.starts_with('_') && !used {
+                    self.warn(Lint::UnusedParam,
+                              format!("parameter `{}' of {} is never used", param.name, sig.fullname),
+                              param.locs.clone());
+                }
+            }
+        }
+    }
+
+    /// Check the expressions and the ones in them
+    fn check_exprs(&mut self, hir: &Hir, exprs: &HirExpressions) {
+        for (i, expr) in exprs.exprs.iter().enumerate() {
+            self.check_expr(hir, expr);
+            if is_endless_loop(expr) {
+                if let Some(next) = exprs.exprs.get(i + 1) {
+                    self.warn(Lint::UnreachableCode,
+                              "unreachable code (the preceding `while true' never finishes)".to_string(),
+                              next.locs.clone());
+                }
+                break
+            }
+        }
+    }
+
+    fn check_expr(&mut self, hir: &Hir, expr: &HirExpression) {
+        match &expr.node {
+            HirWhileExpression { cond_expr, body_exprs } => {
+                if let HirBooleanLiteral { value: false } = cond_expr.node {
+                    self.warn(Lint::WhileFalse, "the body of `while false' never runs".to_string(),
+                              expr.locs.clone());
+                }
+                self.check_expr(hir, cond_expr);
+                self.check_exprs(hir, body_exprs);
+                return
+            },
+            HirConstAssign { fullname, rhs } => {
+                let class_fullname = ClassFullname(fullname.0.trim_start_matches("::").to_string());
+                let is_class = match rhs.node { HirClassLiteral { .. } => true, _ => false };
+                if !is_class && hir.sk_classes.contains_key(&class_fullname) &&
+                   self.shadowed.insert(fullname.clone()) {
+                    self.warn(Lint::ShadowedClass,
+                              format!("constant {} shadows the class {}", class_fullname, class_fullname),
+                              expr.locs.clone().or_else(|| rhs.locs.clone()));
+                }
+            },
+            _ => (),
+        }
+        for child in expr.children() {
+            self.check_expr(hir, child);
+        }
+    }
+
+    fn warn(&mut self, lint: Lint, msg: String, locs: Option<LocationSpan>) {
+        self.warnings.push(Warning { lint, msg, locs });
+    }
+}
+
+/// Collect the local variables assigned (with the location of the first
+/// assignment), the ones read and the indices of the arguments read
+fn collect_vars(expr: &HirExpression,
+                assigned: &mut Vec<(String, Option<LocationSpan>)>,
+                read: &mut HashSet<String>,
+                args: &mut HashSet<usize>) {
+    match &expr.node {
+        HirLVarAssign { name, .. } => assigned.push((name.clone(), expr.locs.clone())),
+        HirLVarRef { name } => { read.insert(name.clone()); },
+        HirArgRef { idx } => { args.insert(*idx); },
+        _ => (),
+    }
+    for child in expr.children() {
+        collect_vars(child, assigned, read, args);
+    }
+}
+
+/// `while true` never finishes because there is no `break`
+fn is_endless_loop(expr: &HirExpression) -> bool {
+    match &expr.node {
+        HirWhileExpression { cond_expr, .. } => {
+            match cond_expr.node {
+                HirBooleanLiteral { value: true } => true,
+                _ => false,
+            }
+        },
+        _ => false,
+    }
+}
+
+fn is_prelude(expr: &HirExpression) -> bool {
+    match &expr.locs {
+        Some(LocationSpan { file: Some(file), .. }) => &**file == PRELUDE_PATH,
+        _ => false,
+    }
+}
@@ -1,26 +1,39 @@
 mod hir_maker;
 mod hir_maker_context;
-mod index;
+pub mod index;
+pub mod lint;
+pub mod opt;
 use std::collections::HashMap;
 use crate::ast;
+use crate::ast::LocationSpan;
+use crate::error;
+use crate::error::Error;
+use crate::hir::index::Index;
 use crate::ty;
 use crate::ty::*;
 use crate::names::*;
 use crate::stdlib::Stdlib;
 
+#[derive(Debug)]
 pub struct Hir {
     pub sk_classes: HashMap<ClassFullname, SkClass>,
+    pub sk_interfaces: HashMap<ClassFullname, SkInterface>,
     pub sk_methods: HashMap<ClassFullname, Vec<SkMethod>>,
     pub constants: HashMap<ConstFullname, TermTy>,
+    /// C functions declared with `extern def` (the first name of the
+    /// signature is the name of the C function)
+    pub extern_funcs: Vec<MethodSignature>,
+    /// Constants evaluated at compile time (set by `opt::optimize`)
+    pub const_values: HashMap<ConstFullname, opt::ConstValue>,
     pub main_exprs: HirExpressions,
 }
 impl Hir {
     pub fn from_ast(ast: ast::Program, stdlib: Stdlib) -> Result<Hir, crate::error::Error> {
         let index = index::Index::new(stdlib.sk_classes, &ast.toplevel_defs)?;
-        let mut hir = hir_maker::HirMaker::convert_program(index, ast)?;
+        let mut hir = hir_maker::HirMaker::convert_program(index, ast, &stdlib.prelude_defs)?;
 
         // While stdlib classes are included in `index`,
-        // stdlib methods are not. Here we need to add them manually
+        // primitive methods are not. Here we need to add them manually
         hir.add_methods(stdlib.sk_methods);
 
         Ok(hir)
@@ -31,7 +44,9 @@ impl Hir {
     }
 
     pub fn add_methods(&mut self, sk_methods: HashMap<ClassFullname, Vec<SkMethod>>) {
-        self.sk_methods.extend(sk_methods)
+        for (class_fullname, methods) in sk_methods {
+            self.sk_methods.entry(class_fullname).or_insert_with(Vec::new).extend(methods);
+        }
     }
 }
 
@@ -41,6 +56,8 @@ pub struct SkClass {
     pub superclass_fullname: Option<ClassFullname>,
     pub instance_ty: TermTy,
     pub method_sigs: HashMap<MethodFirstname, MethodSignature>,
+    /// Interfaces this class implements
+    pub interfaces: Vec<ClassFullname>,
 }
 impl SkClass {
     pub fn class_ty(&self) -> TermTy {
@@ -48,6 +65,18 @@ impl SkClass {
     }
 }
 
+#[derive(Debug, PartialEq, Clone)]
+pub struct SkInterface {
+    pub fullname: ClassFullname,
+    /// In the order of the declaration
+    pub method_sigs: Vec<MethodSignature>,
+}
+impl SkInterface {
+    pub fn find_method(&self, name: &MethodFirstname) -> Option<&MethodSignature> {
+        self.method_sigs.iter().find(|sig| sig.first_name() == name)
+    }
+}
+
 #[derive(Debug, PartialEq)]
 pub struct SkMethod {
     pub signature: MethodSignature,
@@ -59,9 +88,11 @@ pub enum SkMethodBody {
         exprs: HirExpressions
     },
     RustMethodBody {
+        #[cfg(feature = "codegen")]
         gen: GenMethodBody
     },
     RustClosureMethodBody {
+        #[cfg(feature = "codegen")]
         boxed_gen: Box<ClosureMethodBody>
     }
 }
@@ -88,7 +119,9 @@ impl std::cmp::PartialEq for SkMethodBody {
     }
 }
 
+#[cfg(feature = "codegen")]
 pub type GenMethodBody = fn(code_gen: &crate::code_gen::CodeGen, function: &inkwell::values::FunctionValue) -> Result<(), crate::error::Error>;
+#[cfg(feature = "codegen")]
 pub type ClosureMethodBody = dyn Fn(&crate::code_gen::CodeGen, &inkwell::values::FunctionValue) -> Result<(), crate::error::Error>;
 
 #[derive(Debug, PartialEq)]
@@ -97,10 +130,57 @@ pub struct HirExpressions {
     pub exprs: Vec<HirExpression>,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug)]
 pub struct HirExpression {
     pub ty: TermTy,
     pub node: HirExpressionBase,
+    /// Range in the source (None if not created from the source)
+    pub locs: Option<LocationSpan>,
+}
+
+// Manually implemented because `locs` should not be compared
+impl PartialEq for HirExpression {
+    fn eq(&self, other: &HirExpression) -> bool {
+        self.ty == other.ty && self.node == other.node
+    }
+}
+
+impl HirExpression {
+    /// Return the direct sub-expressions
+    pub fn children(&self) -> Vec<&HirExpression> {
+        match &self.node {
+            HirExpressionBase::HirIfExpression { cond_expr, then_expr, else_expr } => {
+                vec![&**cond_expr, &**then_expr, &**else_expr]
+            },
+            HirExpressionBase::HirWhileExpression { cond_expr, body_exprs } => {
+                let mut v = vec![&**cond_expr];
+                v.extend(body_exprs.exprs.iter());
+                v
+            },
+            HirExpressionBase::HirLVarAssign { rhs, .. } => vec![&**rhs],
+            HirExpressionBase::HirConstAssign { rhs, .. } => vec![&**rhs],
+            HirExpressionBase::HirMethodCall { receiver_expr, arg_exprs, .. } => {
+                let mut v = vec![&**receiver_expr];
+                v.extend(arg_exprs.iter());
+                v
+            },
+            HirExpressionBase::HirOptWrap { expr } |
+            HirExpressionBase::HirOptUnwrap { expr } |
+            HirExpressionBase::HirIsNil { expr, .. } |
+            HirExpressionBase::HirUnionWrap { expr } |
+            HirExpressionBase::HirUnionCast { expr } |
+            HirExpressionBase::HirTypeTest { expr, .. } |
+            HirExpressionBase::HirInterfaceWrap { expr } |
+            HirExpressionBase::HirIntersectionCast { expr } |
+            HirExpressionBase::HirGenericWrap { expr } |
+            HirExpressionBase::HirGenericUnwrap { expr } |
+            HirExpressionBase::HirObjectUpcast { expr } |
+            HirExpressionBase::HirDowncast { expr } |
+            HirExpressionBase::HirReplStore { expr, .. } => vec![&**expr],
+            HirExpressionBase::HirTyParamUpcast { expr, tag } => vec![&**expr, &**tag],
+            _ => vec![],
+        }
+    }
 }
 
 #[derive(Debug, PartialEq)]
@@ -146,6 +226,89 @@ pub enum HirExpressionBase {
     HirBooleanLiteral {
         value: bool,
     },
+    HirStringLiteral {
+        value: String,
+    },
+    HirNilLiteral,
+    /// Convert a value of `T` (or nil) to `T?`
+    HirOptWrap {
+        expr: Box<HirExpression>,
+    },
+    /// Take out the value of `T?` which is known not to be nil
+    HirOptUnwrap {
+        expr: Box<HirExpression>,
+    },
+    /// `x == nil` (or `x != nil` if `negated`)
+    HirIsNil {
+        expr: Box<HirExpression>,
+        negated: bool,
+    },
+    /// Convert a value to a union type which contains its type
+    HirUnionWrap {
+        expr: Box<HirExpression>,
+    },
+    /// Take out the value of a union which is known to be of the type
+    /// of this expression (a member or a smaller union)
+    HirUnionCast {
+        expr: Box<HirExpression>,
+    },
+    /// `expr is tested_ty`
+    HirTypeTest {
+        expr: Box<HirExpression>,
+        tested_ty: TermTy,
+    },
+    /// Convert an object to an interface its class implements (or to an
+    /// intersection type it conforms to)
+    HirInterfaceWrap {
+        expr: Box<HirExpression>,
+    },
+    /// Take out a value of `A & B` as one of the members
+    HirIntersectionCast {
+        expr: Box<HirExpression>,
+    },
+    /// Convert an argument to a parameter of type `T` (or `T?`) of a
+    /// generic method
+    HirGenericWrap {
+        expr: Box<HirExpression>,
+    },
+    /// Convert the value of type `T` (or `T?`) returned from a generic
+    /// method to the inferred type
+    HirGenericUnwrap {
+        expr: Box<HirExpression>,
+    },
+    /// The type tag of `ty`, passed to a generic method as the hidden
+    /// argument for a bounded type parameter
+    HirTypeTag {
+        ty: TermTy,
+    },
+    /// Placeholder for an argument omitted at the call site (the method
+    /// evaluates the default value instead)
+    HirOmittedArg,
+    /// The value saved in the `slot`-th slot of the REPL by an earlier input
+    HirReplLoad {
+        slot: usize,
+    },
+    /// Save the value of `expr` to the `slot`-th slot of the REPL
+    HirReplStore {
+        slot: usize,
+        expr: Box<HirExpression>,
+    },
+    /// Convert a value of a bounded type parameter to its bound (an
+    /// interface). `tag` is the type tag of its class
+    HirTyParamUpcast {
+        expr: Box<HirExpression>,
+        tag: Box<HirExpression>,
+    },
+    /// Convert a value to `Object` (values which are not objects, eg.
+    /// `Int`, are boxed)
+    HirObjectUpcast {
+        expr: Box<HirExpression>,
+    },
+    /// `expr as T` where `expr` is a union, an interface or an optional.
+    /// Panics if the value is not of `T`
+    HirDowncast {
+        expr: Box<HirExpression>,
+    },
     /// A special expression that evaluates to a class
     /// (eg. `class A; end; A = 1` shadows A, but this special expr
     /// is never be shadowed)
@@ -166,7 +329,8 @@ impl Hir {
                 cond_expr: Box::new(cond_hir),
                 then_expr: Box::new(then_hir),
                 else_expr: Box::new(else_hir),
-            }
+            },
+            locs: None,
         }
     }
 
@@ -177,7 +341,8 @@ impl Hir {
             node: HirExpressionBase::HirWhileExpression {
                 cond_expr: Box::new(cond_hir),
                 body_exprs: Box::new(body_hirs),
-            }
+            },
+            locs: None,
         }
     }
 
@@ -187,7 +352,8 @@ impl Hir {
             node: HirExpressionBase::HirLVarAssign {
                 name: name.to_string(),
                 rhs: Box::new(rhs),
-            }
+            },
+            locs: None,
         }
     }
 
@@ -197,7 +363,8 @@ impl Hir {
             node: HirExpressionBase::HirConstAssign {
                 fullname: fullname,
                 rhs: Box::new(rhs),
-            }
+            },
+            locs: None,
         }
     }
 
@@ -208,7 +375,8 @@ impl Hir {
                 receiver_expr: Box::new(receiver_hir),
                 method_fullname: method_fullname,
                 arg_exprs: arg_hirs,
-            }
+            },
+            locs: None,
         }
     }
 
@@ -217,6 +385,7 @@ impl Hir {
         HirExpression {
             ty: ty,
             node: HirExpressionBase::HirArgRef { idx: idx },
+            locs: None,
         }
     }
 
@@ -224,6 +393,7 @@ impl Hir {
         HirExpression {
             ty: ty,
             node: HirExpressionBase::HirLVarRef { name },
+            locs: None,
         }
     }
 
@@ -231,6 +401,7 @@ impl Hir {
         HirExpression {
             ty: ty,
             node: HirExpressionBase::HirConstRef { fullname },
+            locs: None,
         }
     }
 
@@ -238,34 +409,201 @@ impl Hir {
         HirExpression {
             ty: ty,
             node: HirExpressionBase::HirSelfExpression,
+            locs: None,
         }
     }
 
     pub fn float_literal(value: f64) -> HirExpression {
         HirExpression {
             ty: ty::raw("Float"),
-            node: HirExpressionBase::HirFloatLiteral { value }
+            node: HirExpressionBase::HirFloatLiteral { value },
+            locs: None,
         }
     }
     
     pub fn decimal_literal(value: i32) -> HirExpression {
         HirExpression {
             ty: ty::raw("Int"),
-            node: HirExpressionBase::HirDecimalLiteral { value }
+            node: HirExpressionBase::HirDecimalLiteral { value },
+            locs: None,
         }
     }
     
     pub fn boolean_literal(value: bool) -> HirExpression {
         HirExpression {
             ty: ty::raw("Bool"),
-            node: HirExpressionBase::HirBooleanLiteral { value }
+            node: HirExpressionBase::HirBooleanLiteral { value },
+            locs: None,
+        }
+    }
+
+    pub fn string_literal(value: String) -> HirExpression {
+        HirExpression {
+            ty: ty::raw("String"),
+            node: HirExpressionBase::HirStringLiteral { value },
+            locs: None,
+        }
+    }
+
+    pub fn nil_literal() -> HirExpression {
+        HirExpression {
+            ty: ty::nil(),
+            node: HirExpressionBase::HirNilLiteral,
+            locs: None,
+        }
+    }
+
+    /// Convert `expr` to `ty` if it is needed (eg. `Int` to `Int?`)
+    pub fn coerce(expr: HirExpression, ty: &TermTy) -> HirExpression {
+        if expr.ty.equals_to(ty) {
+            return expr
+        }
+        let locs = expr.locs.clone();
+        let node = match &ty.body {
+            TyBody::TyOpt { base } => {
+                // `Int` to `(Int | String)?` is converted via `Int | String`
+                let expr = if expr.ty.is_nilable() { expr } else { Hir::coerce(expr, base) };
+                HirExpressionBase::HirOptWrap { expr: Box::new(expr) }
+            },
+            TyBody::TyUnion { .. } => HirExpressionBase::HirUnionWrap { expr: Box::new(expr) },
+            _ => return expr,
+        };
+        HirExpression { ty: ty.clone(), node, locs }
+    }
+
+    pub fn opt_unwrap(expr: HirExpression) -> HirExpression {
+        let ty = expr.ty.opt_base().expect("[BUG] opt_unwrap: not an optional").clone();
+        HirExpression {
+            ty,
+            node: HirExpressionBase::HirOptUnwrap { expr: Box::new(expr) },
+            locs: None,
+        }
+    }
+
+    pub fn union_cast(expr: HirExpression, ty: TermTy) -> HirExpression {
+        HirExpression {
+            ty,
+            node: HirExpressionBase::HirUnionCast { expr: Box::new(expr) },
+            locs: None,
+        }
+    }
+
+    pub fn interface_wrap(expr: HirExpression, interface_ty: &TermTy) -> HirExpression {
+        let locs = expr.locs.clone();
+        HirExpression {
+            ty: interface_ty.clone(),
+            node: HirExpressionBase::HirInterfaceWrap { expr: Box::new(expr) },
+            locs,
+        }
+    }
+
+    pub fn intersection_cast(expr: HirExpression, member_ty: &TermTy) -> HirExpression {
+        let locs = expr.locs.clone();
+        HirExpression {
+            ty: member_ty.clone(),
+            node: HirExpressionBase::HirIntersectionCast { expr: Box::new(expr) },
+            locs,
+        }
+    }
+
+    pub fn generic_wrap(expr: HirExpression, param_ty: &TermTy) -> HirExpression {
+        let locs = expr.locs.clone();
+        HirExpression {
+            ty: param_ty.clone(),
+            node: HirExpressionBase::HirGenericWrap { expr: Box::new(expr) },
+            locs,
+        }
+    }
+
+    pub fn generic_unwrap(expr: HirExpression, ty: &TermTy) -> HirExpression {
+        let locs = expr.locs.clone();
+        HirExpression {
+            ty: ty.clone(),
+            node: HirExpressionBase::HirGenericUnwrap { expr: Box::new(expr) },
+            locs,
+        }
+    }
+
+    pub fn type_tag(ty: &TermTy) -> HirExpression {
+        HirExpression {
+            ty: ty::raw("Int"),
+            node: HirExpressionBase::HirTypeTag { ty: ty.clone() },
+            locs: None,
+        }
+    }
+
+    pub fn omitted_arg(ty: &TermTy) -> HirExpression {
+        HirExpression {
+            ty: ty.clone(),
+            node: HirExpressionBase::HirOmittedArg,
+            locs: None,
+        }
+    }
+
+    pub fn repl_load(slot: usize, ty: TermTy) -> HirExpression {
+        HirExpression {
+            ty,
+            node: HirExpressionBase::HirReplLoad { slot },
+            locs: None,
+        }
+    }
+
+    pub fn repl_store(slot: usize, expr: HirExpression) -> HirExpression {
+        HirExpression {
+            ty: expr.ty.clone(),
+            locs: expr.locs.clone(),
+            node: HirExpressionBase::HirReplStore { slot, expr: Box::new(expr) },
+        }
+    }
+
+    pub fn typaram_upcast(expr: HirExpression, tag: HirExpression, bound: &TermTy) -> HirExpression {
+        let locs = expr.locs.clone();
+        HirExpression {
+            ty: bound.clone(),
+            node: HirExpressionBase::HirTyParamUpcast { expr: Box::new(expr), tag: Box::new(tag) },
+            locs,
+        }
+    }
+
+    pub fn object_upcast(expr: HirExpression, object_ty: &TermTy) -> HirExpression {
+        let locs = expr.locs.clone();
+        HirExpression {
+            ty: object_ty.clone(),
+            node: HirExpressionBase::HirObjectUpcast { expr: Box::new(expr) },
+            locs,
+        }
+    }
+
+    pub fn downcast(expr: HirExpression, ty: TermTy) -> HirExpression {
+        let locs = expr.locs.clone();
+        HirExpression {
+            ty,
+            node: HirExpressionBase::HirDowncast { expr: Box::new(expr) },
+            locs,
+        }
+    }
+
+    pub fn type_test(expr: HirExpression, tested_ty: TermTy) -> HirExpression {
+        HirExpression {
+            ty: ty::raw("Bool"),
+            node: HirExpressionBase::HirTypeTest { expr: Box::new(expr), tested_ty },
+            locs: None,
+        }
+    }
+
+    pub fn is_nil(expr: HirExpression, negated: bool) -> HirExpression {
+        HirExpression {
+            ty: ty::raw("Bool"),
+            node: HirExpressionBase::HirIsNil { expr: Box::new(expr), negated },
+            locs: None,
         }
     }
 
     pub fn class_literal(fullname: ClassFullname) -> HirExpression {
         HirExpression {
             ty: ty::meta(&fullname.0),
-            node: HirExpressionBase::HirClassLiteral { fullname }
+            node: HirExpressionBase::HirClassLiteral { fullname },
+            locs: None,
         }
     }
     
@@ -273,26 +611,97 @@ impl Hir {
         HirExpression {
             ty: ty::raw(" NOP "), // must not be used
             node: HirExpressionBase::HirNop,
+            locs: None,
         }
     }
 }
 
+/// Return true if the class or its ancestor declares to implement the
+/// interface
+pub fn class_implements(classes: &HashMap<ClassFullname, SkClass>,
+                        class_fullname: &ClassFullname,
+                        interface_fullname: &ClassFullname) -> bool {
+    let mut class = classes.get(class_fullname);
+    while let Some(c) = class {
+        if c.interfaces.contains(interface_fullname) {
+            return true
+        }
+        class = c.superclass_fullname.as_ref().and_then(|name| classes.get(name));
+    }
+    false
+}
+
 /// Create `hir::MethodSignature` from `ast::MethodSignature`
 pub fn create_signature(class_fullname: String, sig: &ast::AstMethodSignature) -> MethodSignature {
     let fullname = MethodFullname {
         full_name: (class_fullname + "#" + &sig.name.0),
         first_name: sig.name.clone(),
     };
-    let ret_ty = convert_typ(&sig.ret_typ);
+    let typarams = sig.typarams.iter().map(|t|
+        ty::typaram(&t.name, t.bound.as_ref().map(|bound| convert_typ_with(bound, &[])))
+    ).collect::<Vec<_>>();
+    let ret_ty = convert_typ_with(&sig.ret_typ, &typarams);
     let params = sig.params.iter().map(|param|
-        MethodParam { name: param.name.to_string(), ty: convert_typ(&param.typ) }
+        MethodParam {
+            name: param.name.to_string(),
+            ty: convert_typ_with(&param.typ, &typarams),
+            has_default: param.default_expr.is_some(),
+            locs: param.locs.clone(),
+        }
     ).collect();
 
-    MethodSignature { fullname, ret_ty, params }
+    MethodSignature { fullname, ret_ty, params, typarams }
 }
 
-fn convert_typ(typ: &ast::Typ) -> TermTy {
-    ty::raw(&typ.name)
+/// Convert a type written in a method body (eg. `x as T`). Fails if it
+/// refers to a class or an interface which does not exist
+fn convert_typ(typ: &ast::Typ, index: &Index) -> Result<TermTy, Error> {
+    let ty = convert_typ_with(typ, &[]);
+    check_ty_exists(&ty, index)?;
+    Ok(ty)
+}
+
+/// Check the classes and the interfaces `ty` refers to exist
+fn check_ty_exists(ty: &TermTy, index: &Index) -> Result<(), Error> {
+    match &ty.body {
+        TyBody::TyRaw => {
+            if index.find_class(&ty.fullname).is_none() && index.find_interface(&ty.fullname).is_none() {
+                return Err(error::type_error(&format!("unknown type `{}'", ty.fullname)))
+            }
+            Ok(())
+        },
+        TyBody::TyOpt { base } => check_ty_exists(base, index),
+        TyBody::TyUnion { members } | TyBody::TyIntersection { members } => {
+            members.iter().try_for_each(|t| check_ty_exists(t, index))
+        },
+        TyBody::TyParam { bound: Some(bound) } => check_ty_exists(bound, index),
+        _ => Ok(()),
+    }
+}
+
+/// Check the types in `sig` exist
+fn check_signature_types(sig: &MethodSignature, index: &Index) -> Result<(), Error> {
+    check_ty_exists(&sig.ret_ty, index)?;
+    sig.params.iter().try_for_each(|param| check_ty_exists(&param.ty, index))
+}
+
+/// `typarams` are the type parameters of the method
+fn convert_typ_with(typ: &ast::Typ, typarams: &[TermTy]) -> TermTy {
+    match typ {
+        ast::Typ::Name(name) => {
+            match typarams.iter().find(|t| &t.fullname.0 == name) {
+                Some(typaram) => typaram.clone(),
+                None => ty::raw(name),
+            }
+        },
+        ast::Typ::Opt(typ) => ty::opt(convert_typ_with(typ, typarams)),
+        ast::Typ::Union(members) => {
+            ty::union(members.iter().map(|t| convert_typ_with(t, typarams)).collect())
+        },
+        ast::Typ::Intersection(members) => {
+            ty::intersection(members.iter().map(|t| convert_typ_with(t, typarams)).collect())
+        },
+    }
 }
 
 /// Create a signature of `.new`
@@ -304,5 +713,6 @@ fn signature_of_new(metaclass_fullname: &ClassFullname, instance_ty: &TermTy) ->
         },
         ret_ty: instance_ty.clone(),
         params: vec![],
+        typarams: vec![],
     }
 }
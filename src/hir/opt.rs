@@ -0,0 +1,213 @@
+/// Optimization passes on HIR
+///
+/// - Calls of the stdlib primitives whose receiver and arguments are
+///   literals are folded (eg. `1 + 2` becomes `3`)
+/// - `if` with a literal condition is replaced with the taken branch
+/// - Constants with literal initializers are evaluated at compile time.
+///   Their values are stored in `Hir::const_values` and the references
+///   to them are replaced with literals
+use std::collections::HashMap;
+use crate::ast::LocationSpan;
+use crate::hir::*;
+use crate::hir::HirExpressionBase::*;
+use crate::names::*;
+use crate::stdlib::is_valid_shift_count;
+
+/// Value of a constant known at compile time
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ConstValue {
+    Int(i32),
+    Float(f64),
+    Bool(bool),
+}
+
+/// Apply the optimizations to `hir`
+pub fn optimize(hir: &mut Hir) {
+    let mut opt = Optimizer { const_values: HashMap::new() };
+
+    let exprs = std::mem::replace(&mut hir.main_exprs.exprs, vec![]);
+    let n_exprs = exprs.len();
+    for (i, expr) in exprs.into_iter().enumerate() {
+        let expr = opt.fold(expr);
+        if let HirConstAssign { fullname, rhs } = &expr.node {
+            if let Some(value) = const_value(rhs) {
+                opt.const_values.insert(fullname.clone(), value);
+                // The store is not needed unless it is the value of `main`
+                if i + 1 < n_exprs {
+                    continue
+                }
+            }
+        }
+        hir.main_exprs.exprs.push(expr);
+    }
+
+    for methods in hir.sk_methods.values_mut() {
+        for method in methods.iter_mut() {
+            if let SkMethodBody::ShiikaMethodBody { exprs } = &mut method.body {
+                let body = std::mem::replace(&mut exprs.exprs, vec![]);
+                exprs.exprs = body.into_iter().map(|expr| opt.fold(expr)).collect();
+            }
+        }
+    }
+    hir.const_values = opt.const_values;
+}
+
+struct Optimizer {
+    /// Constants whose values are known so far
+    const_values: HashMap<ConstFullname, ConstValue>,
+}
+
+impl Optimizer {
+    /// Return the optimized expression
+    fn fold(&self, expr: HirExpression) -> HirExpression {
+        let HirExpression { ty, node, locs } = expr;
+        let node = match node {
+            HirIfExpression { cond_expr, then_expr, else_expr } => {
+                let cond_expr = self.fold(*cond_expr);
+                let then_expr = self.fold(*then_expr);
+                let else_expr = self.fold(*else_expr);
+                match cond_expr.node {
+                    HirBooleanLiteral { value: true } if then_expr.ty == ty => return then_expr,
+                    HirBooleanLiteral { value: false } if else_expr.node != HirNop && else_expr.ty == ty => {
+                        return else_expr
+                    },
+                    _ => HirIfExpression {
+                        cond_expr: Box::new(cond_expr),
+                        then_expr: Box::new(then_expr),
+                        else_expr: Box::new(else_expr),
+                    },
+                }
+            },
+            HirWhileExpression { cond_expr, body_exprs } => {
+                let HirExpressions { ty: body_ty, exprs } = *body_exprs;
+                HirWhileExpression {
+                    cond_expr: Box::new(self.fold(*cond_expr)),
+                    body_exprs: Box::new(HirExpressions {
+                        ty: body_ty,
+                        exprs: exprs.into_iter().map(|expr| self.fold(expr)).collect(),
+                    }),
+                }
+            },
+            HirLVarAssign { name, rhs } => {
+                HirLVarAssign { name, rhs: Box::new(self.fold(*rhs)) }
+            },
+            HirConstAssign { fullname, rhs } => {
+                HirConstAssign { fullname, rhs: Box::new(self.fold(*rhs)) }
+            },
+            HirMethodCall { receiver_expr, method_fullname, arg_exprs } => {
+                let receiver_expr = self.fold(*receiver_expr);
+                let arg_exprs = arg_exprs.into_iter().map(|expr| self.fold(expr)).collect::<Vec<_>>();
+                let args = arg_exprs.iter().map(const_value).collect::<Option<Vec<_>>>();
+                if let (Some(receiver), Some(args)) = (const_value(&receiver_expr), args) {
+                    if let Some(value) = fold_primitive(&method_fullname.full_name, receiver, &args) {
+                        return literal(value, locs)
+                    }
+                }
+                HirMethodCall { receiver_expr: Box::new(receiver_expr), method_fullname, arg_exprs }
+            },
+            HirOptWrap { expr } => {
+                HirOptWrap { expr: Box::new(self.fold(*expr)) }
+            },
+            HirOptUnwrap { expr } => {
+                HirOptUnwrap { expr: Box::new(self.fold(*expr)) }
+            },
+            HirIsNil { expr, negated } => {
+                HirIsNil { expr: Box::new(self.fold(*expr)), negated }
+            },
+            HirUnionWrap { expr } => {
+                HirUnionWrap { expr: Box::new(self.fold(*expr)) }
+            },
+            HirUnionCast { expr } => {
+                HirUnionCast { expr: Box::new(self.fold(*expr)) }
+            },
+            HirInterfaceWrap { expr } => {
+                HirInterfaceWrap { expr: Box::new(self.fold(*expr)) }
+            },
+            HirIntersectionCast { expr } => {
+                HirIntersectionCast { expr: Box::new(self.fold(*expr)) }
+            },
+            HirGenericWrap { expr } => {
+                HirGenericWrap { expr: Box::new(self.fold(*expr)) }
+            },
+            HirGenericUnwrap { expr } => {
+                HirGenericUnwrap { expr: Box::new(self.fold(*expr)) }
+            },
+            HirTyParamUpcast { expr, tag } => {
+                HirTyParamUpcast { expr: Box::new(self.fold(*expr)), tag: Box::new(self.fold(*tag)) }
+            },
+            HirObjectUpcast { expr } => {
+                HirObjectUpcast { expr: Box::new(self.fold(*expr)) }
+            },
+            HirDowncast { expr } => {
+                HirDowncast { expr: Box::new(self.fold(*expr)) }
+            },
+            HirReplStore { slot, expr } => {
+                HirReplStore { slot, expr: Box::new(self.fold(*expr)) }
+            },
+            HirTypeTest { expr, tested_ty } => {
+                HirTypeTest { expr: Box::new(self.fold(*expr)), tested_ty }
+            },
+            HirConstRef { fullname } => {
+                match self.const_values.get(&fullname) {
+                    Some(value) => return literal(*value, locs),
+                    None => HirConstRef { fullname },
+                }
+            },
+            node => node,
+        };
+        HirExpression { ty, node, locs }
+    }
+}
+
+/// Return the value of `expr` if it is a literal
+fn const_value(expr: &HirExpression) -> Option<ConstValue> {
+    match expr.node {
+        HirDecimalLiteral { value } => Some(ConstValue::Int(value)),
+        HirFloatLiteral { value } => Some(ConstValue::Float(value)),
+        HirBooleanLiteral { value } => Some(ConstValue::Bool(value)),
+        _ => None,
+    }
+}
+
+fn literal(value: ConstValue, locs: Option<LocationSpan>) -> HirExpression {
+    let mut expr = match value {
+        ConstValue::Int(i) => Hir::decimal_literal(i),
+        ConstValue::Float(f) => Hir::float_literal(f),
+        ConstValue::Bool(b) => Hir::boolean_literal(b),
+    };
+    expr.locs = locs;
+    expr
+}
+
+/// Evaluate a call of a stdlib primitive. Returns None if the method is
+/// not a primitive, the result is not defined or the call panics (eg.
+/// shifting by 32 bits).
+/// The results must be the same as the generated code
+fn fold_primitive(method_name: &str, receiver: ConstValue, args: &[ConstValue]) -> Option<ConstValue> {
+    use self::ConstValue::*;
+    let value = match (method_name, receiver, args) {
+        ("Int#<", Int(a), [Int(b)]) => Bool(a < *b),
+        // Overflows and divisions by zero are left to panic at runtime
+        ("Int#+", Int(a), [Int(b)]) => Int(a.checked_add(*b)?),
+        ("Int#-", Int(a), [Int(b)]) => Int(a.checked_sub(*b)?),
+        ("Int#*", Int(a), [Int(b)]) => Int(a.checked_mul(*b)?),
+        ("Int#/", Int(a), [Int(b)]) => Int(a.checked_div(*b)?),
+        ("Int#%", Int(a), [Int(b)]) => Int(a.checked_rem(*b)?),
+        ("Int#&", Int(a), [Int(b)]) => Int(a & *b),
+        ("Int#|", Int(a), [Int(b)]) => Int(a | *b),
+        ("Int#^", Int(a), [Int(b)]) => Int(a ^ *b),
+        ("Int#<<", Int(a), [Int(b)]) if is_valid_shift_count(*b) => Int(a << *b),
+        ("Int#>>", Int(a), [Int(b)]) if is_valid_shift_count(*b) => Int(a >> *b),
+        ("Int#to_f", Int(a), []) => Float(a as f64),
+        ("Float#<", Float(a), [Float(b)]) => Bool(a < *b),
+        ("Float#+", Float(a), [Float(b)]) => Float(a + *b),
+        ("Float#-", Float(a), [Float(b)]) => Float(a - *b),
+        ("Float#abs", Float(a), []) => Float(a.abs()),
+        // Out of range conversion is undefined in LLVM
+        ("Float#to_i", Float(a), []) if (std::i32::MIN as f64) <= a && a <= (std::i32::MAX as f64) => {
+            Int(a as i32)
+        },
+        _ => return None,
+    };
+    Some(value)
+}
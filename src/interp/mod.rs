@@ -0,0 +1,321 @@
+/// Tree-walking interpreter of HIR
+///
+/// An alternative backend which does not need LLVM at runtime.
+/// Methods implemented in Rust (`RustMethodBody`) are executed with
+/// the interpreter-side counterparts in `interp::stdlib`.
+mod stdlib;
+use std::collections::HashMap;
+use std::io::Write;
+use crate::error;
+use crate::error::Error;
+use crate::hir::*;
+use crate::hir::HirExpressionBase::*;
+use crate::ast::LocationSpan;
+use crate::hir::opt::ConstValue;
+use crate::names::*;
+use crate::ty;
+use crate::ty::TermTy;
+
+/// A runtime value
+#[derive(Debug, PartialEq, Clone)]
+pub enum Value {
+    Int(i32),
+    Float(f64),
+    Bool(bool),
+    Str(String),
+    Nil,
+    /// An instance of a Shiika class (including class objects)
+    Object {
+        class_fullname: ClassFullname,
+    },
+    /// The value of expressions of type Void
+    Void,
+}
+
+impl Value {
+    /// The type of this value at runtime
+    pub fn ty(&self) -> TermTy {
+        match self {
+            Value::Int(_) => ty::raw("Int"),
+            Value::Float(_) => ty::raw("Float"),
+            Value::Bool(_) => ty::raw("Bool"),
+            Value::Str(_) => ty::raw("String"),
+            Value::Nil => ty::nil(),
+            Value::Object { class_fullname } => {
+                if class_fullname.0.starts_with("Meta:") {
+                    ty::meta(&class_fullname.0["Meta:".len()..])
+                }
+                else {
+                    ty::raw(&class_fullname.0)
+                }
+            },
+            Value::Void => ty::raw("Void"),
+        }
+    }
+}
+
+/// Interpreter-side implementation of a stdlib method.
+/// Takes the receiver and the arguments
+pub type Primitive = fn(&mut Interpreter, Value, Vec<Value>) -> Result<Value, Error>;
+
+pub struct Interpreter<'a> {
+    hir: &'a Hir,
+    /// All the methods, indexed by their fullname
+    methods: HashMap<&'a str, &'a SkMethod>,
+    primitives: HashMap<&'static str, Primitive>,
+    /// Current values of the constants
+    constants: HashMap<ConstFullname, Value>,
+    /// Destination of the output (eg. `putchar`)
+    pub out: &'a mut dyn Write,
+    /// Shiika methods being executed and the location of the method call
+    /// each of them is making (for the backtrace of `panic`)
+    call_stack: Vec<(&'a str, Option<LocationSpan>)>,
+}
+
+/// Environment of a method invocation
+struct Frame {
+    self_value: Value,
+    args: Vec<Value>,
+    lvars: HashMap<String, Value>,
+}
+
+impl<'a> Interpreter<'a> {
+    pub fn new(hir: &'a Hir, out: &'a mut dyn Write) -> Interpreter<'a> {
+        let methods = hir.sk_methods.values().flat_map(|sk_methods|
+            sk_methods.iter().map(|m| (m.signature.fullname.full_name.as_str(), m))
+        ).collect();
+        // Constants evaluated by `hir::opt`
+        let constants = hir.const_values.iter().map(|(fullname, value)| {
+            let value = match value {
+                ConstValue::Int(i) => Value::Int(*i),
+                ConstValue::Float(f) => Value::Float(*f),
+                ConstValue::Bool(b) => Value::Bool(*b),
+            };
+            (fullname.clone(), value)
+        }).collect();
+        Interpreter {
+            hir,
+            methods,
+            primitives: stdlib::primitives(),
+            constants,
+            out,
+            call_stack: vec![],
+        }
+    }
+
+    /// Create the error for a runtime panic with the current backtrace
+    pub fn panic(&self, msg: &str) -> Error {
+        let trace = self.call_stack.iter().rev().map(|(name, locs)| {
+            match locs {
+                Some(locs) => format!("{} ({})", name, locs.begin_str()),
+                None => format!("{} (?)", name),
+            }
+        }).collect();
+        let locs = self.call_stack.last().and_then(|(_, locs)| locs.clone());
+        error::runtime_panic(msg, trace).with_locs(&locs)
+    }
+
+    /// Evaluate the main expressions and return the last value
+    pub fn run(&mut self) -> Result<Value, Error> {
+        let the_main = Value::Object { class_fullname: ClassFullname("Object".to_string()) };
+        let mut frame = Frame {
+            self_value: the_main,
+            args: vec![],
+            lvars: HashMap::new(),
+        };
+        let hir = self.hir;
+        self.call_stack.push(("main", None));
+        let value = self.eval_exprs(&mut frame, &hir.main_exprs)?;
+        self.call_stack.pop();
+        Ok(value)
+    }
+
+    fn eval_exprs(&mut self, frame: &mut Frame, exprs: &HirExpressions) -> Result<Value, Error> {
+        let mut last_value = Value::Void;
+        for expr in &exprs.exprs {
+            last_value = self.eval_expr(frame, expr)?;
+        }
+        Ok(last_value)
+    }
+
+    fn eval_expr(&mut self, frame: &mut Frame, expr: &HirExpression) -> Result<Value, Error> {
+        match &expr.node {
+            HirIfExpression { cond_expr, then_expr, else_expr } => {
+                let value = if self.eval_cond(frame, cond_expr)? {
+                    self.eval_expr(frame, then_expr)?
+                }
+                else {
+                    self.eval_expr(frame, else_expr)?
+                };
+                // eg. an `if` without `else`
+                Ok(if expr.ty.is_void_type() { Value::Void } else { value })
+            },
+            HirWhileExpression { cond_expr, body_exprs } => {
+                while self.eval_cond(frame, cond_expr)? {
+                    self.eval_exprs(frame, body_exprs)?;
+                }
+                Ok(Value::Void)
+            },
+            HirLVarAssign { name, rhs } => {
+                let value = self.eval_expr(frame, rhs)?;
+                frame.lvars.insert(name.to_string(), value.clone());
+                Ok(value)
+            },
+            HirConstAssign { fullname, rhs } => {
+                let value = self.eval_expr(frame, rhs)?;
+                self.constants.insert(fullname.clone(), value.clone());
+                Ok(value)
+            },
+            HirMethodCall { receiver_expr, method_fullname, arg_exprs } => {
+                let receiver = self.eval_expr(frame, receiver_expr)?;
+                let args = arg_exprs.iter().map(|arg_expr|
+                    self.eval_expr(frame, arg_expr)
+                ).collect::<Result<Vec<_>, _>>()?;
+                if let Some(top) = self.call_stack.last_mut() {
+                    top.1 = expr.locs.clone();
+                }
+                self.call_method(method_fullname, receiver, args)
+            },
+            HirArgRef { idx } => {
+                Ok(frame.args[*idx].clone())
+            },
+            HirLVarRef { name } => {
+                let value = frame.lvars.get(name)
+                    .expect("[BUG] lvar not assigned");
+                Ok(value.clone())
+            },
+            HirConstRef { fullname } => {
+                match self.constants.get(fullname) {
+                    Some(value) => Ok(value.clone()),
+                    None => Err(error::program_error(&format!("constant `{}' is not initialized yet", fullname))),
+                }
+            },
+            HirSelfExpression => {
+                Ok(frame.self_value.clone())
+            },
+            HirFloatLiteral { value } => {
+                Ok(Value::Float(*value))
+            },
+            HirDecimalLiteral { value } => {
+                Ok(Value::Int(*value))
+            },
+            HirBooleanLiteral { value } => {
+                Ok(Value::Bool(*value))
+            },
+            HirStringLiteral { value } => {
+                Ok(Value::Str(value.clone()))
+            },
+            HirNilLiteral => {
+                Ok(Value::Nil)
+            },
+            HirOptWrap { expr } => {
+                self.eval_expr(frame, expr)
+            },
+            HirOptUnwrap { expr } => {
+                match self.eval_expr(frame, expr)? {
+                    Value::Nil => Err(self.panic("unwrapped nil")),
+                    value => Ok(value),
+                }
+            },
+            HirIsNil { expr, negated } => {
+                let is_nil = self.eval_expr(frame, expr)? == Value::Nil;
+                Ok(Value::Bool(is_nil != *negated))
+            },
+            HirUnionWrap { expr } | HirUnionCast { expr } | HirInterfaceWrap { expr } | HirIntersectionCast { expr } |
+            HirGenericWrap { expr } | HirGenericUnwrap { expr } | HirTyParamUpcast { expr, .. } |
+            HirObjectUpcast { expr } => {
+                self.eval_expr(frame, expr)
+            },
+            HirDowncast { expr: inner } => {
+                let value = self.eval_expr(frame, inner)?;
+                if value.ty().conforms_to(&expr.ty) {
+                    Ok(value)
+                }
+                else {
+                    Err(self.panic("invalid cast"))
+                }
+            },
+            HirTypeTest { expr, tested_ty } => {
+                let value = self.eval_expr(frame, expr)?;
+                Ok(Value::Bool(value.ty().conforms_to(tested_ty)))
+            },
+            // Methods are looked up from the class of the receiver instead
+            HirTypeTag { .. } => Ok(Value::Void),
+            // Never read since the method evaluates the default value
+            HirOmittedArg => Ok(Value::Void),
+            HirReplLoad { .. } | HirReplStore { .. } => {
+                Err(error::bug("REPL slots are not supported by the interpreter"))
+            },
+            HirClassLiteral { fullname } => {
+                Ok(Value::Object { class_fullname: ClassFullname("Meta:".to_string() + &fullname.0) })
+            },
+            HirNop => {
+                Ok(Value::Void)
+            },
+        }
+    }
+
+    fn eval_cond(&mut self, frame: &mut Frame, cond_expr: &HirExpression) -> Result<bool, Error> {
+        match self.eval_expr(frame, cond_expr)? {
+            Value::Bool(b) => Ok(b),
+            v => Err(error::bug(&format!("condition evaluated to non-bool value: {:?}", v))),
+        }
+    }
+
+    /// Find a method from the class and its ancestors
+    fn find_method(&self, class_fullname: &ClassFullname, name: &MethodFirstname) -> Option<&'a SkMethod> {
+        let hir = self.hir;
+        let class = hir.sk_classes.get(class_fullname)?;
+        match class.method_sigs.get(name) {
+            Some(sig) => self.methods.get(sig.fullname.full_name.as_str()).cloned(),
+            None => class.superclass_fullname.as_ref()
+                .and_then(|sup| self.find_method(sup, name)),
+        }
+    }
+
+    fn call_method(&mut self,
+                   method_fullname: &MethodFullname,
+                   receiver: Value,
+                   args: Vec<Value>) -> Result<Value, Error> {
+        let method = match self.methods.get(method_fullname.full_name.as_str()) {
+            Some(method) => *method,
+            // Method of an interface; call the one of the receiver's class
+            None => self.find_method(&receiver.ty().fullname, &method_fullname.first_name)
+                .ok_or_else(|| error::bug(&format!("method not found: {}", method_fullname)))?,
+        };
+        let name = method.signature.fullname.full_name.as_str();
+        match &method.body {
+            SkMethodBody::ShiikaMethodBody { exprs } => {
+                let mut frame = Frame {
+                    self_value: receiver,
+                    args,
+                    lvars: HashMap::new(),
+                };
+                self.call_stack.push((method.signature.fullname.full_name.as_str(), None));
+                let value = self.eval_exprs(&mut frame, exprs)?;
+                self.call_stack.pop();
+                if method.signature.ret_ty.is_void_type() {
+                    Ok(Value::Void)
+                }
+                else {
+                    Ok(value)
+                }
+            },
+            SkMethodBody::RustMethodBody { .. } | SkMethodBody::RustClosureMethodBody { .. } => {
+                if let Some(primitive) = self.primitives.get(name).cloned() {
+                    primitive(self, receiver, args)
+                }
+                else if self.hir.extern_funcs.iter().any(|sig| sig.fullname.full_name == name) {
+                    Err(error::program_error(&format!("cannot call extern function `{}' in the interpreter",
+                                                      method_fullname.first_name)))
+                }
+                else if method_fullname.first_name.0 == "new" {
+                    Ok(Value::Object { class_fullname: method.signature.ret_ty.fullname.clone() })
+                }
+                else {
+                    Err(error::bug(&format!("no interpreter implementation of {}", name)))
+                }
+            },
+        }
+    }
+}
@@ -0,0 +1,85 @@
+/// Interpreter-side implementations of the stdlib methods
+///
+/// Must be kept in sync with `crate::stdlib`
+use std::collections::HashMap;
+use std::io::Write;
+use crate::error;
+use crate::error::Error;
+use crate::interp::{Interpreter, Primitive, Value};
+use crate::stdlib::{is_valid_shift_count, SHIFT_RANGE_MSG};
+
+pub fn primitives() -> HashMap<&'static str, Primitive> {
+    let mut h: HashMap<&'static str, Primitive> = HashMap::new();
+
+    h.insert("Int#<", |_, receiver, args| Ok(Value::Bool(int(&receiver) < int(&args[0]))));
+    h.insert("Int#==", |_, receiver, args| Ok(Value::Bool(int(&receiver) == int(&args[0]))));
+    h.insert("Int#+", |interp, receiver, args| checked(interp, int(&receiver).checked_add(int(&args[0]))));
+    h.insert("Int#-", |interp, receiver, args| checked(interp, int(&receiver).checked_sub(int(&args[0]))));
+    h.insert("Int#*", |interp, receiver, args| checked(interp, int(&receiver).checked_mul(int(&args[0]))));
+    h.insert("Int#/", |interp, receiver, args| {
+        if int(&args[0]) == 0 { return Err(interp.panic("division by zero")) }
+        checked(interp, int(&receiver).checked_div(int(&args[0])))
+    });
+    h.insert("Int#%", |interp, receiver, args| {
+        if int(&args[0]) == 0 { return Err(interp.panic("division by zero")) }
+        checked(interp, int(&receiver).checked_rem(int(&args[0])))
+    });
+    h.insert("Int#&", |_, receiver, args| Ok(Value::Int(int(&receiver) & int(&args[0]))));
+    h.insert("Int#|", |_, receiver, args| Ok(Value::Int(int(&receiver) | int(&args[0]))));
+    h.insert("Int#^", |_, receiver, args| Ok(Value::Int(int(&receiver) ^ int(&args[0]))));
+    h.insert("Int#<<", |interp, receiver, args| {
+        if !is_valid_shift_count(int(&args[0])) { return Err(interp.panic(SHIFT_RANGE_MSG)) }
+        Ok(Value::Int(int(&receiver) << int(&args[0])))
+    });
+    h.insert("Int#>>", |interp, receiver, args| {
+        if !is_valid_shift_count(int(&args[0])) { return Err(interp.panic(SHIFT_RANGE_MSG)) }
+        Ok(Value::Int(int(&receiver) >> int(&args[0])))
+    });
+    h.insert("Int#to_f", |_, receiver, _| Ok(Value::Float(int(&receiver) as f64)));
+
+    h.insert("Float#<", |_, receiver, args| Ok(Value::Bool(float(&receiver) < float(&args[0]))));
+    h.insert("Float#+", |_, receiver, args| Ok(Value::Float(float(&receiver) + float(&args[0]))));
+    h.insert("Float#-", |_, receiver, args| Ok(Value::Float(float(&receiver) - float(&args[0]))));
+    h.insert("Float#abs", |_, receiver, _| Ok(Value::Float(float(&receiver).abs())));
+    h.insert("Float#to_i", |_, receiver, _| Ok(Value::Int(float(&receiver) as i32)));
+
+    h.insert("Meta:Math#sin", |_, _, args| Ok(Value::Float(float(&args[0]).sin())));
+    h.insert("Meta:Math#cos", |_, _, args| Ok(Value::Float(float(&args[0]).cos())));
+    h.insert("Meta:Math#sqrt", |_, _, args| Ok(Value::Float(float(&args[0]).sqrt())));
+
+    h.insert("Object#putchar", |interp, _, args| {
+        interp.out.write_all(&[int(&args[0]) as u8])
+            .map_err(|e| error::program_error(&format!("putchar failed: {}", e)))?;
+        Ok(Value::Void)
+    });
+    h.insert("Object#panic", |interp, _, args| {
+        match &args[0] {
+            Value::Str(msg) => Err(interp.panic(msg)),
+            v => panic!("[BUG] expected String but got {:?}", v),
+        }
+    });
+
+    h
+}
+
+/// Return the result of an integer operation or panic on overflow
+fn checked(interp: &mut Interpreter, result: Option<i32>) -> Result<Value, Error> {
+    match result {
+        Some(i) => Ok(Value::Int(i)),
+        None => Err(interp.panic("integer overflow")),
+    }
+}
+
+fn int(value: &Value) -> i32 {
+    match value {
+        Value::Int(i) => *i,
+        _ => panic!("[BUG] expected Int but got {:?}", value),
+    }
+}
+
+fn float(value: &Value) -> f64 {
+    match value {
+        Value::Float(f) => *f,
+        _ => panic!("[BUG] expected Float but got {:?}", value),
+    }
+}
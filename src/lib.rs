@@ -3,8 +3,17 @@ pub mod ast;
 pub mod ty;
 pub mod parser;
 pub mod hir;
+#[cfg(feature = "codegen")]
 pub mod code_gen;
+pub mod interp;
 pub mod stdlib;
 pub mod type_checking;
 pub mod error;
+pub mod diagnostics;
+pub mod formatter;
 pub mod names;
+pub mod linker;
+pub mod loader;
+pub mod lsp;
+#[cfg(feature = "codegen")]
+pub mod repl;
@@ -0,0 +1,107 @@
+/// Linking executables with the C compiler and locating libgc (with pkg-config)
+use std::process::{Command, Output};
+use crate::error;
+use crate::error::Error;
+
+/// Compiler and linker flags for Boehm GC
+#[derive(Debug, PartialEq, Clone)]
+pub struct GcConfig {
+    pub cflags: Vec<String>,
+    pub libs: Vec<String>,
+}
+
+impl GcConfig {
+    /// Locate libgc from (in this order)
+    ///
+    /// 1. `SHIIKA_GC_INCLUDE` and `SHIIKA_GC_LIB_DIR`
+    /// 2. `pkg-config bdw-gc`
+    /// 3. the default search path of the C compiler
+    pub fn discover() -> GcConfig {
+        let include_dir = std::env::var("SHIIKA_GC_INCLUDE").ok();
+        let lib_dir = std::env::var("SHIIKA_GC_LIB_DIR").ok();
+        if include_dir.is_some() || lib_dir.is_some() {
+            let mut libs = vec![];
+            if let Some(dir) = lib_dir {
+                libs.push(format!("-L{}", dir));
+            }
+            libs.push("-lgc".to_string());
+            return GcConfig {
+                cflags: include_dir.map(|dir| vec![format!("-I{}", dir)]).unwrap_or_default(),
+                libs,
+            }
+        }
+
+        if let Some(config) = GcConfig::from_pkg_config() {
+            return config
+        }
+
+        GcConfig {
+            cflags: vec![],
+            libs: vec!["-lgc".to_string()],
+        }
+    }
+
+    fn from_pkg_config() -> Option<GcConfig> {
+        let cflags = pkg_config(&["--cflags", "bdw-gc"])?;
+        let libs = pkg_config(&["--libs", "bdw-gc"])?;
+        Some(GcConfig { cflags, libs })
+    }
+
+    /// Directories given with `-L`
+    pub fn lib_dirs(&self) -> Vec<String> {
+        self.libs.iter()
+            .filter(|flag| flag.starts_with("-L"))
+            .map(|flag| flag[2..].to_string())
+            .collect()
+    }
+}
+
+/// Run pkg-config and split its output into flags.
+/// Returns None if pkg-config is not available or the package is not found
+fn pkg_config(args: &[&str]) -> Option<Vec<String>> {
+    let output = Command::new("pkg-config").args(args).output().ok()?;
+    if !output.status.success() {
+        return None
+    }
+    let stdout = String::from_utf8(output.stdout).ok()?;
+    Some(stdout.split_whitespace().map(|s| s.to_string()).collect())
+}
+
+/// The C compiler used for linking (`CC` or `cc`)
+pub fn cc() -> String {
+    std::env::var("CC").unwrap_or_else(|_| "cc".to_string())
+}
+
+/// Link an object file with libgc and create an executable
+pub fn link(input_path: &str, out_path: &str) -> Result<(), Error> {
+    link_with(input_path, out_path, &[])
+}
+
+/// Same as `link` but `extra_args` (eg. `-lm`) are passed to the linker
+pub fn link_with(input_path: &str, out_path: &str, extra_args: &[String]) -> Result<(), Error> {
+    let gc = GcConfig::discover();
+    let mut cmd = Command::new(cc());
+    cmd.args(&gc.cflags);
+    cmd.arg("-o");
+    cmd.arg(out_path);
+    cmd.arg(input_path);
+    cmd.args(extra_args);
+    cmd.args(&gc.libs);
+    run_tool(&mut cmd)?;
+    Ok(())
+}
+
+/// Run an external command and return its output.
+/// Fails if the command is not found or exits with non-zero status
+pub fn run_tool(cmd: &mut Command) -> Result<Output, Error> {
+    let output = cmd.output().map_err(|e|
+        error::tool_error(&format!("failed to execute {:?}: {}", cmd, e))
+    )?;
+    if output.status.success() {
+        Ok(output)
+    }
+    else {
+        Err(error::tool_error(&format!("{:?} failed ({})\n{}",
+                                       cmd, output.status, String::from_utf8_lossy(&output.stderr))))
+    }
+}
@@ -0,0 +1,119 @@
+/// Loading programs which consist of multiple files
+///
+/// `require "path"` loads the file at `path`, which is relative to the
+/// file containing the `require` (`.sk` may be omitted). Each file is
+/// loaded only once and its definitions and expressions come before the
+/// ones of the file which requires it.
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::ast;
+use crate::error;
+use crate::error::Error;
+use crate::parser::Parser;
+
+/// Load the program in `filepath` and the files required from it
+pub fn load(filepath: &Path) -> Result<ast::Program, Error> {
+    let src = fs::read_to_string(filepath).map_err(|e|
+        error::program_error(&format!("cannot read {}: {}", filepath.display(), e))
+    )?;
+    load_str(&src, Some(filepath))
+}
+
+/// Same as `load` but the source of the main file is given.
+/// `filepath` is used to resolve `require`s and in the error messages
+/// (the current directory is used if None)
+pub fn load_str(src: &str, filepath: Option<&Path>) -> Result<ast::Program, Error> {
+    let mut loader = Loader {
+        loaded: HashSet::new(),
+        loading: vec![],
+        toplevel_defs: vec![],
+        exprs: vec![],
+    };
+    let (program, dir) = match filepath {
+        Some(path) => {
+            loader.loading.push(canonicalize(path));
+            let program = Parser::parse_file(src, &path.to_string_lossy())?;
+            (program, path.parent().unwrap_or(Path::new(".")).to_path_buf())
+        },
+        None => (Parser::parse(src)?, PathBuf::from(".")),
+    };
+    loader.add_program(program, &dir)?;
+    Ok(ast::Program {
+        toplevel_defs: loader.toplevel_defs,
+        exprs: loader.exprs,
+        requires: vec![],
+    })
+}
+
+struct Loader {
+    /// Files already loaded
+    loaded: HashSet<PathBuf>,
+    /// Files being loaded (used to detect circular requires)
+    loading: Vec<PathBuf>,
+    toplevel_defs: Vec<ast::Definition>,
+    exprs: Vec<ast::AstExpression>,
+}
+
+impl Loader {
+    /// Load the files required by `program` and then add `program` itself
+    fn add_program(&mut self, program: ast::Program, dir: &Path) -> Result<(), Error> {
+        for req in &program.requires {
+            self.require(req, dir)?;
+        }
+        self.toplevel_defs.extend(program.toplevel_defs);
+        self.exprs.extend(program.exprs);
+        Ok(())
+    }
+
+    fn require(&mut self, req: &ast::Require, dir: &Path) -> Result<(), Error> {
+        let locs = Some(req.locs.clone());
+        let path = match resolve(dir, &req.path) {
+            Some(path) => path,
+            None => {
+                let msg = format!("cannot load such file -- {}", req.path);
+                return Err(error::program_error(&msg).with_locs(&locs))
+            }
+        };
+        if let Some(idx) = self.loading.iter().position(|p| *p == path) {
+            let mut chain = self.loading[idx..].iter().map(|p| p.display().to_string()).collect::<Vec<_>>();
+            chain.push(path.display().to_string());
+            let msg = format!("circular require: {}", chain.join(" -> "));
+            return Err(error::program_error(&msg).with_locs(&locs))
+        }
+        if self.loaded.contains(&path) {
+            return Ok(())
+        }
+
+        let src = fs::read_to_string(&path).map_err(|e|
+            error::program_error(&format!("cannot read {}: {}", path.display(), e)).with_locs(&locs)
+        )?;
+        let program = Parser::parse_file(&src, &path.to_string_lossy())?;
+        self.loading.push(path.clone());
+        self.add_program(program, path.parent().unwrap_or(Path::new(".")))?;
+        self.loading.pop();
+        self.loaded.insert(path);
+        Ok(())
+    }
+}
+
+/// Return the path of the file for `require "name"`
+fn resolve(dir: &Path, name: &str) -> Option<PathBuf> {
+    let path = dir.join(name);
+    if path.is_file() {
+        return Some(canonicalize(&path))
+    }
+    let mut with_ext = path.into_os_string();
+    with_ext.push(".sk");
+    let with_ext = PathBuf::from(with_ext);
+    if with_ext.is_file() {
+        return Some(canonicalize(&with_ext))
+    }
+    None
+}
+
+/// Return the canonical form of `path` (or `path` itself if it does not exist.)
+/// Used as the file name of the required files
+pub fn canonicalize(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
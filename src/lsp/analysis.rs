@@ -0,0 +1,314 @@
+/// Static analysis of a document for the language server
+use std::panic;
+use std::path::Path;
+use crate::ast::LocationSpan;
+use crate::diagnostics;
+use crate::diagnostics::Diagnostic;
+use crate::error;
+use crate::error::Error;
+use crate::hir::{Hir, HirExpression, HirExpressionBase, SkMethodBody};
+use crate::hir::index::Index;
+use crate::loader;
+use crate::names::*;
+use crate::parser::lexer::Lexer;
+use crate::parser::token::Token;
+use crate::stdlib::Stdlib;
+use crate::ty::MethodSignature;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum DefinitionKind {
+    Class,
+    Method,
+    Const,
+}
+
+/// Where a class, a method or a constant is defined
+#[derive(Debug, PartialEq, Clone)]
+pub struct DefinitionSite {
+    pub kind: DefinitionKind,
+    /// eg. "A" (class), "Meta:A#foo" (method), "::A::B" (constant)
+    pub fullname: String,
+    /// Range of the name
+    pub locs: LocationSpan,
+}
+
+pub struct Analysis {
+    pub diagnostics: Vec<Diagnostic>,
+    src: String,
+    /// Path of the document (None if it is not a local file)
+    filepath: Option<String>,
+    /// Tokens except spaces
+    tokens: Vec<(Token, LocationSpan)>,
+    definitions: Vec<DefinitionSite>,
+    /// Result of the last successful compilation. This is kept while the
+    /// document has errors so that hover and completion work during editing
+    compiled: Option<(Index, Hir)>,
+}
+
+impl Analysis {
+    pub fn new() -> Analysis {
+        Analysis {
+            diagnostics: vec![],
+            src: "".to_string(),
+            filepath: None,
+            tokens: vec![],
+            definitions: vec![],
+            compiled: None,
+        }
+    }
+
+    /// Analyze the new content of the document. `filepath` is used to
+    /// resolve `require`s
+    pub fn update(&mut self, src: &str, filepath: Option<&Path>) {
+        self.src = src.to_string();
+        self.filepath = filepath.map(|path| path.to_string_lossy().into_owned());
+        // The compiler may panic on the unsupported syntax; it should not
+        // stop the server
+        self.tokens = panic::catch_unwind(|| tokenize(src)).unwrap_or_default();
+        self.definitions = find_definitions(&self.tokens);
+        let result = panic::catch_unwind(|| compile(src, filepath))
+            .unwrap_or_else(|_| Err(error::bug("the compiler crashed")));
+        match result {
+            Ok(compiled) => {
+                self.diagnostics = diagnostics::lint(&compiled.1, &[]);
+                self.compiled = Some(compiled);
+            },
+            Err(err) => {
+                self.diagnostics = vec![Diagnostic::from_error(&err)];
+            }
+        }
+    }
+
+    /// Return the type of the innermost expression at the position
+    pub fn hover(&self, line: usize, col: usize) -> Option<String> {
+        self.expr_at(line, col).map(|expr| expr.ty.fullname.0.clone())
+    }
+
+    /// Return the definition of the class, method or constant at the position
+    pub fn definition(&self, line: usize, col: usize) -> Option<&DefinitionSite> {
+        let (token, _) = self.tokens.iter().find(|(_, locs)| locs.contains(line, col))?;
+        match token {
+            Token::UpperWord(name) => self.find_class_or_const(line, col, name),
+            Token::LowerWord(name) => self.find_method(line, col, name),
+            _ => None,
+        }
+    }
+
+    /// Return the methods which can be called at the position.
+    /// If the position is just after `receiver.`, only the methods of the
+    /// receiver are returned
+    pub fn completion(&self, line: usize, col: usize) -> Vec<MethodSignature> {
+        let (hir, index) = match &self.compiled {
+            Some((index, hir)) => (hir, index),
+            None => return vec![],
+        };
+        let line_str = self.src.lines().nth(line).unwrap_or("");
+        let before = line_str.chars().take(col).collect::<Vec<_>>();
+        let prefix_len = before.iter().rev()
+            .take_while(|c| c.is_ascii_alphanumeric() || **c == '_')
+            .count();
+        let prefix = before[before.len() - prefix_len..].iter().collect::<String>();
+        let dot_col = before.len() - prefix_len;
+
+        let receiver_class = if dot_col > 0 && before[dot_col - 1] == '.' {
+            self.find_expr(&|locs: &LocationSpan| (locs.end.line(), locs.end.col()) == (line, dot_col - 1))
+                .map(|expr| expr.ty.fullname.clone())
+        }
+        else {
+            None
+        };
+        let mut sigs = match receiver_class {
+            Some(class_fullname) => methods_of(hir, class_fullname),
+            None => index.sk_classes.values()
+                .flat_map(|class| class.method_sigs.values().cloned())
+                .collect(),
+        };
+        sigs.retain(|sig| sig.first_name().0.starts_with(&prefix));
+        sigs.sort_by(|a, b| a.fullname.full_name.cmp(&b.fullname.full_name));
+        sigs
+    }
+
+    fn find_class_or_const(&self, line: usize, col: usize, name: &str) -> Option<&DefinitionSite> {
+        let const_fullname = match self.expr_at(line, col).map(|expr| &expr.node) {
+            Some(HirExpressionBase::HirConstRef { fullname }) => fullname.0.clone(),
+            _ => format!("::{}", name),
+        };
+        let class_exists = match &self.compiled {
+            Some((index, _)) => index.find_class(&ClassFullname(name.to_string())).is_some(),
+            None => true,
+        };
+        self.find_site(DefinitionKind::Const, &const_fullname)
+            .or_else(|| if class_exists { self.find_site(DefinitionKind::Class, name) } else { None })
+    }
+
+    fn find_method(&self, line: usize, col: usize, name: &str) -> Option<&DefinitionSite> {
+        if let Some(HirExpressionBase::HirMethodCall { method_fullname, .. }) = self.expr_at(line, col).map(|expr| &expr.node) {
+            if method_fullname.first_name.0 == name {
+                return self.find_site(DefinitionKind::Method, &method_fullname.full_name)
+            }
+        }
+        // Not a method call (eg. the name of `def`); search by the name
+        let first_name = MethodFirstname(name.to_string());
+        match &self.compiled {
+            Some((index, _)) => {
+                index.sk_classes.keys()
+                    .filter_map(|class_fullname| index.find_method(class_fullname, &first_name))
+                    .find_map(|sig| self.find_site(DefinitionKind::Method, &sig.fullname.full_name))
+            },
+            None => {
+                let suffix = format!("#{}", name);
+                self.definitions.iter().find(|site|
+                    site.kind == DefinitionKind::Method && site.fullname.ends_with(&suffix)
+                )
+            }
+        }
+    }
+
+    fn find_site(&self, kind: DefinitionKind, fullname: &str) -> Option<&DefinitionSite> {
+        self.definitions.iter().find(|site| site.kind == kind && site.fullname == fullname)
+    }
+
+    /// Return the innermost expression which contains the position
+    fn expr_at(&self, line: usize, col: usize) -> Option<&HirExpression> {
+        self.find_expr(&|locs: &LocationSpan| locs.contains(line, col))
+    }
+
+    /// Return the innermost expression whose range satisfies `pred`.
+    /// Expressions in the required files are skipped
+    fn find_expr(&self, pred: &dyn Fn(&LocationSpan) -> bool) -> Option<&HirExpression> {
+        let (_, hir) = self.compiled.as_ref()?;
+        let in_document = |locs: &LocationSpan| {
+            locs.file.as_deref() == self.filepath.as_deref() && pred(locs)
+        };
+        toplevel_exprs(hir).into_iter().find_map(|expr| find_innermost(expr, &in_document))
+    }
+}
+
+/// Parse and type-check `src` and the files required from it
+fn compile(src: &str, filepath: Option<&Path>) -> Result<(Index, Hir), Error> {
+    let ast = loader::load_str(src, filepath)?;
+    let index = Index::new(Stdlib::create().sk_classes, &ast.toplevel_defs)?;
+    let hir = Hir::from_ast(ast, Stdlib::create())?;
+    Ok((index, hir))
+}
+
+fn tokenize(src: &str) -> Vec<(Token, LocationSpan)> {
+    let mut lexer = Lexer::new(src);
+    let mut tokens = vec![];
+    loop {
+        let begin = lexer.cur.clone();
+        let token = lexer.consume_token();
+        match token {
+            Token::Eof => break,
+            Token::Bof | Token::Space => (),
+            _ => tokens.push((token, LocationSpan { file: None, begin, end: lexer.cur.clone() })),
+        }
+    }
+    tokens
+}
+
+/// Collect the names of the classes, methods and constants defined in the
+/// document
+fn find_definitions(tokens: &[(Token, LocationSpan)]) -> Vec<DefinitionSite> {
+    let mut sites = vec![];
+    // Class names of the enclosing blocks (None for the other blocks)
+    let mut blocks: Vec<Option<String>> = vec![];
+    // Length of `blocks` in an `interface` (whose `def`s have no `end`)
+    let mut interface_depth = None;
+    for (i, (token, _)) in tokens.iter().enumerate() {
+        let classes = blocks.iter().filter_map(|b| b.clone()).collect::<Vec<_>>();
+        match token {
+            Token::KwClass | Token::KwInterface => {
+                match tokens.get(i + 1) {
+                    Some((Token::UpperWord(name), locs)) => {
+                        sites.push(site(DefinitionKind::Class, name.clone(), locs));
+                        blocks.push(Some(name.clone()));
+                    },
+                    _ => blocks.push(None),
+                }
+                if *token == Token::KwInterface {
+                    interface_depth = Some(blocks.len());
+                }
+            },
+            Token::KwDef => {
+                let class_name = classes.last().cloned().unwrap_or_else(|| "Object".to_string());
+                match (tokens.get(i + 1), tokens.get(i + 2), tokens.get(i + 3)) {
+                    (Some((Token::KwSelf, _)), Some((Token::Dot, _)), Some((Token::LowerWord(name), locs))) => {
+                        sites.push(site(DefinitionKind::Method, format!("Meta:{}#{}", class_name, name), locs));
+                    },
+                    (Some((Token::LowerWord(name), locs)), _, _) => {
+                        sites.push(site(DefinitionKind::Method, format!("{}#{}", class_name, name), locs));
+                    },
+                    _ => (),
+                }
+                // `extern def` and the methods of an interface have no `end`
+                let is_extern = i > 0 && tokens[i - 1].0 == Token::KwExtern;
+                if !is_extern && interface_depth != Some(blocks.len()) {
+                    blocks.push(None);
+                }
+            },
+            Token::KwIf | Token::KwUnless | Token::KwWhile => blocks.push(None),
+            Token::KwEnd => {
+                if interface_depth == Some(blocks.len()) {
+                    interface_depth = None;
+                }
+                blocks.pop();
+            },
+            Token::UpperWord(name) => {
+                let stmt_begin = i == 0 || tokens[i - 1].0 == Token::Separator;
+                if stmt_begin && tokens.get(i + 1).map(|(t, _)| t) == Some(&Token::Equal) {
+                    let mut names = classes.clone();
+                    names.push(name.clone());
+                    sites.push(site(DefinitionKind::Const, format!("::{}", names.join("::")), &tokens[i].1));
+                }
+            },
+            _ => (),
+        }
+    }
+    sites
+}
+
+fn site(kind: DefinitionKind, fullname: String, locs: &LocationSpan) -> DefinitionSite {
+    DefinitionSite { kind, fullname, locs: locs.clone() }
+}
+
+/// Return the top-level expressions of the program and the methods
+fn toplevel_exprs(hir: &Hir) -> Vec<&HirExpression> {
+    let mut exprs = hir.main_exprs.exprs.iter().collect::<Vec<_>>();
+    for methods in hir.sk_methods.values() {
+        for method in methods {
+            if let SkMethodBody::ShiikaMethodBody { exprs: body } = &method.body {
+                exprs.extend(body.exprs.iter());
+            }
+        }
+    }
+    exprs
+}
+
+fn find_innermost<'a>(expr: &'a HirExpression, pred: &dyn Fn(&LocationSpan) -> bool) -> Option<&'a HirExpression> {
+    expr.children().into_iter()
+        .find_map(|child| find_innermost(child, pred))
+        .or_else(|| match &expr.locs {
+            Some(locs) if pred(locs) => Some(expr),
+            _ => None,
+        })
+}
+
+/// Return the methods of the class and its ancestors
+fn methods_of(hir: &Hir, class_fullname: ClassFullname) -> Vec<MethodSignature> {
+    let mut sigs: Vec<MethodSignature> = vec![];
+    let mut next = Some(class_fullname);
+    while let Some(fullname) = next {
+        let class = match hir.sk_classes.get(&fullname) {
+            Some(class) => class,
+            None => break,
+        };
+        for sig in class.method_sigs.values() {
+            if !sigs.iter().any(|s| s.first_name() == sig.first_name()) {
+                sigs.push(sig.clone());
+            }
+        }
+        next = class.superclass_fullname.clone();
+    }
+    sigs
+}
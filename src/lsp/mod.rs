@@ -0,0 +1,286 @@
+/// Language server (`shiika lsp`)
+///
+/// Speaks the Language Server Protocol over stdio and provides
+/// diagnostics, hover (type of the expression), go to definition and
+/// method name completion.
+pub mod analysis;
+use std::collections::HashMap;
+use std::io;
+use std::io::{BufRead, Read, Write};
+use std::path::PathBuf;
+use serde_json::{json, Value};
+use crate::ast::LocationSpan;
+use crate::diagnostics::Severity;
+use crate::loader;
+use crate::ty::MethodSignature;
+use self::analysis::Analysis;
+
+// Error codes defined by JSON-RPC
+const PARSE_ERROR: i64 = -32700;
+const METHOD_NOT_FOUND: i64 = -32601;
+
+// Values of `CompletionItemKind`
+const COMPLETION_KIND_METHOD: i64 = 2;
+
+/// Run the server until `exit` is received or the input is closed
+pub fn run<R: BufRead, W: Write>(input: &mut R, output: &mut W) -> io::Result<()> {
+    let mut server = Server {
+        out: output,
+        documents: HashMap::new(),
+    };
+    while let Some(body) = read_message(input)? {
+        match serde_json::from_slice::<Value>(&body) {
+            Ok(msg) => {
+                if !server.handle(&msg)? {
+                    break
+                }
+            },
+            Err(e) => server.send_error(&Value::Null, PARSE_ERROR, &e.to_string())?,
+        }
+    }
+    Ok(())
+}
+
+/// Read a message and return its content (None on EOF)
+fn read_message<R: BufRead>(input: &mut R) -> io::Result<Option<Vec<u8>>> {
+    let mut content_length = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None)
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break
+        }
+        let mut parts = line.splitn(2, ':');
+        if let (Some(name), Some(value)) = (parts.next(), parts.next()) {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                content_length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+    let len = content_length.ok_or_else(||
+        io::Error::new(io::ErrorKind::InvalidData, "missing Content-Length")
+    )?;
+    let mut body = vec![0; len];
+    input.read_exact(&mut body)?;
+    Ok(Some(body))
+}
+
+struct Server<'a, W: Write> {
+    out: &'a mut W,
+    /// Analysis of the opened documents (key is the uri)
+    documents: HashMap<String, Analysis>,
+}
+
+impl<'a, W: Write> Server<'a, W> {
+    /// Process a message. Returns false if the server should stop
+    fn handle(&mut self, msg: &Value) -> io::Result<bool> {
+        let method = msg["method"].as_str().unwrap_or("");
+        let params = &msg["params"];
+        let id = msg.get("id");
+        match method {
+            "initialize" => {
+                self.send_result(id, json!({
+                    "capabilities": {
+                        "textDocumentSync": 1, // Full
+                        "hoverProvider": true,
+                        "definitionProvider": true,
+                        "completionProvider": { "triggerCharacters": ["."] },
+                    },
+                    "serverInfo": { "name": "shiika" },
+                }))?;
+            },
+            "shutdown" => self.send_result(id, Value::Null)?,
+            "exit" => return Ok(false),
+            "textDocument/didOpen" => {
+                let uri = uri_of(params);
+                let text = params["textDocument"]["text"].as_str().unwrap_or("");
+                self.update_document(uri, text)?;
+            },
+            "textDocument/didChange" => {
+                let uri = uri_of(params);
+                // We only support full sync, so the last change is the whole text
+                let changes = params["contentChanges"].as_array();
+                if let Some(text) = changes.and_then(|v| v.last()).and_then(|c| c["text"].as_str()) {
+                    self.update_document(uri, text)?;
+                }
+            },
+            "textDocument/didClose" => {
+                let uri = uri_of(params);
+                self.documents.remove(&uri);
+                self.send_notification("textDocument/publishDiagnostics", json!({
+                    "uri": uri,
+                    "diagnostics": [],
+                }))?;
+            },
+            "textDocument/hover" => {
+                let result = self.with_position(params, |doc, line, col| {
+                    doc.hover(line, col).map(|ty| json!({
+                        "contents": { "kind": "plaintext", "value": ty },
+                    }))
+                });
+                self.send_result(id, result.unwrap_or(Value::Null))?;
+            },
+            "textDocument/definition" => {
+                let uri = uri_of(params);
+                let result = self.with_position(params, |doc, line, col| {
+                    doc.definition(line, col).map(|site| json!({
+                        "uri": uri,
+                        "range": range_json(&site.locs),
+                    }))
+                });
+                self.send_result(id, result.unwrap_or(Value::Null))?;
+            },
+            "textDocument/completion" => {
+                let result = self.with_position(params, |doc, line, col| {
+                    let items = doc.completion(line, col).iter().map(completion_item).collect::<Vec<_>>();
+                    Some(Value::Array(items))
+                });
+                self.send_result(id, result.unwrap_or_else(|| json!([])))?;
+            },
+            _ => {
+                // Notifications which we do not support are just ignored
+                if let Some(id) = id {
+                    let msg = format!("unsupported method: {}", method);
+                    self.send_error(id, METHOD_NOT_FOUND, &msg)?;
+                }
+            }
+        }
+        Ok(true)
+    }
+
+    /// Analyze the document and publish the diagnostics
+    fn update_document(&mut self, uri: String, text: &str) -> io::Result<()> {
+        let filepath = path_of(&uri);
+        let doc = self.documents.entry(uri.clone()).or_insert_with(Analysis::new);
+        doc.update(text, filepath.as_ref().map(|path| path.as_path()));
+        let diagnostics = doc.diagnostics.iter().map(|d| {
+            let (line, col) = d.location.unwrap_or((0, 0));
+            let (end_line, end_col) = d.end.unwrap_or((line, col + 1));
+            let other_file = d.file.as_ref().filter(|file|
+                filepath.as_ref().map_or(true, |path| path.to_string_lossy() != file.as_str())
+            );
+            // Errors in the required files are shown at the top of the document
+            let (line, col, end_line, end_col, msg) = match other_file {
+                Some(file) => (0, 0, 0, 1, format!("{}:{}:{}: {}", file, line + 1, col + 1, d.msg)),
+                None => (line, col, end_line, end_col, d.msg.clone()),
+            };
+            let severity = match d.severity {
+                Severity::Error => 1,
+                Severity::Warning => 2,
+            };
+            json!({
+                "range": {
+                    "start": { "line": line, "character": col },
+                    "end": { "line": end_line, "character": end_col },
+                },
+                "severity": severity,
+                "source": "shiika",
+                "code": d.kind,
+                "message": msg,
+            })
+        }).collect::<Vec<_>>();
+        self.send_notification("textDocument/publishDiagnostics", json!({
+            "uri": uri,
+            "diagnostics": diagnostics,
+        }))
+    }
+
+    /// Call `f` with the document and the position in `params`
+    fn with_position<F>(&self, params: &Value, f: F) -> Option<Value>
+        where F: Fn(&Analysis, usize, usize) -> Option<Value> {
+        let doc = self.documents.get(&uri_of(params))?;
+        let line = params["position"]["line"].as_u64()? as usize;
+        let col = params["position"]["character"].as_u64()? as usize;
+        f(doc, line, col)
+    }
+
+    fn send_result(&mut self, id: Option<&Value>, result: Value) -> io::Result<()> {
+        self.send(json!({
+            "jsonrpc": "2.0",
+            "id": id.cloned().unwrap_or(Value::Null),
+            "result": result,
+        }))
+    }
+
+    fn send_error(&mut self, id: &Value, code: i64, msg: &str) -> io::Result<()> {
+        self.send(json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "error": { "code": code, "message": msg },
+        }))
+    }
+
+    fn send_notification(&mut self, method: &str, params: Value) -> io::Result<()> {
+        self.send(json!({
+            "jsonrpc": "2.0",
+            "method": method,
+            "params": params,
+        }))
+    }
+
+    fn send(&mut self, msg: Value) -> io::Result<()> {
+        let body = msg.to_string();
+        write!(self.out, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+        self.out.flush()
+    }
+}
+
+fn uri_of(params: &Value) -> String {
+    params["textDocument"]["uri"].as_str().unwrap_or("").to_string()
+}
+
+/// Convert `file://` uri to the path (None for other schemes)
+///
+/// The path is canonicalized in the same way as the required files so that
+/// it can be compared with `locs.file`
+fn path_of(uri: &str) -> Option<PathBuf> {
+    if uri.starts_with("file://") {
+        let path = PathBuf::from(percent_decode(&uri["file://".len()..]));
+        Some(loader::canonicalize(&path))
+    }
+    else {
+        None
+    }
+}
+
+/// Decode `%XX` escapes in the uri
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+            if let Some(b) = hex {
+                decoded.push(b);
+                i += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn range_json(locs: &LocationSpan) -> Value {
+    json!({
+        "start": { "line": locs.begin.line(), "character": locs.begin.col() },
+        "end": { "line": locs.end.line(), "character": locs.end.col() },
+    })
+}
+
+fn completion_item(sig: &MethodSignature) -> Value {
+    let params = sig.params.iter()
+        .map(|p| format!("{}: {}", p.name, p.ty.fullname))
+        .collect::<Vec<_>>();
+    json!({
+        "label": sig.first_name().0,
+        "kind": COMPLETION_KIND_METHOD,
+        "detail": format!("{}({}) -> {}", sig.fullname, params.join(", "), sig.ret_ty.fullname),
+    })
+}
@@ -1,8 +1,106 @@
 use std::fs;
-use std::process::Command;
+#[cfg(feature = "codegen")]
+use std::io::BufRead;
+use std::io::Write;
+use std::path::Path;
 #[macro_use]
 extern crate clap;
+#[cfg(feature = "codegen")]
+use inkwell::OptimizationLevel;
+#[cfg(feature = "codegen")]
+use inkwell::targets::FileType;
 use shiika;
+use shiika::hir::lint::Lint;
+
+/// Kind of output of `compile --emit`
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Emit {
+    Ast,
+    Hir,
+    LlvmIr,
+    Bc,
+    Asm,
+    Obj,
+    Exe,
+}
+
+impl Emit {
+    fn from_str(s: &str) -> Emit {
+        match s {
+            "ast" => Emit::Ast,
+            "hir" => Emit::Hir,
+            "llvm-ir" => Emit::LlvmIr,
+            "bc" => Emit::Bc,
+            "asm" => Emit::Asm,
+            "obj" => Emit::Obj,
+            "exe" => Emit::Exe,
+            _ => panic!("[BUG] unknown emit type: {}", s) // Checked by clap
+        }
+    }
+
+    /// Extension of the default output file.
+    /// None if the output is printed to stdout
+    fn default_ext(&self) -> Option<&'static str> {
+        match self {
+            Emit::Ast | Emit::Hir => None,
+            Emit::LlvmIr => Some("ll"),
+            Emit::Bc => Some("bc"),
+            Emit::Asm => Some("s"),
+            Emit::Obj => Some("o"),
+            Emit::Exe => Some("out"),
+        }
+    }
+}
+
+/// Options for code generation
+#[derive(Debug, Clone)]
+#[cfg_attr(not(feature = "codegen"), allow(dead_code))]
+struct CodeGenOptions {
+    /// 0 to 3 (`-O`)
+    opt_level: u32,
+    verify: bool,
+    /// Path of the source file to emit debug info for (`-g`)
+    debug_source: Option<String>,
+}
+
+impl CodeGenOptions {
+    fn from_matches(matches: &clap::ArgMatches) -> CodeGenOptions {
+        let opt_level = matches.value_of("opt-level").unwrap().parse()
+            .expect("[BUG] unknown optimization level"); // Checked by clap
+        CodeGenOptions { opt_level, verify: matches.is_present("verify"), debug_source: None }
+    }
+
+    /// Apply the HIR optimizations if optimizing. Done for all the
+    /// backends (including the interpreter)
+    fn optimize_hir(&self, hir: &mut shiika::hir::Hir) {
+        if self.opt_level != 0 {
+            shiika::hir::opt::optimize(hir);
+        }
+    }
+
+    #[cfg(feature = "codegen")]
+    fn llvm_opt_level(&self) -> OptimizationLevel {
+        match self.opt_level {
+            0 => OptimizationLevel::None,
+            1 => OptimizationLevel::Less,
+            2 => OptimizationLevel::Default,
+            _ => OptimizationLevel::Aggressive,
+        }
+    }
+
+    /// Generate the code of `hir` with the options
+    #[cfg(feature = "codegen")]
+    fn gen_program(&self, hir: shiika::hir::Hir) -> Result<shiika::code_gen::CodeGen, shiika::error::Error> {
+        let mut code_gen = shiika::code_gen::CodeGen::new();
+        code_gen.set_opt_level(self.llvm_opt_level());
+        code_gen.set_verify(self.verify);
+        if let Some(path) = &self.debug_source {
+            code_gen.enable_debug_info(path);
+        }
+        code_gen.gen_program(hir)?;
+        Ok(code_gen)
+    }
+}
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let yaml = load_yaml!("cli.yml");
@@ -10,67 +108,287 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     if let Some(ref matches) = matches.subcommand_matches("compile") {
         let filepath = matches.value_of("INPUT").unwrap();
-        compile(filepath)?;
+        let emit = Emit::from_str(matches.value_of("emit").unwrap());
+        let out_path = matches.value_of("output").map(|s| s.to_string());
+        let mut link_args = values_of(matches, "lib").iter().map(|lib| format!("-l{}", lib)).collect::<Vec<_>>();
+        link_args.extend(values_of(matches, "link-arg"));
+        let mut options = CodeGenOptions::from_matches(matches);
+        if matches.is_present("debug") {
+            options.debug_source = Some(filepath.to_string());
+        }
+        compile(filepath, emit, out_path, &link_args, options, &denied_lints(matches)?)?;
     }
 
     if let Some(ref matches) = matches.subcommand_matches("run") {
         let filepath = matches.value_of("INPUT").unwrap();
-        compile(filepath)?;
-        run(filepath)?;
+        let backend = matches.value_of("backend").unwrap();
+        let status = run(filepath, backend, &values_of(matches, "lib"), CodeGenOptions::from_matches(matches),
+                         &denied_lints(matches)?)?;
+        if status != 0 {
+            std::process::exit(status);
+        }
+    }
+
+    if let Some(ref matches) = matches.subcommand_matches("check") {
+        let filepath = matches.value_of("INPUT").unwrap();
+        let format = matches.value_of("format").unwrap();
+        if !check(filepath, format, &denied_lints(matches)?)? {
+            std::process::exit(1);
+        }
+    }
+
+    if let Some(ref matches) = matches.subcommand_matches("fmt") {
+        let filepaths = matches.values_of("INPUT").unwrap().collect::<Vec<_>>();
+        if !fmt(&filepaths, matches.is_present("check"))? {
+            std::process::exit(1);
+        }
+    }
+
+    if let Some(_) = matches.subcommand_matches("repl") {
+        repl()?;
+    }
+
+    if let Some(_) = matches.subcommand_matches("lsp") {
+        let stdin = std::io::stdin();
+        let stdout = std::io::stdout();
+        shiika::lsp::run(&mut stdin.lock(), &mut stdout.lock())?;
     }
 
     Ok(())
 }
 
-fn compile(filepath: &str) -> Result<(), Box<dyn std::error::Error>> {
-    let str = fs::read_to_string(filepath)?;
-    let ast = shiika::parser::Parser::parse(&str)?;
+/// Values of an option which may be given multiple times
+fn values_of(matches: &clap::ArgMatches, name: &str) -> Vec<String> {
+    matches.values_of(name).map(|values| values.map(|s| s.to_string()).collect()).unwrap_or_default()
+}
+
+/// Lints given with `-D` (`warnings` means all of them)
+fn denied_lints(matches: &clap::ArgMatches) -> Result<Vec<Lint>, Box<dyn std::error::Error>> {
+    let names = values_of(matches, "deny");
+    if names.iter().any(|name| name == "warnings") {
+        return Ok(Lint::ALL.to_vec())
+    }
+    names.iter().map(|name| {
+        Lint::from_name(name).ok_or_else(|| {
+            let lint_names = Lint::ALL.iter().map(|lint| lint.name()).collect::<Vec<_>>();
+            format!("unknown lint `{}' (possible values: warnings, {})", name, lint_names.join(", ")).into()
+        })
+    }).collect()
+}
+
+/// Print the warnings of `hir` to stderr. Fails if any of them is denied
+fn lint(hir: &shiika::hir::Hir, filepath: &str, deny: &[Lint]) -> Result<(), Box<dyn std::error::Error>> {
+    let diagnostics = shiika::diagnostics::lint(hir, deny);
+    for d in &diagnostics {
+        eprintln!("{}", d.to_text(filepath));
+    }
+    if shiika::diagnostics::has_error(&diagnostics) {
+        return Err(Box::new(shiika::error::program_error("aborted due to denied warnings")))
+    }
+    Ok(())
+}
+
+fn compile(filepath: &str,
+           emit: Emit,
+           out_path: Option<String>,
+           link_args: &[String],
+           options: CodeGenOptions,
+           deny: &[Lint]) -> Result<(), Box<dyn std::error::Error>> {
+    let ast = shiika::loader::load(Path::new(filepath))?;
+    if emit == Emit::Ast {
+        return write_dump(&format!("{:#?}\n", ast), out_path);
+    }
+
     let stdlib = shiika::stdlib::Stdlib::create();
-    let hir = shiika::hir::Hir::from_ast(ast, stdlib)?;
-    let mut code_gen = shiika::code_gen::CodeGen::new();
-    code_gen.gen_program(hir)?;
-    code_gen.module.print_to_file(filepath.to_string() + ".ll")?;
+    let mut hir = shiika::hir::Hir::from_ast(ast, stdlib)?;
+    lint(&hir, filepath, deny)?;
+    options.optimize_hir(&mut hir);
+    if emit == Emit::Hir {
+        return write_dump(&format!("{:#?}\n", hir), out_path);
+    }
+
+    let out_path = out_path.unwrap_or_else(||
+        format!("{}.{}", filepath, emit.default_ext().unwrap())
+    );
+    emit_code(hir, emit, &out_path, link_args, options)
+}
+
+/// Generate the code of `hir` and write it to `out_path`
+#[cfg(feature = "codegen")]
+fn emit_code(hir: shiika::hir::Hir,
+             emit: Emit,
+             out_path: &str,
+             link_args: &[String],
+             options: CodeGenOptions) -> Result<(), Box<dyn std::error::Error>> {
+    let code_gen = options.gen_program(hir)?;
+    match emit {
+        Emit::LlvmIr => code_gen.module.print_to_file(out_path)?,
+        Emit::Bc => code_gen.write_bitcode(Path::new(out_path))?,
+        Emit::Asm => code_gen.write_native_file(Path::new(out_path), FileType::Assembly)?,
+        Emit::Obj => code_gen.write_native_file(Path::new(out_path), FileType::Object)?,
+        Emit::Exe => {
+            let obj_path = out_path.to_string() + ".o";
+            code_gen.write_native_file(Path::new(&obj_path), FileType::Object)?;
+            let result = shiika::linker::link_with(&obj_path, out_path, link_args);
+            fs::remove_file(obj_path)?;
+            result?
+        },
+        Emit::Ast | Emit::Hir => panic!("[BUG] already handled"),
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "codegen"))]
+fn emit_code(_hir: shiika::hir::Hir,
+             _emit: Emit,
+             _out_path: &str,
+             _link_args: &[String],
+             _options: CodeGenOptions) -> Result<(), Box<dyn std::error::Error>> {
+    Err(codegen_disabled("compile (other than --emit=ast/hir)"))
+}
+
+/// Error for the commands which need the LLVM backend
+#[cfg(not(feature = "codegen"))]
+fn codegen_disabled(what: &str) -> Box<dyn std::error::Error> {
+    let msg = format!("{} is not available (shiika is built without the `codegen` feature)", what);
+    Box::new(shiika::error::program_error(&msg))
+}
+
+/// Write AST/HIR dump to `out_path` (or stdout if not given)
+fn write_dump(dump: &str, out_path: Option<String>) -> Result<(), Box<dyn std::error::Error>> {
+    match out_path {
+        Some(path) => fs::write(path, dump)?,
+        None => print!("{}", dump),
+    }
     Ok(())
 }
 
-fn run(sk_path: &str) -> Result<(), Box<dyn std::error::Error>> {
-    let ll_path = sk_path.to_string() + ".ll";
-    let opt_ll_path = sk_path.to_string() + ".opt.ll";
-    let bc_path = sk_path.to_string() + ".bc";
-    let asm_path = sk_path.to_string() + ".s";
-    let out_path = sk_path.to_string() + ".out";
-
-    let mut cmd = Command::new("opt");
-    cmd.arg("-O3");
-    cmd.arg(ll_path);
-    cmd.arg("-o");
-    cmd.arg(bc_path.clone());
-    cmd.output()?;
-
-    let mut cmd = Command::new("llvm-dis");
-    cmd.arg(bc_path.clone());
-    cmd.arg("-o");
-    cmd.arg(opt_ll_path);
-    cmd.output()?;
-
-    let mut cmd = Command::new("llc");
-    cmd.arg(bc_path.clone());
-    cmd.output()?;
-
-    let mut cmd = Command::new("cc");
-    cmd.arg("-I/usr/local/Cellar/bdw-gc/7.6.0/include/");
-    cmd.arg("-L/usr/local/Cellar/bdw-gc/7.6.0/lib/");
-    cmd.arg("-lgc");
-    cmd.arg("-o");
-    cmd.arg(out_path.clone());
-    cmd.arg(asm_path.clone());
-    cmd.output()?;
-
-    fs::remove_file(bc_path)?;
-    fs::remove_file(asm_path)?;
-
-    let mut cmd = Command::new(out_path);
-    cmd.status()?;
+/// Print diagnostics of the program. Returns false if there are errors
+/// (including denied warnings)
+fn check(filepath: &str, format: &str, deny: &[Lint]) -> Result<bool, Box<dyn std::error::Error>> {
+    let str = fs::read_to_string(filepath)?;
+    let diagnostics = shiika::diagnostics::check_file(&str, Path::new(filepath), deny);
+    if format == "json" {
+        println!("{}", shiika::diagnostics::to_json_array(&diagnostics, filepath));
+    }
+    else {
+        for d in &diagnostics {
+            eprintln!("{}", d.to_text(filepath));
+        }
+    }
+    Ok(!shiika::diagnostics::has_error(&diagnostics))
+}
+
+/// Format the files. With `check`, print the files which are not
+/// formatted instead and return false if there are any
+fn fmt(filepaths: &[&str], check: bool) -> Result<bool, Box<dyn std::error::Error>> {
+    let mut ok = true;
+    for filepath in filepaths {
+        let str = fs::read_to_string(filepath)?;
+        let formatted = shiika::formatter::format(&str)?;
+        if formatted == str {
+            continue
+        }
+        if check {
+            println!("{}", filepath);
+            ok = false;
+        }
+        else {
+            fs::write(filepath, formatted)?;
+        }
+    }
+    Ok(ok)
+}
+
+/// Execute the program with the JIT compiler (or the interpreter)
+/// and return its exit status
+fn run(filepath: &str,
+       backend: &str,
+       libs: &[String],
+       options: CodeGenOptions,
+       deny: &[Lint]) -> Result<i32, Box<dyn std::error::Error>> {
+    let ast = shiika::loader::load(Path::new(filepath))?;
+    let stdlib = shiika::stdlib::Stdlib::create();
+    let mut hir = shiika::hir::Hir::from_ast(ast, stdlib)?;
+    lint(&hir, filepath, deny)?;
+    options.optimize_hir(&mut hir);
+    if backend == "interp" {
+        let stdout = std::io::stdout();
+        let mut out = stdout.lock();
+        let result = shiika::interp::Interpreter::new(&hir, &mut out).run();
+        out.flush()?;
+        return match result {
+            Ok(_) => Ok(0),
+            Err(shiika::error::Error { msg, details: shiika::error::ErrorDetails::Panic { trace }, .. }) => {
+                // Same format as `shiika_panic` of the compiled code
+                eprintln!("panic: {}", msg);
+                for frame in trace {
+                    eprintln!("  at {}", frame);
+                }
+                Ok(shiika::error::PANIC_EXIT_STATUS)
+            },
+            Err(e) => Err(Box::new(e)),
+        }
+    }
+    run_jit(hir, libs, options)
+}
+
+#[cfg(feature = "codegen")]
+fn run_jit(hir: shiika::hir::Hir,
+           libs: &[String],
+           options: CodeGenOptions) -> Result<i32, Box<dyn std::error::Error>> {
+    for lib in libs {
+        shiika::code_gen::load_native_library(lib)?;
+    }
+    let code_gen = options.gen_program(hir)?;
+    Ok(code_gen.run_jit()?)
+}
+
+#[cfg(not(feature = "codegen"))]
+fn run_jit(_hir: shiika::hir::Hir,
+           _libs: &[String],
+           _options: CodeGenOptions) -> Result<i32, Box<dyn std::error::Error>> {
+    Err(codegen_disabled("--backend=llvm"))
+}
 
+#[cfg(not(feature = "codegen"))]
+fn repl() -> Result<(), Box<dyn std::error::Error>> {
+    Err(codegen_disabled("repl"))
+}
+
+#[cfg(feature = "codegen")]
+fn repl() -> Result<(), Box<dyn std::error::Error>> {
+    let mut repl = shiika::repl::Repl::new();
+    let stdin = std::io::stdin();
+    let mut input = String::new();
+    loop {
+        print!("{}", if input.is_empty() { "shiika> " } else { "shiika* " });
+        std::io::stdout().flush()?;
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line)? == 0 {
+            println!();
+            break
+        }
+        input.push_str(&line);
+        if input.trim().is_empty() {
+            input.clear();
+            continue
+        }
+        if shiika::repl::is_incomplete(&input) {
+            continue
+        }
+
+        match repl.eval(&input) {
+            Ok(s) => if !s.is_empty() { println!("=> {}", s) },
+            Err(shiika::error::Error { msg, details: shiika::error::ErrorDetails::Panic { trace }, .. }) => {
+                println!("panic: {}", msg);
+                for frame in trace {
+                    println!("  at {}", frame);
+                }
+            },
+            Err(err) => println!("Error: {}", err),
+        }
+        input.clear();
+    }
     Ok(())
 }
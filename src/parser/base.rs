@@ -101,6 +101,27 @@ impl<'a> Parser<'a> {
         self.lexer.cur.clone()
     }
 
+    /// Get the position where the current token begins (spaces are skipped)
+    pub (in super) fn token_begin(&self) -> Cursor {
+        self.lexer.token_begin()
+    }
+
+    /// Set the range from `begin` to the end of the last consumed token
+    /// to `expr`
+    pub (in super) fn set_locs(&self, mut expr: AstExpression, begin: Cursor) -> AstExpression {
+        expr.locs = Some(self.locs_from(begin));
+        expr
+    }
+
+    /// The range from `begin` to the end of the last consumed token
+    pub (in super) fn locs_from(&self, begin: Cursor) -> LocationSpan {
+        LocationSpan {
+            file: self.filepath.clone(),
+            begin,
+            end: self.lexer.last_end.clone(),
+        }
+    }
+
     /// Rewind lexer position (backtrack)
     pub (in super) fn rewind_to(&mut self, cur: Cursor) {
         self.lexer.set_position(cur);
@@ -116,7 +137,12 @@ impl<'a> Parser<'a> {
             backtrace: backtrace::Backtrace::new(),
             details: ErrorDetails::ParseError {
                 location: self.lexer.cur.clone(),
-            }
+            },
+            locs: Some(LocationSpan {
+                file: self.filepath.clone(),
+                begin: self.lexer.cur.clone(),
+                end: self.lexer.cur.clone(),
+            }),
         }
     }
 
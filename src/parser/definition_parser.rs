@@ -21,7 +21,9 @@ impl<'a> Parser<'a> {
     fn parse_definition(&mut self) -> Result<Option<ast::Definition>, Error> {
         match self.current_token() {
             Token::KwClass => Ok(Some(self.parse_class_definition()?)),
+            Token::KwInterface => Ok(Some(self.parse_interface_definition()?)),
             Token::KwDef => Ok(Some(self.parse_method_definition()?)),
+            Token::At => Ok(Some(self.parse_primitive_method_definition()?)),
             Token::UpperWord(_) => Ok(Some(self.parse_const_definition()?)),
             _ => Ok(None),
         }
@@ -44,6 +46,32 @@ impl<'a> Parser<'a> {
             },
             token => return Err(parse_error!(self, "class name must start with A-Z but got {:?}", token))
         }
+
+        // Only methods can have type parameters (and their bounds) for now
+        if self.current_token_is(Token::LessThan) {
+            return Err(parse_error!(self, "class {} cannot have type parameters (generic classes are not supported yet)", name.0))
+        }
+
+        // `: I1, I2' (Optional)
+        let mut interfaces = vec![];
+        if self.next_nonspace_token() == Token::Colon {
+            self.skip_ws();
+            self.consume_token();
+            loop {
+                self.skip_ws();
+                match self.current_token() {
+                    Token::UpperWord(s) => {
+                        interfaces.push(ClassFirstname(s.to_string()));
+                        self.consume_token();
+                    },
+                    token => return Err(parse_error!(self, "interface name must start with A-Z but got {:?}", token))
+                }
+                self.skip_ws();
+                if !self.consume(Token::Comma) {
+                    break
+                }
+            }
+        }
         self.expect_sep()?;
 
         // Internal definitions
@@ -56,7 +84,48 @@ impl<'a> Parser<'a> {
         }
         
         self.lv -= 1;
-        Ok(ast::Definition::ClassDefinition { name, defs })
+        Ok(ast::Definition::ClassDefinition { name, interfaces, defs })
+    }
+
+    /// `interface I` followed by `def` without body and `end`
+    pub fn parse_interface_definition(&mut self) -> Result<ast::Definition, Error> {
+        self.debug_log("parse_interface_definition"); self.lv += 1;
+        assert!(self.consume(Token::KwInterface));
+        self.skip_ws();
+
+        let name = match self.current_token() {
+            Token::UpperWord(s) => {
+                let name = ClassFirstname(s.to_string());
+                self.consume_token();
+                name
+            },
+            token => return Err(parse_error!(self, "interface name must start with A-Z but got {:?}", token))
+        };
+        self.expect_sep()?;
+
+        let mut sigs = vec![];
+        loop {
+            match self.current_token() {
+                Token::KwDef => {
+                    self.consume_token();
+                    self.skip_ws();
+                    let (sig, is_class_method) = self.parse_method_signature()?;
+                    if is_class_method {
+                        return Err(parse_error!(self, "interface cannot have a class method: {:?}", sig.name))
+                    }
+                    sigs.push(sig);
+                    self.expect_sep()?;
+                },
+                Token::KwEnd => {
+                    self.consume_token();
+                    break
+                },
+                token => return Err(parse_error!(self, "missing `end' for interface {:?}; got {:?}", name, token))
+            }
+        }
+
+        self.lv -= 1;
+        Ok(ast::Definition::InterfaceDefinition { name, sigs })
     }
 
     pub fn parse_method_definition(&mut self) -> Result<ast::Definition, Error> {
@@ -88,6 +157,51 @@ impl<'a> Parser<'a> {
         }
     }
 
+    /// `@primitive def foo(bar) -> Baz` (has no body)
+    fn parse_primitive_method_definition(&mut self) -> Result<ast::Definition, Error> {
+        self.debug_log("parse_primitive_method_definition"); self.lv += 1;
+        // `@primitive'
+        assert!(self.consume(Token::At));
+        match self.current_token() {
+            Token::LowerWord(s) if s == "primitive" => { self.consume_token(); },
+            token => return Err(parse_error!(self, "unknown attribute {:?}", token))
+        }
+        self.skip_wsn();
+
+        // `def'
+        if !self.consume(Token::KwDef) {
+            return Err(parse_error!(self, "expected `def' after @primitive but got {:?}", self.current_token()))
+        }
+        self.skip_ws();
+
+        // `foo(bar) -> Baz`
+        let (sig, is_class_method) = self.parse_method_signature()?;
+
+        self.lv -= 1;
+        Ok(ast::Definition::PrimitiveMethodDefinition { sig, is_class_method })
+    }
+
+    /// `extern def c_name(x: Int) -> Float` (has no body)
+    pub fn parse_extern_definition(&mut self) -> Result<ast::Definition, Error> {
+        self.debug_log("parse_extern_definition"); self.lv += 1;
+        // `extern def'
+        assert!(self.consume(Token::KwExtern));
+        self.skip_ws();
+        if !self.consume(Token::KwDef) {
+            return Err(parse_error!(self, "expected `def' after extern but got {:?}", self.current_token()))
+        }
+        self.skip_ws();
+
+        // `c_name(x) -> Float`
+        let (sig, is_class_method) = self.parse_method_signature()?;
+        if is_class_method {
+            return Err(parse_error!(self, "extern function cannot be a class method: {:?}", sig.name))
+        }
+
+        self.lv -= 1;
+        Ok(ast::Definition::ExternDefinition { sig })
+    }
+
     pub fn parse_method_signature(&mut self) -> Result<(ast::AstMethodSignature, bool), Error> {
         let mut name = None;
         let params;
@@ -124,6 +238,7 @@ impl<'a> Parser<'a> {
                 Token::LessEq => { name_str = "<=" },
                 Token::GraterThan => { name_str = ">" },
                 Token::GraterEq => { name_str = ">=" },
+                Token::EqEq => { name_str = "==" },
                 token => {
                     return Err(parse_error!(self, "invalid method name {:?}", token))
                 }
@@ -131,6 +246,14 @@ impl<'a> Parser<'a> {
             name = Some(MethodFirstname(name_str.to_string()));
             self.consume_token();
         }
+
+        // Type parameters (optional)
+        let typarams = if self.current_token_is(Token::LessThan) {
+            self.parse_typarams()?
+        }
+        else {
+            vec![]
+        };
         self.skip_ws();
 
         // Params (optional)
@@ -149,15 +272,52 @@ impl<'a> Parser<'a> {
                 ret_typ = self.parse_ty()?;
             },
             _ => {
-                ret_typ = ast::Typ { name: "Void".to_string() };
+                ret_typ = ast::typ("Void");
                 self.skip_ws();
             }
         }
 
-        let sig = ast::AstMethodSignature { name: name.unwrap(), params, ret_typ };
+        let sig = ast::AstMethodSignature { name: name.unwrap(), typarams, params, ret_typ };
         Ok((sig, is_class_method))
     }
 
+    /// `<T, U : Bound>`
+    fn parse_typarams(&mut self) -> Result<Vec<ast::AstTyParam>, Error> {
+        assert!(self.consume(Token::LessThan));
+        let mut typarams: Vec<ast::AstTyParam> = vec![];
+        loop {
+            self.skip_ws();
+            let name = match self.current_token() {
+                Token::UpperWord(s) => {
+                    if typarams.iter().any(|t| &t.name == s) {
+                        return Err(parse_error!(self, "duplicated type parameter {}", s))
+                    }
+                    let name = s.to_string();
+                    self.consume_token();
+                    name
+                },
+                token => return Err(parse_error!(self, "type parameter must start with A-Z but got {:?}", token))
+            };
+            self.skip_ws();
+            let bound = if self.consume(Token::Colon) {
+                self.skip_ws();
+                let typ = self.parse_single_ty()?;
+                self.skip_ws();
+                Some(typ)
+            }
+            else {
+                None
+            };
+            typarams.push(ast::AstTyParam { name, bound });
+            match self.current_token() {
+                Token::Comma => { self.consume_token(); },
+                Token::GraterThan => { self.consume_token(); break },
+                token => return Err(parse_error!(self, "unexpected token in type parameters: {:?}", token))
+            }
+        }
+        Ok(typarams)
+    }
+
     fn parse_params(&mut self) -> Result<Vec<ast::Param>, Error> {
         let mut params = vec!();
 
@@ -166,7 +326,13 @@ impl<'a> Parser<'a> {
         loop {
             // Param
             match self.current_token() {
-                Token::LowerWord(_) => { params.push(self.parse_param()?) },
+                Token::LowerWord(_) => {
+                    let param = self.parse_param()?;
+                    if param.default_expr.is_none() && params.iter().any(|p: &ast::Param| p.default_expr.is_some()) {
+                        return Err(parse_error!(self, "param `{}' without default value must come before the ones with default values", param.name))
+                    }
+                    params.push(param)
+                },
                 Token::RParen       => { self.consume_token(); break },
                 token => return Err(parse_error!(self, "invalid token in method arguments: {:?}", token))
             }
@@ -184,10 +350,12 @@ impl<'a> Parser<'a> {
         let name;
 
         // Name
+        let begin = self.token_begin();
         match self.current_token() {
             Token::LowerWord(s) => { name = s.to_string(); self.consume_token(); },
             token => return Err(parse_error!(self, "invalid token as method param: {:?}", token))
         }
+        let locs = Some(self.locs_from(begin));
         self.skip_ws();
 
         // `:'
@@ -197,14 +365,62 @@ impl<'a> Parser<'a> {
         // Type
         let typ = self.parse_ty()?;
 
-        Ok(ast::Param { name, typ })
+        // Default value (optional)
+        let mut default_expr = None;
+        if self.next_nonspace_token() == Token::Equal {
+            self.skip_ws();
+            self.consume_token();
+            self.skip_wsn();
+            default_expr = Some(self.parse_operator_expr()?);
+        }
+
+        Ok(ast::Param { name, typ, default_expr, locs })
+    }
+
+    /// Parse a type (eg. `Int`, `Int?`, `Int | String`). `&` binds tighter
+    /// than `|`
+    pub (in super) fn parse_ty(&mut self) -> Result<ast::Typ, Error> {
+        let mut members = vec![self.parse_intersection_ty()?];
+        while self.next_nonspace_token() == Token::Or {
+            self.skip_ws();
+            self.consume_token();
+            self.skip_ws();
+            members.push(self.parse_intersection_ty()?);
+        }
+        if members.len() == 1 {
+            Ok(members.remove(0))
+        }
+        else {
+            Ok(ast::Typ::Union(members))
+        }
+    }
+
+    fn parse_intersection_ty(&mut self) -> Result<ast::Typ, Error> {
+        let mut members = vec![self.parse_single_ty()?];
+        while self.next_nonspace_token() == Token::And {
+            self.skip_ws();
+            self.consume_token();
+            self.skip_ws();
+            members.push(self.parse_single_ty()?);
+        }
+        if members.len() == 1 {
+            Ok(members.remove(0))
+        }
+        else {
+            Ok(ast::Typ::Intersection(members))
+        }
     }
 
-    fn parse_ty(&mut self) -> Result<ast::Typ, Error> {
+    fn parse_single_ty(&mut self) -> Result<ast::Typ, Error> {
         match self.current_token() {
             Token::UpperWord(s) => {
-                let typ = ast::Typ { name: s.to_string() };
+                let typ = ast::typ(s);
                 self.consume_token();
+                // Optional type (eg. `Int?`)
+                if self.current_token_is(Token::Question) {
+                    self.consume_token();
+                    return Ok(ast::opt_typ(typ))
+                }
                 Ok(typ)
             },
             token => Err(parse_error!(self, "invalid token as type: {:?}", token))
@@ -22,8 +22,10 @@ impl<'a> Parser<'a> {
 
     pub fn parse_var_decl(&mut self) -> Result<AstExpression, Error> {
         self.lv += 1; self.debug_log("parse_var_decl");
+        let begin = self.token_begin();
         let expr;
-        if self.current_token_is(Token::KwVar) {
+        if self.current_token_is(Token::KwVar) || self.current_token_is(Token::KwLet) {
+            let is_var = self.current_token_is(Token::KwVar);
             self.consume_token();
             self.skip_ws();
             match self.current_token() {
@@ -31,10 +33,21 @@ impl<'a> Parser<'a> {
                     let name = s.to_string();
                     self.consume_token();
                     self.skip_ws();
+                    // Type annotation
+                    let typ = if self.consume(Token::Colon) {
+                        self.skip_ws();
+                        let typ = self.parse_ty()?;
+                        self.skip_ws();
+                        Some(typ)
+                    }
+                    else {
+                        None
+                    };
                     self.expect(Token::Equal)?;  // TODO: `+=` etc.
                     self.skip_wsn();
                     let rhs = self.parse_operator_expr()?;
-                    expr = ast::var_decl(name, rhs);
+                    let decl = if is_var { ast::var_decl(name, typ, rhs) } else { ast::let_decl(name, typ, rhs) };
+                    expr = self.set_locs(decl, begin);
 
                 },
                 token => {
@@ -51,6 +64,7 @@ impl<'a> Parser<'a> {
 
     pub fn parse_and_or_expr(&mut self) -> Result<AstExpression, Error> {
         self.lv += 1; self.debug_log("parse_and_or_expr");
+        let begin = self.token_begin();
         let mut expr = self.parse_not_expr()?;
         self.skip_ws();
         loop {
@@ -58,12 +72,14 @@ impl<'a> Parser<'a> {
                 Token::KwAnd => {
                     self.consume_token();
                     self.skip_wsn();
-                    expr = ast::logical_and(expr, self.parse_not_expr()?);
+                    let right = self.parse_not_expr()?;
+                    expr = self.set_locs(ast::logical_and(expr, right), begin.clone());
                 },
                 Token::KwOr => {
                     self.consume_token();
                     self.skip_wsn();
-                    expr = ast::logical_or(expr, self.parse_not_expr()?);
+                    let right = self.parse_not_expr()?;
+                    expr = self.set_locs(ast::logical_or(expr, right), begin.clone());
                 },
                 _ => break,
             }
@@ -75,16 +91,17 @@ impl<'a> Parser<'a> {
 
     fn parse_not_expr(&mut self) -> Result<AstExpression, Error> {
         self.lv += 1; self.debug_log("parse_not_expr");
+        let begin = self.token_begin();
         let expr = match self.current_token() {
             Token::KwOr => {
                 self.skip_ws();
                 let inner = self.parse_not_expr()?;
-                ast::logical_not(inner)
+                self.set_locs(ast::logical_not(inner), begin)
             },
             Token::Bang => {
                 self.skip_ws();
                 let inner = self.parse_call_wo_paren()?;
-                ast::logical_not(inner)
+                self.set_locs(ast::logical_not(inner), begin)
             },
             _ => {
                 self.parse_call_wo_paren()?
@@ -100,6 +117,7 @@ impl<'a> Parser<'a> {
     //        operatorExpression
     fn parse_call_wo_paren(&mut self) -> Result<AstExpression, Error> {
         self.lv += 1; self.debug_log("parse_call_wo_paren");
+        let begin = self.token_begin();
 
         let token = self.current_token();
         if let Token::LowerWord(s) = token.clone() {
@@ -113,12 +131,8 @@ impl<'a> Parser<'a> {
                 self.debug_log(&format!("tried/args: {:?}", args));
                 if !args.is_empty() {
                     self.lv -= 1;
-                    return Ok(ast::method_call(
-                            None,
-                            &s,
-                            args,
-                            false,
-                            false))
+                    let call = ast::method_call(None, &s, args, false, false);
+                    return Ok(self.set_locs(call, begin))
                 }
                 self.rewind_to(cur)
             }
@@ -128,7 +142,7 @@ impl<'a> Parser<'a> {
             // foo bar, baz
             let args = self.parse_operator_exprs()?;
             if !args.is_empty() {
-                expr = ast::set_method_call_args(expr, args);
+                expr = self.set_locs(ast::set_method_call_args(expr, args), begin);
             }
         }
         self.lv -= 1;
@@ -149,27 +163,44 @@ impl<'a> Parser<'a> {
         self.lv += 1; self.debug_log("parse_operator_exprs");
         let mut v = vec![];
         if self.next_nonspace_token().value_starts() {
-            v.push(self.parse_operator_expr()?);
+            v.push(self.parse_arg()?);
             loop {
                 self.skip_ws();
                 if !self.current_token_is(Token::Comma) { break }
                 self.consume_token();
                 self.skip_wsn();
-                v.push( self.parse_operator_expr()? );
+                v.push( self.parse_arg()? );
             }
         }
         self.lv -= 1;
         Ok(v)
     }
 
+    /// Parse an operator_expr or a keyword argument (`name: expr`)
+    fn parse_arg(&mut self) -> Result<AstExpression, Error> {
+        let begin = self.token_begin();
+        if let Token::LowerWord(s) = self.current_token().clone() {
+            if self.peek_next_token() == Token::Colon {
+                self.consume_token();
+                self.consume_token();
+                self.skip_wsn();
+                let expr = self.parse_operator_expr()?;
+                return Ok(self.set_locs(ast::keyword_arg(&s, expr), begin))
+            }
+        }
+        self.parse_operator_expr()
+    }
+
     // operatorExpression:
     //   assignmentExpression |
     //   conditionalOperatorExpression
-    fn parse_operator_expr(&mut self) -> Result<AstExpression, Error> {
+    pub (in super) fn parse_operator_expr(&mut self) -> Result<AstExpression, Error> {
         self.lv += 1; self.debug_log("parse_operator_expr");
+        let begin = self.token_begin();
         let expr = self.parse_conditional_expr()?;
         if expr.is_lhs() && self.next_nonspace_token() == Token::Equal {
-            self.parse_assignment_expr(expr)
+            let assign = self.parse_assignment_expr(expr)?;
+            Ok(self.set_locs(assign, begin))
         }
         else {
             self.lv -= 1;
@@ -195,6 +226,7 @@ impl<'a> Parser<'a> {
     /// `a ? b : c`
     fn parse_conditional_expr(&mut self) -> Result<AstExpression, Error> {
         self.lv += 1; self.debug_log("parse_conditional_expr");
+        let begin = self.token_begin();
         let expr = self.parse_range_expr()?;
         if self.next_nonspace_token() == Token::Question {
             self.skip_ws(); assert!(self.consume(Token::Question));
@@ -205,7 +237,7 @@ impl<'a> Parser<'a> {
             self.skip_wsn();
             let else_expr = self.parse_operator_expr()?;
             self.lv -= 1;
-            Ok(ast::if_expr(expr, then_expr, Some(else_expr)))
+            Ok(self.set_locs(ast::if_expr(expr, then_expr, Some(else_expr)), begin))
         }
         else {
             self.lv -= 1;
@@ -234,13 +266,15 @@ impl<'a> Parser<'a> {
     /// `||`
     fn parse_operator_or(&mut self) -> Result<AstExpression, Error> {
         self.lv += 1; self.debug_log("parse_operator_or");
+        let begin = self.token_begin();
         let mut expr = self.parse_operator_and()?;
         let mut token = &self.next_nonspace_token();
         loop {
             if *token == Token::OrOr {
                 self.skip_ws(); assert!(self.consume(Token::OrOr));
                 self.skip_wsn();
-                expr = ast::logical_or(expr, self.parse_operator_and()?);
+                let right = self.parse_operator_and()?;
+                expr = self.set_locs(ast::logical_or(expr, right), begin.clone());
                 self.skip_ws();
                 token = self.current_token();
             }
@@ -255,13 +289,15 @@ impl<'a> Parser<'a> {
     /// `&&`
     fn parse_operator_and(&mut self) -> Result<AstExpression, Error> {
         self.lv += 1; self.debug_log("parse_operator_and");
+        let begin = self.token_begin();
         let mut expr = self.parse_equality_expr()?;
         let mut token = &self.next_nonspace_token();
         loop {
             if *token == Token::AndAnd {
                 self.skip_ws(); assert!(self.consume(Token::AndAnd));
                 self.skip_wsn();
-                expr = ast::logical_and(expr, self.parse_equality_expr()?);
+                let right = self.parse_equality_expr()?;
+                expr = self.set_locs(ast::logical_and(expr, right), begin.clone());
                 self.skip_ws();
                 token = self.current_token();
             }
@@ -273,14 +309,31 @@ impl<'a> Parser<'a> {
         Ok(expr)
     }
 
-    /// `==`, etc.
+    /// `==`, `is`, `as`, etc.
     fn parse_equality_expr(&mut self) -> Result<AstExpression, Error> {
         self.lv += 1; self.debug_log("parse_equality_expr");
+        let begin = self.token_begin();
         let left = self.parse_relational_expr()?;
         let op = match self.next_nonspace_token() {
             // TODO: <=> === =~ !~
             Token::EqEq => "==",
             Token::NotEq => "!=",
+            Token::KwIs => {
+                self.skip_ws();
+                self.consume_token();
+                self.skip_ws();
+                let typ = self.parse_ty()?;
+                self.lv -= 1;
+                return Ok(self.set_locs(ast::type_test(left, typ), begin))
+            },
+            Token::KwAs => {
+                self.skip_ws();
+                self.consume_token();
+                self.skip_ws();
+                let typ = self.parse_ty()?;
+                self.lv -= 1;
+                return Ok(self.set_locs(ast::cast(left, typ), begin))
+            },
             _ => {
                 self.lv -= 1;
                 return Ok(left)
@@ -298,11 +351,12 @@ impl<'a> Parser<'a> {
                                        false);
         let expr = if op == "!=" { ast::logical_not(call_eq) } else { call_eq };
         self.lv -= 1;
-        Ok(expr)
+        Ok(self.set_locs(expr, begin))
     }
 
     fn parse_relational_expr(&mut self) -> Result<AstExpression, Error> {
         self.lv += 1; self.debug_log("parse_relational_expr");
+        let begin = self.token_begin();
         let mut expr = self.parse_bitwise_or()?; // additive (> >= < <=) additive
         let mut nesting = false;
         loop {
@@ -322,11 +376,11 @@ impl<'a> Parser<'a> {
                 if let AstExpressionBody::MethodCall { arg_exprs, .. } = &expr.body {
                     let mid = arg_exprs[0].clone();
                     let compare = ast::method_call(Some(mid), op, vec![right], false, false);
-                    expr = ast::logical_and(expr, compare);
+                    expr = self.set_locs(ast::logical_and(expr, compare), begin.clone());
                 }
             }
             else {
-                expr = ast::method_call(Some(expr), op, vec![right], false, false);
+                expr = self.set_locs(ast::method_call(Some(expr), op, vec![right], false, false), begin.clone());
                 nesting = true;
             }
         }
@@ -381,6 +435,7 @@ impl<'a> Parser<'a> {
 
     fn parse_unary_minus_expr(&mut self) -> Result<AstExpression, Error> {
         self.lv += 1; self.debug_log("parse_unary_minus_expr");
+        let begin = self.token_begin();
         //TODO:
         //  parse_unary_minus_expr
         //  parse_power_expr
@@ -388,7 +443,7 @@ impl<'a> Parser<'a> {
         //  parse_secondary_expr
         let expr = if self.consume(Token::UnaryMinus) {
             let target = self.parse_secondary_expr()?;
-            ast::unary_expr(target, "-@")
+            self.set_locs(ast::unary_expr(target, "-@"), begin)
         }
         else {
             self.parse_secondary_expr()?
@@ -405,13 +460,14 @@ impl<'a> Parser<'a> {
     ///    OK: (if foo then bar else baz end).quux()
     fn parse_secondary_expr(&mut self) -> Result<AstExpression, Error> {
         self.lv += 1; self.debug_log("parse_secondary_expr");
+        let begin = self.token_begin();
         let expr = match self.current_token() {
             Token::KwIf => self.parse_if_expr(),
             Token::KwWhile => self.parse_while_expr(),
             _ => self.parse_primary_expr()
         }?;
         self.lv -= 1;
-        Ok(expr)
+        Ok(self.set_locs(expr, begin))
     }
 
     fn parse_if_expr(&mut self) -> Result<AstExpression, Error> {
@@ -462,11 +518,13 @@ impl<'a> Parser<'a> {
     // prim [ indexingArgumentList? ] not(EQUAL)
     fn parse_primary_expr(&mut self) -> Result<AstExpression, Error> {
         self.lv += 1; self.debug_log("parse_primary_expr");
+        let begin = self.token_begin();
         let mut expr = self.parse_atomic()?;
         loop {
             if self.next_nonspace_token() == Token::Dot { // TODO: Newline should also be allowed here (but Semicolon is not)
                 self.skip_ws();
-                expr = self.parse_method_chain(expr)?;
+                let call = self.parse_method_chain(expr)?;
+                expr = self.set_locs(call, begin.clone());
             }
             else {
                 break
@@ -526,6 +584,7 @@ impl<'a> Parser<'a> {
 
     fn parse_atomic(&mut self) -> Result<AstExpression, Error> {
         self.lv += 1; self.debug_log("parse_atomic");
+        let begin = self.token_begin();
         let token = self.current_token();
         let expr = match token {
             Token::LowerWord(s) => {
@@ -537,7 +596,7 @@ impl<'a> Parser<'a> {
                 let name = s.to_string();
                 self.parse_const_ref(name)
             },
-            Token::KwSelf | Token::KwTrue | Token::KwFalse => {
+            Token::KwSelf | Token::KwTrue | Token::KwFalse | Token::KwNil => {
                 let t = token.clone();
                 self.consume_token();
                 Ok(ast::pseudo_variable(t))
@@ -545,6 +604,11 @@ impl<'a> Parser<'a> {
             Token::Number(_) => {
                 self.parse_decimal_literal()
             },
+            Token::Str(s) => {
+                let content = s.to_string();
+                self.consume_token();
+                Ok(ast::string_literal(content))
+            },
             Token::LParen => {
                 self.parse_parenthesized_expr()
             },
@@ -553,7 +617,7 @@ impl<'a> Parser<'a> {
             }
         }?;
         self.lv -= 1;
-        Ok(expr)
+        Ok(self.set_locs(expr, begin))
     }
 
     // Method call with explicit parenthesis (eg. `foo(bar)`)
@@ -635,6 +699,7 @@ impl<'a> Parser<'a> {
                              func: F,
                              symbols: HashMap<Token, &str>) -> Result<AstExpression, Error> {
         self.lv += 1; self.debug_log(name);
+        let begin = self.token_begin();
         let left = func(self)?;
         let t = self.next_nonspace_token();
         let op = match symbols.get(&t) {
@@ -645,6 +710,6 @@ impl<'a> Parser<'a> {
         self.skip_wsn();
         let right = func(self)?;
         self.lv -= 1;
-        Ok(ast::bin_op_expr(left, op, right))
+        Ok(self.set_locs(ast::bin_op_expr(left, op, right), begin))
     }
 }
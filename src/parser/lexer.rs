@@ -9,6 +9,18 @@ pub struct Lexer<'a> {
     space_seen: bool,
     pub current_token: Token,
     next_cur: Option<Cursor>,
+    /// End of the last consumed token (spaces and separators are not counted)
+    pub last_end: Cursor,
+    /// Comments read so far (they are not passed to the parser)
+    pub comments: Vec<Comment>,
+}
+
+/// A comment in the source (kept for tools like the formatter)
+#[derive(Debug, PartialEq, Clone)]
+pub struct Comment {
+    /// Text of the comment including `#` (without the newline)
+    pub text: String,
+    pub location: Cursor,
 }
 
 /// Flags to decide a `-`, `+`, etc. is unary or binary.
@@ -50,6 +62,21 @@ impl Cursor {
         }
     }
 
+    /// Line number (0-origin)
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// Column number (0-origin)
+    pub fn col(&self) -> usize {
+        self.col
+    }
+
+    /// Number of bytes from the beginning of the source
+    pub fn pos(&self) -> usize {
+        self.pos
+    }
+
     /// Return the current char (None if eof)
     pub fn peek(&self, src: &str) -> Option<char> {
         src[self.pos..].chars().next()
@@ -91,6 +118,7 @@ enum CharType {
     LowerWord, // Keyword or identifier which starts with lower-case letter
     Symbol, // '+', '(', etc.
     Number, // '0'~'9'
+    Str, // '"'
     Eof,
 }
 
@@ -104,6 +132,8 @@ impl<'a> Lexer<'a> {
             space_seen: false,
             next_cur: None,
             current_token: Token::Bof,
+            last_end: Cursor::new(),
+            comments: vec![],
         };
         lexer.read_token();
         lexer
@@ -122,6 +152,17 @@ impl<'a> Lexer<'a> {
         format!("{:?} {:?}", self.current_token, self.state)
     }
 
+    /// Return the beginning of the current token (or the next one if the
+    /// current token is a space)
+    pub fn token_begin(&self) -> Cursor {
+        if self.current_token == Token::Space {
+            self.next_cur.as_ref().unwrap().clone()
+        }
+        else {
+            self.cur.clone()
+        }
+    }
+
     /// Remove the current token and read next
     ///
     /// # Examples
@@ -140,6 +181,9 @@ impl<'a> Lexer<'a> {
     pub fn consume_token(&mut self) -> Token {
         self.cur = self.next_cur.take().unwrap();
         let tok = self.current_token.clone(); // PERF: how not to clone?
+        if tok != Token::Space && tok != Token::Separator {
+            self.last_end = self.cur.clone();
+        }
         self.read_token();
         tok
     }
@@ -198,6 +242,7 @@ impl<'a> Lexer<'a> {
             CharType::LowerWord => self.read_lower_word(&mut next_next_cur, Some(&next_cur)),
             CharType::Symbol    => self.read_symbol(&mut next_next_cur),
             CharType::Number    => (self.read_number(&mut next_next_cur, Some(&next_cur)), None),
+            CharType::Str       => (self.read_str(&mut next_next_cur), None),
             CharType::Eof       => (self.read_eof(), None),
         };
         token
@@ -210,11 +255,16 @@ impl<'a> Lexer<'a> {
         let (token, new_state) = match self.char_type(c) {
             CharType::Space     => (self.read_space(&mut next_cur),            None),
             CharType::Separator => (self.read_separator(&mut next_cur),        None),
-            CharType::Comment   => (self.read_comment(&mut next_cur), None),
+            CharType::Comment   => {
+                let token = self.read_comment(&mut next_cur);
+                self.record_comment(&next_cur);
+                (token, None)
+            },
             CharType::UpperWord => (self.read_upper_word(&mut next_cur, None), Some(LexerState::ExprEnd)),
             CharType::LowerWord => self.read_lower_word(&mut next_cur, None),
             CharType::Symbol    => self.read_symbol(&mut next_cur),
             CharType::Number    => (self.read_number(&mut next_cur, None),     Some(LexerState::ExprEnd)),
+            CharType::Str       => (self.read_str(&mut next_cur),              Some(LexerState::ExprEnd)),
             CharType::Eof       => (self.read_eof(),                           None),
         };
         self.set_current_token(token);
@@ -249,12 +299,24 @@ impl<'a> Lexer<'a> {
     fn read_comment(&mut self, next_cur: &mut Cursor) -> Token {
         next_cur.proceed(self.src); // Skip the `#'
         loop {
+            if next_cur.peek(self.src).is_none() { break }
             let c = next_cur.proceed(self.src);
             if c == '\n' { break }
         }
         Token::Separator
     }
 
+    /// Remember the comment which starts at `self.cur` and ends at `end`.
+    /// Does nothing if it is already recorded (i.e. read again after
+    /// backtracking)
+    fn record_comment(&mut self, end: &Cursor) {
+        if self.comments.last().map_or(false, |c| c.location.pos >= self.cur.pos) {
+            return
+        }
+        let text = self.src[self.cur.pos..end.pos].trim_end().to_string();
+        self.comments.push(Comment { text, location: self.cur.clone() });
+    }
+
     fn read_upper_word(&mut self, next_cur: &mut Cursor, cur: Option<&Cursor>) -> Token {
         loop {
             match self.char_type(next_cur.peek(self.src)) {
@@ -281,9 +343,11 @@ impl<'a> Lexer<'a> {
         let s = &self.src[begin..next_cur.pos];
         let (token, state) = match s {
             "class" => (Token::KwClass, LexerState::ExprBegin),
+            "interface" => (Token::KwInterface, LexerState::ExprBegin),
             "end" => (Token::KwEnd, LexerState::ExprEnd),
             "def" => (Token::KwDef, LexerState::ExprBegin),
             "var" => (Token::KwVar, LexerState::ExprBegin),
+            "let" => (Token::KwLet, LexerState::ExprBegin),
             "and" => (Token::KwAnd, LexerState::ExprBegin),
             "or" => (Token::KwOr, LexerState::ExprBegin),
             "not" => (Token::KwNot, LexerState::ExprBegin),
@@ -295,6 +359,11 @@ impl<'a> Lexer<'a> {
             "self" => (Token::KwSelf, LexerState::ExprEnd),
             "true" => (Token::KwTrue, LexerState::ExprEnd),
             "false" => (Token::KwFalse, LexerState::ExprEnd),
+            "nil" => (Token::KwNil, LexerState::ExprEnd),
+            "is" => (Token::KwIs, LexerState::ExprBegin),
+            "as" => (Token::KwAs, LexerState::ExprBegin),
+            "require" => (Token::KwRequire, LexerState::ExprBegin),
+            "extern" => (Token::KwExtern, LexerState::ExprBegin),
             _ => (Token::LowerWord(s.to_string()), LexerState::ExprEnd),
         };
         (token, Some(state))
@@ -462,6 +531,31 @@ impl<'a> Lexer<'a> {
         Token::Number(self.src[begin..next_cur.pos].to_string())
     }
 
+    /// Read a string literal. An unterminated string ends at the end of
+    /// the file
+    fn read_str(&mut self, next_cur: &mut Cursor) -> Token {
+        next_cur.proceed(self.src); // Skip the opening `"'
+        let mut s = String::new();
+        loop {
+            match next_cur.peek(self.src) {
+                None => break,
+                Some('"') => { next_cur.proceed(self.src); break },
+                Some('\\') => {
+                    next_cur.proceed(self.src);
+                    if next_cur.peek(self.src).is_none() { break }
+                    let c = match next_cur.proceed(self.src) {
+                        'n' => '\n',
+                        't' => '\t',
+                        c => c,
+                    };
+                    s.push(c);
+                },
+                Some(_) => s.push(next_cur.proceed(self.src)),
+            }
+        }
+        Token::Str(s)
+    }
+
     fn read_eof(&mut self) -> Token {
         Token::Eof
     }
@@ -474,6 +568,7 @@ impl<'a> Lexer<'a> {
             ' ' | '\t' => CharType::Space,
             '\n' | ';' => CharType::Separator,
             '#' => CharType::Comment,
+            '"' => CharType::Str,
             '0'..='9' => CharType::Number,
             '(' | ')' | '[' | ']' | '<' | '>' | '{' | '}' |
             '+' | '-' | '*' | '/' | '%' | '=' | '!' |
@@ -15,15 +15,18 @@ pub mod token;
 pub mod lexer;
 mod definition_parser;
 mod expression_parser;
+use std::rc::Rc;
 use crate::ast;
 use crate::error::Error;
-use crate::parser::lexer::Lexer;
+use crate::parser::lexer::{Comment, Lexer};
 pub use crate::parser::token::Token;
 
 pub struct Parser<'a> {
     pub lexer: Lexer<'a>,
     /// For debug print
     pub lv: usize,
+    /// Path of the source (used for error messages)
+    filepath: Option<Rc<str>>,
 }
 
 impl<'a> Parser<'a> {
@@ -31,6 +34,7 @@ impl<'a> Parser<'a> {
         Parser {
             lexer: Lexer::new(src),
             lv: 0,
+            filepath: None,
         }
     }
 
@@ -39,28 +43,67 @@ impl<'a> Parser<'a> {
         parser.parse_program()
     }
 
+    /// Parse the program read from `filepath`. Locations in the AST and
+    /// the errors are marked with `filepath`
+    pub fn parse_file(src: &str, filepath: &str) -> Result<ast::Program, Error> {
+        let mut parser = Parser::new(src);
+        parser.filepath = Some(Rc::from(filepath));
+        parser.parse_program()
+    }
+
+    /// Parse the program and return it with the comments in the source
+    pub fn parse_with_comments(src: &str) -> Result<(ast::Program, Vec<Comment>), Error> {
+        let mut parser = Parser::new(src);
+        let program = parser.parse_program()?;
+        Ok((program, parser.lexer.comments))
+    }
+
     fn parse_program(&mut self) -> Result<ast::Program, Error> {
         self.skip_wsn();
-        let (toplevel_defs, exprs) = self.parse_toplevel_items()?;
+        let (toplevel_defs, exprs, requires) = self.parse_toplevel_items()?;
         // No tokens should be left 
         if *self.current_token() != Token::Eof {
             return Err(parse_error!(self, "unexpected token: {:?}", self.current_token()))
         }
-        Ok(ast::Program { toplevel_defs, exprs })
+        Ok(ast::Program { toplevel_defs, exprs, requires })
     }
 
-    fn parse_toplevel_items(&mut self) -> Result<(Vec<ast::Definition>, Vec<ast::AstExpression>), Error> {
+    fn parse_toplevel_items(&mut self) -> Result<(Vec<ast::Definition>, Vec<ast::AstExpression>, Vec<ast::Require>), Error> {
         let mut defs = vec![];
         let mut exprs = vec![];
+        let mut requires = vec![];
         loop {
             match self.current_token() {
                 Token::KwClass => defs.push(self.parse_class_definition()?),
+                Token::KwInterface => defs.push(self.parse_interface_definition()?),
                 Token::KwDef => defs.push(self.parse_method_definition()?),
+                Token::KwRequire => requires.push(self.parse_require()?),
+                Token::KwExtern => defs.push(self.parse_extern_definition()?),
                 Token::Eof | Token::KwEnd => break,
                 _ => exprs.push(self.parse_expr()?),
             }
             self.skip_wsn();
         }
-        Ok((defs, exprs))
+        Ok((defs, exprs, requires))
+    }
+
+    /// `require "path"`
+    fn parse_require(&mut self) -> Result<ast::Require, Error> {
+        let begin = self.token_begin();
+        assert!(self.consume(Token::KwRequire));
+        self.skip_ws();
+        match self.current_token() {
+            Token::Str(s) => {
+                let path = s.to_string();
+                self.consume_token();
+                let locs = ast::LocationSpan {
+                    file: self.filepath.clone(),
+                    begin,
+                    end: self.lexer.last_end.clone(),
+                };
+                Ok(ast::Require { path, locs })
+            },
+            token => Err(parse_error!(self, "expected a file path after `require' but got {:?}", token))
+        }
     }
 }
@@ -7,6 +7,7 @@ pub enum Token {
     UpperWord(String),
     LowerWord(String),
     Number(String),
+    Str(String),  // "..." (escape sequences are already processed)
     // Symbols
     LParen,       //  ( 
     RParen,       //  ) 
@@ -46,9 +47,11 @@ pub enum Token {
     RShift,       //  >>
     // Keywords
     KwClass,
+    KwInterface,
     KwEnd,
     KwDef,
     KwVar,
+    KwLet,
     KwAnd,
     KwOr,
     KwNot,
@@ -60,6 +63,11 @@ pub enum Token {
     KwSelf,
     KwTrue,
     KwFalse,
+    KwNil,
+    KwIs,
+    KwAs,
+    KwRequire,
+    KwExtern,
 }
 
 impl Token {
@@ -79,6 +87,7 @@ impl Token {
             Token::UpperWord(_) => true,
             Token::LowerWord(_) => true,
             Token::Number(_) => true,
+            Token::Str(_) => true,
             // Symbols
             Token::LParen => true,        //  ( 
             Token::RParen => false,       //  ) 
@@ -118,9 +127,11 @@ impl Token {
             Token::RShift => false,    //  >>
             // Keywords
             Token::KwClass => false,
+            Token::KwInterface => false,
             Token::KwEnd => false,
             Token::KwDef => false,
             Token::KwVar => false,
+            Token::KwLet => false,
             Token::KwAnd => false,
             Token::KwOr => false,
             Token::KwNot => true,
@@ -132,6 +143,11 @@ impl Token {
             Token::KwSelf => true,
             Token::KwTrue => true,
             Token::KwFalse => true,
+            Token::KwNil => true,
+            Token::KwIs => false,
+            Token::KwAs => false,
+            Token::KwRequire => false,
+            Token::KwExtern => false,
         }
     }
 }
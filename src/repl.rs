@@ -0,0 +1,217 @@
+/// Interactive evaluation of Shiika programs
+///
+/// Each input is compiled together with the definitions and assignments
+/// entered so far, and then executed with the JIT compiler. The values of
+/// the assignments are saved in slots, so that later inputs read them
+/// instead of evaluating the right hand sides again.
+use std::collections::HashMap;
+use crate::ast;
+use crate::ast::AstExpressionBody;
+use crate::code_gen;
+use crate::code_gen::{CodeGen, REPL_SLOT_SIZE};
+use crate::error;
+use crate::error::{Error, ErrorDetails};
+use crate::hir::{Hir, HirExpression, HirExpressionBase};
+use crate::loader;
+use crate::names::*;
+use crate::parser::Parser;
+use crate::stdlib::Stdlib;
+use crate::ty;
+
+/// Maximum number of the slots
+const SLOTS_MAX: usize = 4096;
+
+pub struct Repl {
+    /// Definitions (classes, constants) entered so far
+    toplevel_defs: Vec<ast::Definition>,
+    /// Assignments to constants and local variables entered so far.
+    /// They are compiled again for each input to declare the variables
+    assignments: Vec<ast::AstExpression>,
+    /// The slot which holds the value of each of `assignments`
+    assignment_slots: Vec<usize>,
+    /// The slot which holds the value of each constant
+    const_slots: HashMap<ConstFullname, usize>,
+    /// Memory of the slots. Never reallocated since the compiled code
+    /// refers to it by address
+    slots: Box<[[u64; 2]]>,
+    /// Number of the slots in use
+    n_slots: usize,
+    /// Tags of the union values saved in the slots
+    type_tags: HashMap<ClassFullname, u64>,
+    /// Compiled inputs. Kept alive because the values in the slots may
+    /// point to their memory (eg. string literals)
+    sessions: Vec<CodeGen>,
+    /// Whether `slots` is registered to the GC
+    gc_roots_added: bool,
+}
+
+impl Repl {
+    pub fn new() -> Repl {
+        Repl {
+            toplevel_defs: vec![],
+            assignments: vec![],
+            assignment_slots: vec![],
+            const_slots: HashMap::new(),
+            slots: vec![[0; 2]; SLOTS_MAX].into_boxed_slice(),
+            n_slots: 0,
+            type_tags: HashMap::new(),
+            sessions: vec![],
+            gc_roots_added: false,
+        }
+    }
+
+    /// Evaluate `src` and return its value and type (eg. `3 : Int`).
+    /// Returns an empty string if `src` has no value (eg. class definition)
+    ///
+    /// Definitions and assignments in `src` are kept only when the
+    /// evaluation succeeded
+    pub fn eval(&mut self, src: &str) -> Result<String, Error> {
+        let prog = loader::load_str(src, None)?;
+        let has_value = !prog.exprs.is_empty();
+
+        let mut toplevel_defs = self.toplevel_defs.clone();
+        toplevel_defs.extend(prog.toplevel_defs.iter().cloned());
+        let mut exprs = self.assignments.clone();
+        exprs.extend(prog.exprs.iter().cloned());
+        let n_main_exprs = exprs.len();
+
+        let mut hir = Hir::from_ast(ast::Program { toplevel_defs, exprs, requires: vec![] }, Stdlib::create())?;
+        if !has_value {
+            hir.main_exprs.ty = ty::raw("Void");
+        }
+        let mut allocation = SlotAllocation {
+            assignment_slots: self.assignment_slots.clone(),
+            const_slots: self.const_slots.clone(),
+            n_slots: self.n_slots,
+        };
+        let main_exprs = std::mem::replace(&mut hir.main_exprs.exprs, vec![]);
+        hir.main_exprs.exprs = allocation.rewrite(main_exprs, n_main_exprs, self.assignments.len())?;
+
+        let result_ty = hir.main_exprs.ty.clone();
+        let mut code_gen = CodeGen::new();
+        code_gen.set_type_tags(self.type_tags.clone());
+        code_gen.gen_repl_program(hir, self.slots.as_ptr() as u64)?;
+        let value = code_gen.run_jit_repl(&result_ty)?;
+        if !self.gc_roots_added {
+            code_gen::add_gc_roots(self.slots.as_ptr() as *const u8, SLOTS_MAX * REPL_SLOT_SIZE);
+            self.gc_roots_added = true;
+        }
+
+        self.toplevel_defs.extend(prog.toplevel_defs);
+        self.assignments.extend(prog.exprs.into_iter().filter(is_assignment));
+        self.assignment_slots = allocation.assignment_slots;
+        self.const_slots = allocation.const_slots;
+        self.n_slots = allocation.n_slots;
+        self.type_tags = code_gen.type_tags();
+        self.sessions.push(code_gen);
+        if result_ty.is_void_type() {
+            Ok("".to_string())
+        }
+        else {
+            Ok(format!("{} : {}", value, result_ty.fullname))
+        }
+    }
+}
+
+/// Slots allocated while compiling an input
+struct SlotAllocation {
+    assignment_slots: Vec<usize>,
+    const_slots: HashMap<ConstFullname, usize>,
+    n_slots: usize,
+}
+
+impl SlotAllocation {
+    /// Rewrite the main expressions so that the assignments save their
+    /// values to the slots and the earlier ones load them.
+    /// `exprs` consists of the constant initializations and
+    /// `n_main_exprs` expressions of the input, whose first `n_earlier`
+    /// are the assignments entered before
+    fn rewrite(&mut self,
+               exprs: Vec<HirExpression>,
+               n_main_exprs: usize,
+               n_earlier: usize) -> Result<Vec<HirExpression>, Error> {
+        let n_inits = exprs.len() - n_main_exprs;
+        exprs.into_iter().enumerate().map(|(i, mut expr)| {
+            match &mut expr.node {
+                HirExpressionBase::HirConstAssign { fullname, rhs } => {
+                    if i < n_inits {
+                        self.rewrite_const_init(fullname, rhs)?;
+                    }
+                    else {
+                        // The value is saved by the initialization
+                        let slot = self.const_slots[&*fullname];
+                        replace_rhs(rhs, |rhs| Hir::repl_load(slot, rhs.ty));
+                        if i - n_inits >= n_earlier {
+                            self.assignment_slots.push(slot);
+                        }
+                    }
+                },
+                HirExpressionBase::HirLVarAssign { rhs, .. } if i >= n_inits => {
+                    if i - n_inits < n_earlier {
+                        let slot = self.assignment_slots[i - n_inits];
+                        replace_rhs(rhs, |rhs| Hir::repl_load(slot, rhs.ty));
+                    }
+                    else {
+                        let slot = self.allocate()?;
+                        self.assignment_slots.push(slot);
+                        replace_rhs(rhs, |rhs| Hir::repl_store(slot, rhs));
+                    }
+                },
+                _ => (),
+            }
+            Ok(expr)
+        }).collect()
+    }
+
+    /// Save the value of a constant to a slot when it is initialized
+    /// for the first time and load it afterwards
+    fn rewrite_const_init(&mut self,
+                          fullname: &ConstFullname,
+                          rhs: &mut Box<HirExpression>) -> Result<(), Error> {
+        if let HirExpressionBase::HirClassLiteral { .. } = rhs.node {
+            return Ok(())
+        }
+        match self.const_slots.get(fullname) {
+            Some(&slot) => replace_rhs(rhs, |rhs| Hir::repl_load(slot, rhs.ty)),
+            None => {
+                let slot = self.allocate()?;
+                self.const_slots.insert(fullname.clone(), slot);
+                replace_rhs(rhs, |rhs| Hir::repl_store(slot, rhs));
+            }
+        }
+        Ok(())
+    }
+
+    fn allocate(&mut self) -> Result<usize, Error> {
+        if self.n_slots >= SLOTS_MAX {
+            return Err(error::program_error(&format!("too many assignments in the REPL (max: {})", SLOTS_MAX)))
+        }
+        self.n_slots += 1;
+        Ok(self.n_slots - 1)
+    }
+}
+
+fn replace_rhs<F>(rhs: &mut Box<HirExpression>, f: F)
+    where F: FnOnce(HirExpression) -> HirExpression {
+    let placeholder = Hir::omitted_arg(&rhs.ty);
+    let old = std::mem::replace(&mut **rhs, placeholder);
+    **rhs = f(old);
+}
+
+/// Return true if `src` is a beginning of a valid program
+/// (eg. `class A` without `end`)
+pub fn is_incomplete(src: &str) -> bool {
+    match Parser::parse(src) {
+        Err(Error { details: ErrorDetails::ParseError { location }, .. }) => {
+            location.pos() >= src.trim_end().len()
+        },
+        _ => false,
+    }
+}
+
+fn is_assignment(expr: &ast::AstExpression) -> bool {
+    match expr.body {
+        AstExpressionBody::LVarAssign { .. } | AstExpressionBody::ConstAssign { .. } => true,
+        _ => false,
+    }
+}
@@ -1,7 +0,0 @@
-use crate::hir::*;
-//use crate::stdlib::create_method;
-
-pub fn create_methods() -> Vec<SkMethod> {
-    vec![
-    ]
-}
@@ -1,80 +1,136 @@
-use crate::hir::*;
-use crate::stdlib::create_method;
+use inkwell::values::*;
+use crate::code_gen::CodeGen;
+use crate::error::Error;
+use crate::stdlib::{Primitives, SHIFT_RANGE_MSG};
 
-pub fn create_methods() -> Vec<SkMethod> {
-    vec![
-
-    create_method("Int", "<(other: Int) -> Bool", |code_gen, function| {
+pub fn add_primitives(h: &mut Primitives) {
+    h.insert("Int#<", |code_gen, function| {
         let val1 = function.get_params()[0].into_int_value();
         let val2 = function.get_params()[1].into_int_value();
         let result = code_gen.builder.build_int_compare(inkwell::IntPredicate::SLT, val1, val2, "result");
         code_gen.builder.build_return(Some(&result));
         Ok(())
-    }),
+    });
 
-    create_method("Int", "+(other: Int) -> Int", |code_gen, function| {
+    h.insert("Int#==", |code_gen, function| {
         let val1 = function.get_params()[0].into_int_value();
         let val2 = function.get_params()[1].into_int_value();
-        let result = code_gen.builder.build_int_add(val1, val2, "result");
+        let result = code_gen.builder.build_int_compare(inkwell::IntPredicate::EQ, val1, val2, "result");
         code_gen.builder.build_return(Some(&result));
         Ok(())
-    }),
+    });
 
-    create_method("Int", "-(other: Int) -> Int", |code_gen, function| {
-        let val1 = function.get_params()[0].into_int_value();
-        let val2 = function.get_params()[1].into_int_value();
-        let result = code_gen.builder.build_int_sub(val1, val2, "result");
+    h.insert("Int#+", |code_gen, function| {
+        gen_checked_arith(code_gen, function, "llvm.sadd.with.overflow.i32")
+    });
+
+    h.insert("Int#-", |code_gen, function| {
+        gen_checked_arith(code_gen, function, "llvm.ssub.with.overflow.i32")
+    });
+
+    h.insert("Int#*", |code_gen, function| {
+        gen_checked_arith(code_gen, function, "llvm.smul.with.overflow.i32")
+    });
+
+    h.insert("Int#/", |code_gen, function| {
+        let (val1, val2) = gen_division_checks(code_gen, function);
+        let result = code_gen.builder.build_int_signed_div(val1, val2, "result");
+        code_gen.builder.build_return(Some(&result));
+        Ok(())
+    });
+
+    h.insert("Int#%", |code_gen, function| {
+        let (val1, val2) = gen_division_checks(code_gen, function);
+        let result = code_gen.builder.build_int_signed_rem(val1, val2, "result");
         code_gen.builder.build_return(Some(&result));
         Ok(())
-    }),
+    });
 
-    create_method("Int", "&(other: Int) -> Int", |code_gen, function| {
+    h.insert("Int#&", |code_gen, function| {
         let val1 = function.get_params()[0].into_int_value();
         let val2 = function.get_params()[1].into_int_value();
         let result = code_gen.builder.build_and(val1, val2, "and");
         code_gen.builder.build_return(Some(&result));
         Ok(())
-    }),
+    });
 
-    create_method("Int", "|(other: Int) -> Int", |code_gen, function| {
+    h.insert("Int#|", |code_gen, function| {
         let val1 = function.get_params()[0].into_int_value();
         let val2 = function.get_params()[1].into_int_value();
         let result = code_gen.builder.build_or(val1, val2, "or");
         code_gen.builder.build_return(Some(&result));
         Ok(())
-    }),
+    });
 
-    create_method("Int", "^(other: Int) -> Int", |code_gen, function| {
+    h.insert("Int#^", |code_gen, function| {
         let val1 = function.get_params()[0].into_int_value();
         let val2 = function.get_params()[1].into_int_value();
         let result = code_gen.builder.build_xor(val1, val2, "xor");
         code_gen.builder.build_return(Some(&result));
         Ok(())
-    }),
+    });
 
-    create_method("Int", "<<(other: Int) -> Int", |code_gen, function| {
-        let val1 = function.get_params()[0].into_int_value();
-        let val2 = function.get_params()[1].into_int_value();
+    h.insert("Int#<<", |code_gen, function| {
+        let (val1, val2) = gen_shift_check(code_gen, function);
         let result = code_gen.builder.build_left_shift(val1, val2, "lshift");
         code_gen.builder.build_return(Some(&result));
         Ok(())
-    }),
+    });
 
-    create_method("Int", ">>(other: Int) -> Int", |code_gen, function| {
-        let val1 = function.get_params()[0].into_int_value();
-        let val2 = function.get_params()[1].into_int_value();
+    h.insert("Int#>>", |code_gen, function| {
+        let (val1, val2) = gen_shift_check(code_gen, function);
         let result = code_gen.builder.build_right_shift(val1, val2, true, "rshift");
         code_gen.builder.build_return(Some(&result));
         Ok(())
-    }),
+    });
 
-    create_method("Int", "to_f() -> Float", |code_gen, function| {
+    h.insert("Int#to_f", |code_gen, function| {
         let int = function.get_params()[0].into_int_value();
         let float = code_gen.builder.build_signed_int_to_float(int, code_gen.f64_type, "float");
         code_gen.builder.build_return(Some(&float));
         Ok(())
-    }),
+    });
+}
 
-    ]
+/// Call the `llvm.*.with.overflow.i32` intrinsic and panic on overflow
+fn gen_checked_arith(code_gen: &CodeGen, function: &FunctionValue, intrinsic: &str) -> Result<(), Error> {
+    let val1 = function.get_params()[0];
+    let val2 = function.get_params()[1];
+    let func = code_gen.module.get_function(intrinsic).unwrap();
+    let pair = code_gen.builder.build_call(func, &[val1, val2], "pair")
+        .try_as_basic_value().left().unwrap().into_struct_value();
+    let result = code_gen.builder.build_extract_value(pair, 0, "result").unwrap();
+    let overflow = code_gen.builder.build_extract_value(pair, 1, "overflow").unwrap().into_int_value();
+    code_gen.gen_panic_if(function, overflow, "integer overflow");
+    code_gen.builder.build_return(Some(&result));
+    Ok(())
 }
 
+/// Panic if the divisor is zero or the result overflows (MIN / -1)
+fn gen_division_checks(code_gen: &CodeGen, function: &FunctionValue) -> (IntValue, IntValue) {
+    let val1 = function.get_params()[0].into_int_value();
+    let val2 = function.get_params()[1].into_int_value();
+    let zero = code_gen.i32_type.const_int(0, false);
+    let is_zero = code_gen.builder.build_int_compare(inkwell::IntPredicate::EQ, val2, zero, "is_zero");
+    code_gen.gen_panic_if(function, is_zero, "division by zero");
+
+    let min = code_gen.i32_type.const_int(std::i32::MIN as u64, true);
+    let minus_one = code_gen.i32_type.const_int(-1i64 as u64, true);
+    let is_min = code_gen.builder.build_int_compare(inkwell::IntPredicate::EQ, val1, min, "is_min");
+    let is_minus_one = code_gen.builder.build_int_compare(inkwell::IntPredicate::EQ, val2, minus_one, "is_minus_one");
+    let overflow = code_gen.builder.build_and(is_min, is_minus_one, "overflow");
+    code_gen.gen_panic_if(function, overflow, "integer overflow");
+    (val1, val2)
+}
+
+/// Panic unless the shift count is in `0..32` (see `is_valid_shift_count`)
+/// and return the operands
+fn gen_shift_check(code_gen: &CodeGen, function: &FunctionValue) -> (IntValue, IntValue) {
+    let val1 = function.get_params()[0].into_int_value();
+    let val2 = function.get_params()[1].into_int_value();
+    // Negative counts are also out of range as unsigned integers
+    let max = code_gen.i32_type.const_int(32, false);
+    let out_of_range = code_gen.builder.build_int_compare(inkwell::IntPredicate::UGE, val2, max, "out_of_range");
+    code_gen.gen_panic_if(function, out_of_range, SHIFT_RANGE_MSG);
+    (val1, val2)
+}
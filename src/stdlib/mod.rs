@@ -1,83 +1,164 @@
-mod bool;
+/// The standard library
+///
+/// Classes and methods are defined in prelude.sk. Methods marked with
+/// `@primitive` there are implemented in the submodules of this module.
+#[cfg(feature = "codegen")]
 mod float;
+#[cfg(feature = "codegen")]
 mod int;
+#[cfg(feature = "codegen")]
 mod math;
+#[cfg(feature = "codegen")]
 mod object;
-mod void;
 use std::collections::HashMap;
+use crate::ast;
 use crate::names::*;
+use crate::parser::Parser;
 use crate::ty;
 use crate::hir::*;
 
+const PRELUDE: &str = include_str!("prelude.sk");
+
+/// `Int#<<` and `Int#>>` panic with this message unless the shift count is
+/// in `0..32` (the same for the generated code, the interpreter and the
+/// constant folding)
+pub const SHIFT_RANGE_MSG: &str = "shift count out of range";
+
+/// Return true if `count` is a valid shift count of `Int#<<` and `Int#>>`
+pub fn is_valid_shift_count(count: i32) -> bool {
+    0 <= count && count < 32
+}
+/// File name of the prelude in the locations of its expressions
+pub const PRELUDE_PATH: &str = "prelude.sk";
+
+/// Rust implementations of the `@primitive` methods (key is the method
+/// fullname, eg. "Int#+")
+#[cfg(feature = "codegen")]
+pub type Primitives = HashMap<&'static str, GenMethodBody>;
+/// Without the `codegen` feature the primitives are only implemented by
+/// the interpreter, so there is nothing to look up
+#[cfg(not(feature = "codegen"))]
+pub type Primitives = HashMap<&'static str, ()>;
+
 pub struct Stdlib {
     pub sk_classes: HashMap<ClassFullname, SkClass>,
     pub sk_methods: HashMap<ClassFullname, Vec<SkMethod>>,
+    /// Classes of prelude.sk which contain the methods written in Shiika.
+    /// They are converted into HIR together with the user program
+    pub prelude_defs: Vec<ast::Definition>,
 }
 
 impl Stdlib {
     /// Create empty Stdlib (for tests)
     pub fn empty() -> Stdlib {
-        Stdlib { 
+        Stdlib {
             sk_classes: HashMap::new(),
             sk_methods: HashMap::new(),
+            prelude_defs: vec![],
         }
     }
 
     pub fn create() -> Stdlib {
-        let mut sk_classes = HashMap::new();
-        let mut sk_methods = HashMap::new();
-        let items = vec![
-            ("Bool", bool::create_methods(), vec![]),
-            ("Float", float::create_methods(), vec![]),
-            ("Int", int::create_methods(), vec![]),
-            ("Object", object::create_methods(), vec![]),
-            ("Void", void::create_methods(), vec![]),
-            ("Math", vec![], math::create_class_methods()),
-        ];
-        for t in items.into_iter() {
-            let (name, imethods, cmethods) = t;
-            let super_name = if name == "Object" { None }
-                             else { Some(ClassFullname("Object".to_string())) };
-            sk_classes.insert(
-                ClassFullname(name.to_string()),
-                SkClass {
-                    fullname: ClassFullname(name.to_string()),
-                    superclass_fullname: super_name,
-                    instance_ty: ty::raw(name),
-                    method_sigs: imethods.iter().map(|x|
-                        (x.signature.first_name().clone(), x.signature.clone())
-                    ).collect(),
-                }
-            );
-            sk_classes.insert(
-                ClassFullname("Meta:".to_string() + name),
-                SkClass {
-                    fullname: ClassFullname("Meta:".to_string() + name),
-                    superclass_fullname: Some(ClassFullname("Meta:Object".to_string())),
-                    instance_ty: ty::meta(name),
-                    method_sigs: cmethods.iter().map(|x|
-                        (x.signature.first_name().clone(), x.signature.clone())
-                    ).collect(),
-                }
-            );
-            sk_methods.insert(
-                ClassFullname(name.to_string()),
-                imethods.into_iter().chain(cmethods).collect()
-            );
-        };
-        Stdlib { sk_classes, sk_methods }
+        let prelude = Parser::parse_file(PRELUDE, PRELUDE_PATH)
+            .unwrap_or_else(|err| panic!("[BUG] failed to parse the prelude: {}", err));
+        #[cfg_attr(not(feature = "codegen"), allow(unused_mut))]
+        let mut primitives = Primitives::new();
+        #[cfg(feature = "codegen")]
+        {
+            float::add_primitives(&mut primitives);
+            int::add_primitives(&mut primitives);
+            math::add_primitives(&mut primitives);
+            object::add_primitives(&mut primitives);
+        }
+
+        let mut stdlib = Stdlib::empty();
+        for def in prelude.toplevel_defs {
+            match def {
+                ast::Definition::ClassDefinition { name, defs, .. } => {
+                    stdlib.add_class(&name.0, defs, &mut primitives);
+                },
+                _ => panic!("[BUG] unexpected definition in the prelude: {:?}", def),
+            }
+        }
+        if let Some(name) = primitives.keys().next() {
+            panic!("[BUG] primitive {} is not declared in the prelude", name);
+        }
+        stdlib
     }
-}
 
-fn create_method(class_name: &str,
-                      sig_str: &str,
-                      gen: GenMethodBody) -> SkMethod {
-    let mut parser = crate::parser::Parser::new(sig_str);
-    let (ast_sig, _) = parser.parse_method_signature().unwrap();
-    let sig = crate::hir::create_signature(class_name.to_string(), &ast_sig);
+    fn add_class(&mut self, name: &str, defs: Vec<ast::Definition>, primitives: &mut Primitives) {
+        let meta_name = "Meta:".to_string() + name;
+        let mut imethods = vec![];
+        let mut cmethods = vec![];
+        let mut sk_methods = vec![];
+        let mut shiika_defs = vec![];
+        for def in defs {
+            match &def {
+                ast::Definition::PrimitiveMethodDefinition { sig, is_class_method } => {
+                    let class_name = if *is_class_method { meta_name.as_str() } else { name };
+                    let signature = create_signature(class_name.to_string(), sig);
+                    let body = primitive_body(primitives, &signature);
+                    if *is_class_method { cmethods.push(signature.clone()) }
+                    else { imethods.push(signature.clone()) }
+                    sk_methods.push(SkMethod { signature, body });
+                },
+                ast::Definition::InstanceMethodDefinition { sig, .. } => {
+                    imethods.push(create_signature(name.to_string(), sig));
+                    shiika_defs.push(def.clone());
+                },
+                ast::Definition::ClassMethodDefinition { sig, .. } => {
+                    cmethods.push(create_signature(meta_name.clone(), sig));
+                    shiika_defs.push(def.clone());
+                },
+                _ => panic!("[BUG] unexpected definition in the prelude: {:?}", def),
+            }
+        }
 
-    SkMethod {
-        signature: sig,
-        body: SkMethodBody::RustMethodBody{ gen: gen }
+        let super_name = if name == "Object" { None }
+                         else { Some(ClassFullname("Object".to_string())) };
+        self.sk_classes.insert(
+            ClassFullname(name.to_string()),
+            SkClass {
+                fullname: ClassFullname(name.to_string()),
+                superclass_fullname: super_name,
+                instance_ty: ty::raw(name),
+                method_sigs: imethods.into_iter().map(|x|
+                    (x.first_name().clone(), x)
+                ).collect(),
+                interfaces: vec![],
+            }
+        );
+        self.sk_classes.insert(
+            ClassFullname(meta_name.clone()),
+            SkClass {
+                fullname: ClassFullname(meta_name),
+                superclass_fullname: Some(ClassFullname("Meta:Object".to_string())),
+                instance_ty: ty::meta(name),
+                method_sigs: cmethods.into_iter().map(|x|
+                    (x.first_name().clone(), x)
+                ).collect(),
+                interfaces: vec![],
+            }
+        );
+        self.sk_methods.insert(ClassFullname(name.to_string()), sk_methods);
+        if !shiika_defs.is_empty() {
+            self.prelude_defs.push(ast::Definition::ClassDefinition {
+                name: ClassFirstname(name.to_string()),
+                interfaces: vec![],
+                defs: shiika_defs,
+            });
+        }
     }
 }
+
+#[cfg(feature = "codegen")]
+fn primitive_body(primitives: &mut Primitives, signature: &ty::MethodSignature) -> SkMethodBody {
+    let gen = primitives.remove(signature.fullname.full_name.as_str())
+        .unwrap_or_else(|| panic!("[BUG] primitive {} is not implemented", signature.fullname));
+    SkMethodBody::RustMethodBody { gen }
+}
+
+#[cfg(not(feature = "codegen"))]
+fn primitive_body(_primitives: &mut Primitives, _signature: &ty::MethodSignature) -> SkMethodBody {
+    SkMethodBody::RustMethodBody {}
+}
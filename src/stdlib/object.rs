@@ -1,10 +1,7 @@
 use inkwell::values::*;
-use crate::hir::*;
-use crate::stdlib::create_method;
-
-pub fn create_methods() -> Vec<SkMethod> {
-    vec![
+use crate::stdlib::Primitives;
 
+pub fn add_primitives(h: &mut Primitives) {
 // TODO: `new' will mostly look like this, but we need spacial care for `new' because
 // 1. its parameters are defined by `initialize', and
 // 2. the return type differs in each class
@@ -12,15 +9,21 @@ pub fn create_methods() -> Vec<SkMethod> {
 //        let addr = code_gen.allocate_sk_obj(&ClassFullname("Object".to_string()));
 //        code_gen.builder.build_return(Some(addr));
 //        Ok(())
-//    }),
+//    });
 
-    create_method("Object", "putchar(ord: Int) -> Void", |code_gen, function| {
+    h.insert("Object#putchar", |code_gen, function| {
         let n = function.get_params()[1].into_int_value();
         let func = code_gen.module.get_function("putchar").unwrap();
         code_gen.builder.build_call(func, &[n.as_basic_value_enum()], "");
         code_gen.builder.build_return(None);
         Ok(())
-    }),
+    });
 
-    ]
+    h.insert("Object#panic", |code_gen, function| {
+        let msg = function.get_params()[1];
+        let func = code_gen.module.get_function("shiika_panic").unwrap();
+        code_gen.builder.build_call(func, &[msg], "");
+        code_gen.builder.build_unreachable();
+        Ok(())
+    });
 }
@@ -13,6 +13,8 @@
 ///     [1,2,3] ~ Array  ~ Meta:Array ~ Class
 /// ```
 ///
+use std::collections::HashMap;
+use crate::ast::LocationSpan;
 use crate::names::*;
 use crate::ty;
 
@@ -33,6 +35,21 @@ pub enum TyBody {
     TyMeta { base_fullname: String },
     // This object belongs to the class `Class` (i.e. this is a class object)
     TyClass,
+    // Optional type, which is `base` or nil
+    // eg. "Int?", "A?" (`fullname` has the trailing `?`)
+    TyOpt { base: Box<TermTy> },
+    // The type of `nil`. Conforms to all the optional types
+    TyNil,
+    // Values of one of `members`
+    // eg. "Int | String"
+    TyUnion { members: Vec<TermTy> },
+    // Values which conform to all of `members`
+    // eg. "A & B"
+    TyIntersection { members: Vec<TermTy> },
+    // Type parameter of a generic method
+    // eg. "T" of `def id<T>(x: T) -> T`, "T" of `def max<T : Comparable>`
+    // (`bound` is the interface `Comparable`)
+    TyParam { bound: Option<Box<TermTy>> },
 }
 
 use TyBody::*;
@@ -53,15 +70,97 @@ impl TermTy {
         }
     }
 
+    /// Returns true if this may be nil (an optional type or the type of nil)
+    pub fn is_nilable(&self) -> bool {
+        match self.body {
+            TyOpt { .. } | TyNil => true,
+            _ => false,
+        }
+    }
+
+    /// Return `T` of `T?` (None if this is not an optional type)
+    pub fn opt_base(&self) -> Option<&TermTy> {
+        match &self.body {
+            TyOpt { base } => Some(base),
+            _ => None,
+        }
+    }
+
+    /// Returns true if this is a type parameter or contains one
+    pub fn contains_typaram(&self) -> bool {
+        match &self.body {
+            TyParam { .. } => true,
+            TyOpt { base } => base.contains_typaram(),
+            TyUnion { members } | TyIntersection { members } => {
+                members.iter().any(|m| m.contains_typaram())
+            },
+            _ => false,
+        }
+    }
+
+    /// Replace the type parameters with the types in `map`
+    pub fn substitute(&self, map: &HashMap<String, TermTy>) -> TermTy {
+        match &self.body {
+            TyParam { .. } => map.get(&self.fullname.0).cloned().unwrap_or_else(|| self.clone()),
+            TyOpt { base } => ty::opt(base.substitute(map)),
+            TyUnion { members } => ty::union(members.iter().map(|m| m.substitute(map)).collect()),
+            TyIntersection { members } => ty::intersection(members.iter().map(|m| m.substitute(map)).collect()),
+            _ => self.clone(),
+        }
+    }
+
+    /// Return the bound of a type parameter (None if this is not a bounded
+    /// type parameter)
+    pub fn typaram_bound(&self) -> Option<&TermTy> {
+        match &self.body {
+            TyParam { bound: Some(bound) } => Some(bound),
+            _ => None,
+        }
+    }
+
+    /// Return the members of a union type (None if this is not a union)
+    pub fn union_members(&self) -> Option<&[TermTy]> {
+        match &self.body {
+            TyUnion { members } => Some(members),
+            _ => None,
+        }
+    }
+
     pub fn meta_ty(&self) -> TermTy {
         match self.body {
             TyRaw => ty::meta(&self.fullname.0),
             TyMeta { .. } => ty::class(),
             TyClass => ty::class(),
+            TyOpt { .. } | TyNil | TyUnion { .. } | TyIntersection { .. } | TyParam { .. } => {
+                panic!("[BUG] {} has no metaclass", self.fullname)
+            },
         }
     }
 
     pub fn conforms_to(&self, other: &TermTy) -> bool {
+        // `A | B` conforms to `T` if both `A` and `B` do
+        if let TyUnion { members } = &self.body {
+            return members.iter().all(|m| m.conforms_to(other))
+        }
+        match &other.body {
+            // `T` conforms to `A & B` if it conforms to both
+            TyIntersection { members } => return members.iter().all(|m| self.conforms_to(m)),
+            // `T` conforms to `A | B` if it conforms to either
+            TyUnion { members } => return members.iter().any(|m| self.conforms_to(m)),
+            _ => (),
+        }
+        // `A & B` conforms to `T` if either `A` or `B` does
+        if let TyIntersection { members } = &self.body {
+            return members.iter().any(|m| m.conforms_to(other))
+        }
+        // `T` and nil conform to `T?`
+        if let TyOpt { base } = &other.body {
+            return match &self.body {
+                TyNil => true,
+                TyOpt { base: self_base } => self_base.equals_to(base),
+                _ => self.conforms_to(base),
+            }
+        }
         match self.body {
             TyRaw => {
                 match other.body {
@@ -80,7 +179,15 @@ impl TermTy {
                     TyClass => true,
                     _ => false,
                 }
-            }
+            },
+            TyParam { .. } => {
+                match other.body {
+                    TyParam { .. } => (self.fullname == other.fullname),
+                    _ => false,
+                }
+            },
+            TyOpt { .. } | TyNil => false,
+            TyUnion { .. } | TyIntersection { .. } => unreachable!(),
         }
     }
 
@@ -103,11 +210,47 @@ impl TermTy {
                     TyClass => true,
                     _ => false,
                 }
-            }
+            },
+            TyOpt { ref base } => {
+                match &other.body {
+                    TyOpt { base: other_base } => base.equals_to(other_base),
+                    _ => false,
+                }
+            },
+            TyNil => {
+                match other.body {
+                    TyNil => true,
+                    _ => false,
+                }
+            },
+            // The order of the members does not matter
+            TyUnion { ref members } => {
+                match &other.body {
+                    TyUnion { members: other_members } => same_members(members, other_members),
+                    _ => false,
+                }
+            },
+            TyIntersection { ref members } => {
+                match &other.body {
+                    TyIntersection { members: other_members } => same_members(members, other_members),
+                    _ => false,
+                }
+            },
+            TyParam { .. } => {
+                match other.body {
+                    TyParam { .. } => (self.fullname == other.fullname),
+                    _ => false,
+                }
+            },
         }
     }
 }
 
+fn same_members(members: &[TermTy], other_members: &[TermTy]) -> bool {
+    members.len() == other_members.len() &&
+        members.iter().all(|m| other_members.iter().any(|o| m.equals_to(o)))
+}
+
 pub fn raw(fullname: &str) -> TermTy {
     TermTy { fullname: ClassFullname(fullname.to_string()), body: TyRaw }
 }
@@ -119,6 +262,71 @@ pub fn meta(base_fullname: &str) -> TermTy {
     }
 }
 
+/// Returns `T?` for `T` (`T?` itself if `base` is already optional)
+pub fn opt(base: TermTy) -> TermTy {
+    if base.is_nilable() {
+        return base
+    }
+    let base_name = match base.body {
+        TyUnion { .. } | TyIntersection { .. } => format!("({})", base.fullname),
+        _ => base.fullname.0.clone(),
+    };
+    TermTy {
+        fullname: ClassFullname(base_name + "?"),
+        body: TyOpt { base: Box::new(base) },
+    }
+}
+
+/// Returns `A | B | ...`. Nested unions are flattened and duplicated
+/// members are removed (the result is not a union if only one remains)
+pub fn union(tys: Vec<TermTy>) -> TermTy {
+    combine(tys, true)
+}
+
+/// Returns `A & B & ...` (normalized like `union`)
+pub fn intersection(tys: Vec<TermTy>) -> TermTy {
+    combine(tys, false)
+}
+
+fn combine(tys: Vec<TermTy>, is_union: bool) -> TermTy {
+    let mut members: Vec<TermTy> = vec![];
+    for ty in tys {
+        let flattened = match (ty.body, is_union) {
+            (TyUnion { members }, true) | (TyIntersection { members }, false) => members,
+            (body, _) => vec![TermTy { fullname: ty.fullname, body }],
+        };
+        for m in flattened {
+            if !members.iter().any(|x| x.equals_to(&m)) {
+                members.push(m);
+            }
+        }
+    }
+    if members.len() == 1 {
+        return members.pop().unwrap()
+    }
+    let sep = if is_union { " | " } else { " & " };
+    let fullname = members.iter().map(|m| m.fullname.0.as_str()).collect::<Vec<_>>().join(sep);
+    let body = if is_union { TyUnion { members } } else { TyIntersection { members } };
+    TermTy {
+        fullname: ClassFullname(fullname),
+        body,
+    }
+}
+
+pub fn typaram(name: &str, bound: Option<TermTy>) -> TermTy {
+    TermTy {
+        fullname: ClassFullname(name.to_string()),
+        body: TyParam { bound: bound.map(Box::new) },
+    }
+}
+
+pub fn nil() -> TermTy {
+    TermTy {
+        fullname: ClassFullname("Nil".to_string()),
+        body: TyNil,
+    }
+}
+
 pub fn class() -> TermTy {
     TermTy {
         fullname: ClassFullname("Class".to_string()),
@@ -126,22 +334,16 @@ pub fn class() -> TermTy {
     }
 }
 
-// Types corresponds to (non-specialized) generic class
-//pub struct TyGen {}
-// Note: TyGen does not implement TermTy (Generic class itself cannot have an instance)
-
-// Types corresponds to specialized generic class
-//pub struct TySpe {}
-//impl TermTy for TySpe {}
-// Types corresponds to specialized generic metaclass
-//pub struct TySpeMeta {}
-//impl TermTy for TySpeMeta {}
+// Only methods can have type parameters (`TyParam`). The parser rejects
+// generic classes (eg. `class SortedList<T : Comparable>`)
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct MethodSignature {
     pub fullname: MethodFullname,
     pub ret_ty: TermTy,
     pub params: Vec<MethodParam>,
+    /// Type parameters of a generic method (empty if not generic)
+    pub typarams: Vec<TermTy>,
 }
 
 impl MethodSignature {
@@ -153,10 +355,37 @@ impl MethodSignature {
     pub fn first_name(&self) -> &MethodFirstname {
         &self.fullname.first_name
     }
+
+    /// Return the index of the hidden parameter which receives the type
+    /// tag of the bounded type parameter `name`. These parameters follow
+    /// the normal ones
+    pub fn tag_param_idx(&self, name: &str) -> Option<usize> {
+        self.typarams.iter()
+            .filter(|t| t.typaram_bound().is_some())
+            .position(|t| t.fullname.0 == name)
+            .map(|i| self.params.len() + i)
+    }
+
+    /// Return the index of the hidden parameter which tells whether the
+    /// argument for the `idx`-th param (which has a default value) is
+    /// given at the call site. These parameters follow the tag ones
+    pub fn given_flag_idx(&self, idx: usize) -> Option<usize> {
+        if !self.params[idx].has_default {
+            return None
+        }
+        let n_tags = self.typarams.iter().filter(|t| t.typaram_bound().is_some()).count();
+        let n_before = self.params[..idx].iter().filter(|p| p.has_default).count();
+        Some(self.params.len() + n_tags + n_before)
+    }
 }
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct MethodParam {
     pub name: String,
     pub ty: TermTy,
+    /// Whether the param has a default value (which is evaluated in the
+    /// method when the argument is omitted)
+    pub has_default: bool,
+    /// Location of the name
+    pub locs: Option<LocationSpan>,
 }
@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use crate::error::Error;
 use crate::ty;
 use crate::ty::*;
@@ -27,8 +28,19 @@ pub fn check_condition_ty(ty: &TermTy, on: &str) -> Result<(), Error> {
     }
 }
 
+pub fn check_logical_operator_ty(ty: &TermTy, op: &str) -> Result<(), Error> {
+    if *ty == ty::raw("Bool") {
+        Ok(())
+    }
+    else {
+        Err(type_error!("operand of {} must be bool but got {:?}", op, ty.fullname))
+    }
+}
+
 pub fn check_reassign_var(orig_ty: &TermTy, new_ty: &TermTy, name: &str) -> Result<(), Error> {
-    if orig_ty.equals_to(new_ty) {
+    // `T` and nil may be assigned to a variable of `T?` (and `T` to `T | U`)
+    let widening = orig_ty.is_nilable() || orig_ty.union_members().is_some();
+    if orig_ty.equals_to(new_ty) || (widening && new_ty.conforms_to(orig_ty)) {
         Ok(())
     }
     else {
@@ -36,6 +48,26 @@ pub fn check_reassign_var(orig_ty: &TermTy, new_ty: &TermTy, name: &str) -> Resu
     }
 }
 
+/// The value of `var x: T = ...` must conform to `T`
+pub fn check_lvar_annotation(declared_ty: &TermTy, value_ty: &TermTy, name: &str) -> Result<(), Error> {
+    if value_ty.conforms_to(declared_ty) {
+        Ok(())
+    }
+    else {
+        Err(type_error!("variable {} is declared as {} but got {}", name, declared_ty.fullname, value_ty.fullname))
+    }
+}
+
+/// `expr is T` must be possibly true
+pub fn check_type_test(expr_ty: &TermTy, tested_ty: &TermTy) -> Result<(), Error> {
+    if tested_ty.conforms_to(expr_ty) || expr_ty.conforms_to(tested_ty) {
+        Ok(())
+    }
+    else {
+        Err(type_error!("{} can never be {}", expr_ty.fullname, tested_ty.fullname))
+    }
+}
+
 pub fn check_method_args(sig: &MethodSignature, arg_tys: &Vec<&TermTy>) -> Result<(), Error> {
     if sig.params.len() != arg_tys.len() {
         return Err(type_error!("{} takes {} args but got {}", sig.fullname, sig.params.len(), arg_tys.len()));
@@ -53,3 +85,95 @@ pub fn check_method_args(sig: &MethodSignature, arg_tys: &Vec<&TermTy>) -> Resul
 
     Ok(())
 }
+
+pub fn check_default_value(sig: &MethodSignature, param: &MethodParam, default_ty: &TermTy) -> Result<(), Error> {
+    if default_ty.conforms_to(&param.ty) {
+        Ok(())
+    }
+    else {
+        Err(type_error!("default value of `{}' of {} should be {} but got {}",
+                        param.name, sig.fullname, param.ty.fullname, default_ty.fullname))
+    }
+}
+
+/// Type parameters can only be used as `T` or `T?` in a signature
+pub fn check_typaram_usage(sig: &MethodSignature) -> Result<(), Error> {
+    let tys = sig.params.iter().map(|param| &param.ty).chain(std::iter::once(&sig.ret_ty));
+    for ty in tys {
+        let ok = match &ty.body {
+            TyBody::TyParam { .. } => true,
+            TyBody::TyOpt { base } => {
+                match base.body {
+                    TyBody::TyParam { .. } => true,
+                    _ => !base.contains_typaram(),
+                }
+            },
+            _ => !ty.contains_typaram(),
+        };
+        if !ok {
+            return Err(type_error!("{}: type parameters can only be used as `T' or `T?' (got {})",
+                                   sig.fullname, ty.fullname))
+        }
+    }
+    Ok(())
+}
+
+/// Infer the type arguments of a generic method from `arg_tys` and
+/// return the signature where the type parameters are replaced with them
+/// (and the inferred types)
+pub fn instantiate(sig: &MethodSignature, arg_tys: &[&TermTy])
+                  -> Result<(MethodSignature, HashMap<String, TermTy>), Error> {
+    // Leave the arity error to `check_method_args`
+    if sig.params.len() != arg_tys.len() {
+        return Ok((sig.clone(), HashMap::new()))
+    }
+    let mut map = HashMap::new();
+    for (param, arg_ty) in sig.params.iter().zip(arg_tys.iter()) {
+        unify(sig, &param.ty, arg_ty, &mut map)?;
+    }
+    if let Some(typaram) = sig.typarams.iter().find(|t| !map.contains_key(&t.fullname.0)) {
+        return Err(type_error!("cannot infer type parameter {} of {}", typaram.fullname, sig.fullname))
+    }
+    let instantiated = MethodSignature {
+        fullname: sig.fullname.clone(),
+        ret_ty: sig.ret_ty.substitute(&map),
+        params: sig.params.iter().map(|param| MethodParam {
+            name: param.name.clone(),
+            ty: param.ty.substitute(&map),
+            has_default: param.has_default,
+            locs: param.locs.clone(),
+        }).collect(),
+        typarams: vec![],
+    };
+    Ok((instantiated, map))
+}
+
+/// Bind the type parameters in `param_ty` so that `arg_ty` conforms to it
+fn unify(sig: &MethodSignature, param_ty: &TermTy, arg_ty: &TermTy,
+         map: &mut HashMap<String, TermTy>) -> Result<(), Error> {
+    match &param_ty.body {
+        TyBody::TyParam { .. } => {
+            let name = &param_ty.fullname.0;
+            match map.get(name) {
+                Some(bound) => {
+                    if !arg_ty.conforms_to(bound) {
+                        return Err(type_error!("{} of {} is inferred as {} but got {}",
+                                               name, sig.fullname, bound.fullname, arg_ty.fullname))
+                    }
+                },
+                None => { map.insert(name.clone(), arg_ty.clone()); },
+            }
+            Ok(())
+        },
+        TyBody::TyOpt { base } => {
+            match &arg_ty.body {
+                // nil tells nothing about `T` of `T?`
+                TyBody::TyNil => Ok(()),
+                TyBody::TyOpt { base: arg_base } => unify(sig, base, arg_base, map),
+                _ => unify(sig, base, arg_ty, map),
+            }
+        },
+        // Checked by `check_method_args`
+        _ => Ok(()),
+    }
+}
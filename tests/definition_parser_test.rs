@@ -13,6 +13,7 @@ fn test_emtpy_class() {
     assert_eq!(result.unwrap(), vec![ 
         ast::Definition::ClassDefinition {
             name: ClassFirstname("A".to_string()),
+            interfaces: vec![],
             defs: vec![]
         }
     ])
@@ -24,6 +25,7 @@ fn test_class_with_constant() {
     assert_eq!(result.unwrap(), vec![ 
         ast::Definition::ClassDefinition {
             name: ClassFirstname("A".to_string()),
+            interfaces: vec![],
             defs: vec![
                 ast::Definition::ConstDefinition {
                     name: ConstFirstname("B".to_string()),
@@ -40,12 +42,14 @@ fn test_class_with_empty_method() {
     assert_eq!(result.unwrap(), vec![
         ast::Definition::ClassDefinition {
             name: ClassFirstname("A".to_string()),
+            interfaces: vec![],
             defs: vec![
                 ast::Definition::InstanceMethodDefinition {
                     sig: ast::AstMethodSignature {
                         name: MethodFirstname("foo".to_string()),
+                        typarams: vec![],
                         params: vec![],
-                        ret_typ: ast::Typ { name: "Void".to_string() },
+                        ret_typ: ast::typ("Void"),
                     },
                     body_exprs: vec![],
                 }
@@ -54,6 +58,57 @@ fn test_class_with_empty_method() {
     ])
 }
 
+#[test]
+fn test_primitive_method() {
+    let result = parse_definitions("class A\n  @primitive def self.foo -> Int\nend");
+    assert_eq!(result.unwrap(), vec![
+        ast::Definition::ClassDefinition {
+            name: ClassFirstname("A".to_string()),
+            interfaces: vec![],
+            defs: vec![
+                ast::Definition::PrimitiveMethodDefinition {
+                    sig: ast::AstMethodSignature {
+                        name: MethodFirstname("foo".to_string()),
+                        typarams: vec![],
+                        params: vec![],
+                        ret_typ: ast::typ("Int"),
+                    },
+                    is_class_method: true,
+                }
+            ]
+        }
+    ])
+}
+
+#[test]
+fn test_interface() {
+    let result = parse_definitions("interface I\n  def foo(x: Int) -> Int\n  def bar\nend\nclass A : I, J; end");
+    assert_eq!(result.unwrap(), vec![
+        ast::Definition::InterfaceDefinition {
+            name: ClassFirstname("I".to_string()),
+            sigs: vec![
+                ast::AstMethodSignature {
+                    name: MethodFirstname("foo".to_string()),
+                    typarams: vec![],
+                    params: vec![ast::Param { name: "x".to_string(), typ: ast::typ("Int"), default_expr: None, locs: None }],
+                    ret_typ: ast::typ("Int"),
+                },
+                ast::AstMethodSignature {
+                    name: MethodFirstname("bar".to_string()),
+                    typarams: vec![],
+                    params: vec![],
+                    ret_typ: ast::typ("Void"),
+                },
+            ],
+        },
+        ast::Definition::ClassDefinition {
+            name: ClassFirstname("A".to_string()),
+            interfaces: vec![ClassFirstname("I".to_string()), ClassFirstname("J".to_string())],
+            defs: vec![],
+        },
+    ])
+}
+
 #[test]
 fn test_method_with_params() {
     let mut parser = Parser::new("def foo(a: Int, b: Float); end");
@@ -61,11 +116,54 @@ fn test_method_with_params() {
     assert_eq!(result.unwrap(), ast::Definition::InstanceMethodDefinition {
         sig: ast::AstMethodSignature {
             name: MethodFirstname("foo".to_string()),
+            typarams: vec![],
+            params: vec![
+                ast::Param { name: "a".to_string(), typ: ast::typ("Int"), default_expr: None, locs: None },
+                ast::Param { name: "b".to_string(), typ: ast::typ("Float"), default_expr: None, locs: None },
+            ],
+            ret_typ: ast::typ("Void"),
+        },
+        body_exprs: vec![],
+    })
+}
+
+#[test]
+fn test_method_with_default_value() {
+    let mut parser = Parser::new("def foo(a: Int, b: Int = 10); end");
+    let result = parser.parse_method_definition();
+    assert_eq!(result.unwrap(), ast::Definition::InstanceMethodDefinition {
+        sig: ast::AstMethodSignature {
+            name: MethodFirstname("foo".to_string()),
+            typarams: vec![],
             params: vec![
-                ast::Param { name: "a".to_string(), typ: ast::Typ { name: "Int".to_string() }},
-                ast::Param { name: "b".to_string(), typ: ast::Typ { name: "Float".to_string() }},
+                ast::Param { name: "a".to_string(), typ: ast::typ("Int"), default_expr: None, locs: None },
+                ast::Param { name: "b".to_string(), typ: ast::typ("Int"), default_expr: Some(ast::decimal_literal(10)), locs: None },
             ],
-            ret_typ: ast::Typ { name: "Void".to_string() },
+            ret_typ: ast::typ("Void"),
+        },
+        body_exprs: vec![],
+    });
+
+    let mut parser = Parser::new("def foo(a: Int = 1, b: Int); end");
+    assert!(parser.parse_method_definition().is_err());
+}
+
+#[test]
+fn test_generic_method() {
+    let mut parser = Parser::new("def pick<T, U : Comparable>(a: T, b: U?) -> T; end");
+    let result = parser.parse_method_definition();
+    assert_eq!(result.unwrap(), ast::Definition::InstanceMethodDefinition {
+        sig: ast::AstMethodSignature {
+            name: MethodFirstname("pick".to_string()),
+            typarams: vec![
+                ast::AstTyParam { name: "T".to_string(), bound: None },
+                ast::AstTyParam { name: "U".to_string(), bound: Some(ast::typ("Comparable")) },
+            ],
+            params: vec![
+                ast::Param { name: "a".to_string(), typ: ast::typ("T"), default_expr: None, locs: None },
+                ast::Param { name: "b".to_string(), typ: ast::opt_typ(ast::typ("U")), default_expr: None, locs: None },
+            ],
+            ret_typ: ast::typ("T"),
         },
         body_exprs: vec![],
     })
@@ -78,8 +176,24 @@ fn test_method_with_explicit_return_type() {
     assert_eq!(result.unwrap(), ast::Definition::InstanceMethodDefinition {
         sig: ast::AstMethodSignature {
             name: MethodFirstname("foo".to_string()),
+            typarams: vec![],
             params: vec![],
-            ret_typ: ast::Typ { name: "Int".to_string() },
+            ret_typ: ast::typ("Int"),
+        },
+        body_exprs: vec![],
+    })
+}
+
+#[test]
+fn test_optional_types() {
+    let mut parser = Parser::new("def foo(x: Int?) -> A?; end");
+    let result = parser.parse_method_definition();
+    assert_eq!(result.unwrap(), ast::Definition::InstanceMethodDefinition {
+        sig: ast::AstMethodSignature {
+            name: MethodFirstname("foo".to_string()),
+            typarams: vec![],
+            params: vec![ast::Param { name: "x".to_string(), typ: ast::opt_typ(ast::typ("Int")), default_expr: None, locs: None }],
+            ret_typ: ast::opt_typ(ast::typ("A")),
         },
         body_exprs: vec![],
     })
@@ -92,9 +206,38 @@ fn test_class_method_def() {
     assert_eq!(result.unwrap(), ast::Definition::ClassMethodDefinition {
         sig: ast::AstMethodSignature {
             name: MethodFirstname("foo".to_string()),
+            typarams: vec![],
             params: vec![],
-            ret_typ: ast::Typ { name: "Void".to_string() },
+            ret_typ: ast::typ("Void"),
         },
         body_exprs: vec![],
     })
 }
+
+#[test]
+fn test_extern_definition() {
+    let program = Parser::parse("extern def hypot(x: Float, y: Float) -> Float").unwrap();
+    assert_eq!(program.toplevel_defs, vec![
+        ast::Definition::ExternDefinition {
+            sig: ast::AstMethodSignature {
+                name: MethodFirstname("hypot".to_string()),
+                typarams: vec![],
+                params: vec![
+                    ast::Param { name: "x".to_string(), typ: ast::typ("Float"), default_expr: None, locs: None },
+                    ast::Param { name: "y".to_string(), typ: ast::typ("Float"), default_expr: None, locs: None },
+                ],
+                ret_typ: ast::typ("Float"),
+            },
+        }
+    ]);
+}
+
+#[test]
+fn test_class_type_parameters() {
+    let err = parse_definitions("class SortedList<T : Comparable<T>>; end").unwrap_err();
+    match err.details {
+        shiika::error::ErrorDetails::ParseError { .. } => (),
+        details => panic!("unexpected error: {:?}", details),
+    }
+    assert_eq!(err.msg, "class SortedList cannot have type parameters (generic classes are not supported yet)");
+}
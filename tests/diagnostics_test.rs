@@ -0,0 +1,88 @@
+use std::path::Path;
+use shiika::diagnostics;
+use shiika::diagnostics::Severity;
+use shiika::hir::lint::Lint;
+
+#[test]
+fn test_check_ok() {
+    assert!(diagnostics::check("1 + 2").is_empty());
+}
+
+#[test]
+fn test_check_parse_error() {
+    let diags = diagnostics::check("class A\n  def foo(\nend");
+    assert_eq!(diags.len(), 1);
+    assert_eq!(diags[0].kind, "parse_error");
+    assert!(diags[0].location.is_some());
+}
+
+#[test]
+fn test_check_type_error() {
+    let diags = diagnostics::check("if 1 then 2 else 3 end");
+    assert_eq!(diags.len(), 1);
+    assert_eq!(diags[0].kind, "type_error");
+    let json: serde_json::Value = serde_json::from_str(&diags[0].to_json("a.sk")).unwrap();
+    assert_eq!(json, serde_json::json!({
+        "file": "a.sk",
+        "line": 1,
+        "column": 1,
+        "kind": "type_error",
+        "severity": "error",
+        "message": "if condition must be bool but got ClassFullname(\"Int\")"
+    }));
+}
+
+#[test]
+fn test_lint_warnings() {
+    let src = "class A
+  def foo(n: Int, _m: Int) -> Int
+    x = 1
+    _y = 2
+    3
+  end
+end
+A = 1
+while false; end
+while true; end
+putchar 65
+";
+    let diags = diagnostics::check(src);
+    let kinds = diags.iter().map(|d| (d.kind, d.severity)).collect::<Vec<_>>();
+    assert_eq!(kinds, vec![
+        ("shadowed_class", Severity::Warning),
+        ("while_false", Severity::Warning),
+        ("unreachable_code", Severity::Warning),
+        ("unused_variable", Severity::Warning),
+        ("unused_param", Severity::Warning),
+    ]);
+    assert_eq!(diags[3].to_text("a.sk"), "a.sk:3:5: warning: variable `x' is never used [unused_variable]");
+    assert_eq!(diags[4].msg, "parameter `n' of A#foo is never used");
+}
+
+#[test]
+fn test_deny_lint() {
+    let diags = diagnostics::check_file("x = 1", Path::new("a.sk"), &[Lint::UnusedVariable]);
+    assert_eq!(diags.len(), 1);
+    assert_eq!(diags[0].severity, Severity::Error);
+    assert!(diagnostics::has_error(&diags));
+    assert_eq!(diags[0].to_text("a.sk"), "a.sk:1:1: unused_variable: variable `x' is never used");
+}
+
+#[test]
+fn test_unused_defaulted_param() {
+    let src = "class A
+  def foo(n: Int = 1, m: Int = n) -> Int
+    m
+  end
+
+  def bar(k: Int = 2) -> Int
+    3
+  end
+end
+";
+    let diags = diagnostics::check_file(src, Path::new("a.sk"), &[Lint::UnusedParam]);
+    assert_eq!(diags.len(), 1);
+    assert_eq!(diags[0].kind, "unused_param");
+    assert_eq!(diags[0].severity, Severity::Error);
+    assert_eq!(diags[0].to_text("a.sk"), "a.sk:6:11: unused_param: parameter `k' of A#bar is never used");
+}
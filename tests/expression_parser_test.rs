@@ -168,6 +168,53 @@ fn test_decimal_literal() {
     )
 }
 
+#[test]
+fn test_string_literal() {
+    let result = parse_expr("\"a\\n\"");
+    assert_eq!(result.unwrap(),
+        ast::string_literal("a\n".to_string()),
+    )
+}
+
+#[test]
+fn test_type_test() {
+    let result = parse_expr("x is Int | String");
+    assert_eq!(result.unwrap(),
+        ast::type_test(ast::bare_name("x"), ast::Typ::Union(vec![ast::typ("Int"), ast::typ("String")])),
+    )
+}
+
+#[test]
+fn test_intersection_type() {
+    let result = parse_expr("x is A & B | C?");
+    assert_eq!(result.unwrap(),
+        ast::type_test(ast::bare_name("x"), ast::Typ::Union(vec![
+            ast::Typ::Intersection(vec![ast::typ("A"), ast::typ("B")]),
+            ast::opt_typ(ast::typ("C")),
+        ])),
+    )
+}
+
+#[test]
+fn test_cast() {
+    let result = parse_expr("x as Int");
+    assert_eq!(result.unwrap(),
+        ast::cast(ast::bare_name("x"), ast::typ("Int")),
+    )
+}
+
+#[test]
+fn test_annotated_var_decl() {
+    let result = parse_expr("var x: Int? = 1");
+    assert_eq!(result.unwrap(),
+        ast::var_decl("x".to_string(), Some(ast::opt_typ(ast::typ("Int"))), ast::decimal_literal(1)),
+    );
+    let result = parse_expr("let y: Float = 1.0");
+    assert_eq!(result.unwrap(),
+        ast::let_decl("y".to_string(), Some(ast::typ("Float")), ast::float_literal(1.0)),
+    )
+}
+
 //
 // Method call (0 args)
 //
@@ -263,3 +310,30 @@ fn test_call_with_space_2() {
         false,
         false))
 }
+
+#[test]
+fn test_call_with_keyword_arg() {
+    let result = parse_expr("foo(1, y: 2)");
+    assert_eq!(result.unwrap(),
+    ast::method_call(
+        None,
+        "foo",
+        vec![
+            ast::decimal_literal(1),
+            ast::keyword_arg("y", ast::decimal_literal(2)),
+        ],
+        true,
+        false));
+
+    let result = parse_expr("foo 1, y: 2");
+    assert_eq!(result.unwrap(),
+    ast::method_call(
+        None,
+        "foo",
+        vec![
+            ast::decimal_literal(1),
+            ast::keyword_arg("y", ast::decimal_literal(2)),
+        ],
+        false,
+        false))
+}
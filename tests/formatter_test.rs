@@ -0,0 +1,58 @@
+use shiika::formatter;
+
+#[test]
+fn test_indentation_and_spacing() {
+    let src = "class A\n    def foo(a: Int)  ->  Int\n  1+a * 2\n end\nend\nA.new.foo(1)\n";
+    assert_eq!(formatter::format(src).unwrap(),
+"class A
+  def foo(a: Int) -> Int
+    1 + a * 2
+  end
+end
+
+A.new.foo(1)
+");
+}
+
+#[test]
+fn test_parentheses() {
+    assert_eq!(formatter::format("x = (1+2)*3\n").unwrap(), "x = (1 + 2) * 3\n");
+}
+
+#[test]
+fn test_if_block() {
+    assert_eq!(formatter::format("if true then 1 else 2 end\n").unwrap(),
+               "if true\n  1\nelse\n  2\nend\n");
+}
+
+#[test]
+fn test_annotation_and_cast() {
+    assert_eq!(formatter::format("var x : Int? =nil\nlet y:Float=1.0\nx  as  Int\n").unwrap(),
+               "var x: Int? = nil\nlet y: Float = 1.0\nx as Int\n");
+}
+
+#[test]
+fn test_default_and_keyword_args() {
+    assert_eq!(formatter::format("class A\n  def f(x: Int, y:Int=1)\n  end\nend\nA.new.f(1,y:2)\n").unwrap(),
+               "class A\n  def f(x: Int, y: Int = 1)\n  end\nend\n\nA.new.f(1, y: 2)\n");
+}
+
+#[test]
+fn test_comments() {
+    let src = "# Greeting
+class A
+  # Returns one
+  def foo -> Int
+    1  # one
+  end
+end
+";
+    assert_eq!(formatter::format(src).unwrap(), src);
+}
+
+#[test]
+fn test_idempotent() {
+    let src = "class A\ndef self.foo(x: Int, y: Int) -> Int\nx*y\nend\n\n\n\ndef bar\nend\nend\nA.foo(1, 2)\n";
+    let formatted = formatter::format(src).unwrap();
+    assert_eq!(formatter::format(&formatted).unwrap(), formatted);
+}
@@ -1,4 +1,30 @@
-use std::process::Command;
+use std::fs;
+use std::process::{Command, Output};
+use inkwell::targets::FileType;
+use shiika::code_gen::CodeGen;
+
+/// Generate the code of `src`. `filepath` is used in the locations
+fn gen(src: &str, filepath: &str) -> Result<CodeGen, Box<dyn std::error::Error>> {
+    let ast = shiika::parser::Parser::parse_file(src, filepath)?;
+    let stdlib = shiika::stdlib::Stdlib::create();
+    let hir = shiika::hir::Hir::from_ast(ast, stdlib)?;
+    let mut code_gen = CodeGen::new();
+    code_gen.set_verify(true);
+    code_gen.gen_program(hir)?;
+    Ok(code_gen)
+}
+
+/// Build an executable from `code_gen` in a directory for the test and run it
+fn run(code_gen: &CodeGen, name: &str) -> Result<Output, Box<dyn std::error::Error>> {
+    let dir = std::env::temp_dir().join(format!("shiika_integration_test_{}", name));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir)?;
+    let obj_path = dir.join("out.o");
+    let exe_path = dir.join("out");
+    code_gen.write_native_file(&obj_path, FileType::Object)?;
+    shiika::linker::link(&obj_path.to_string_lossy(), &exe_path.to_string_lossy())?;
+    Ok(Command::new(&exe_path).output()?)
+}
 
 #[test]
 fn test_compile_and_run() -> Result<(), Box<dyn std::error::Error>> {
@@ -6,27 +32,7 @@ fn test_compile_and_run() -> Result<(), Box<dyn std::error::Error>> {
 class A; def foo; end; end
 putchar 72
 putchar 100 + 5";
-    let ast = shiika::parser::Parser::parse(src)?;
-    let stdlib = shiika::stdlib::Stdlib::create();
-    let hir = shiika::hir::Hir::from_ast(ast, stdlib)?;
-    let mut code_gen = shiika::code_gen::CodeGen::new();
-    code_gen.gen_program(hir)?;
-    code_gen.module.print_to_file("tests/out.ll")?;
-
-    let mut cmd = Command::new("llc");
-    cmd.arg("tests/out.ll");
-    cmd.output().unwrap();
-
-    let mut cmd = Command::new("cc");
-    cmd.arg("-I/usr/local/Cellar/bdw-gc/7.6.0/include/");
-    cmd.arg("-L/usr/local/Cellar/bdw-gc/7.6.0/lib/");
-    cmd.arg("-lgc");
-    cmd.arg("-otests/out");
-    cmd.arg("tests/out.s");
-    cmd.output().unwrap();
-
-    let mut cmd = Command::new("tests/out");
-    let output = cmd.output().expect("failed to execute process");
+    let output = run(&gen(src, "compile_and_run.sk")?, "compile_and_run")?;
     let stdout = String::from_utf8(output.stdout).expect("invalid utf8 in stdout");
     let stderr = String::from_utf8(output.stderr).expect("invalid utf8 in stderr");
     assert_eq!(stderr, "");
@@ -34,3 +40,237 @@ putchar 100 + 5";
     Ok(())
 }
 
+
+#[test]
+fn test_run_jit() -> Result<(), Box<dyn std::error::Error>> {
+    let src = "
+class A; def foo -> Int; 1 + 2; end; end
+A.new.foo";
+    let ast = shiika::parser::Parser::parse(src)?;
+    let stdlib = shiika::stdlib::Stdlib::create();
+    let hir = shiika::hir::Hir::from_ast(ast, stdlib)?;
+    let mut code_gen = CodeGen::new();
+    code_gen.gen_program(hir)?;
+    assert_eq!(code_gen.run_jit()?, 0);
+    Ok(())
+}
+
+#[test]
+fn test_run_jit_optimized() -> Result<(), Box<dyn std::error::Error>> {
+    let src = "
+class A; def foo(n: Int) -> Int; n.abs.max(3); end; end
+A.new.foo(0 - 7)";
+    let ast = shiika::parser::Parser::parse(src)?;
+    let stdlib = shiika::stdlib::Stdlib::create();
+    let mut hir = shiika::hir::Hir::from_ast(ast, stdlib)?;
+    shiika::hir::opt::optimize(&mut hir);
+    let mut code_gen = CodeGen::new();
+    code_gen.set_opt_level(inkwell::OptimizationLevel::Aggressive);
+    code_gen.set_verify(true);
+    code_gen.gen_program(hir)?;
+    assert_eq!(code_gen.run_jit()?, 0);
+    Ok(())
+}
+
+#[test]
+fn test_debug_info() -> Result<(), Box<dyn std::error::Error>> {
+    let src = "
+class A
+  def foo(n: Int) -> Int
+    x = n + 1
+    x
+  end
+end
+A.new.foo(1)";
+    let ast = shiika::parser::Parser::parse_file(src, "debug_info.sk")?;
+    let stdlib = shiika::stdlib::Stdlib::create();
+    let hir = shiika::hir::Hir::from_ast(ast, stdlib)?;
+    let mut code_gen = CodeGen::new();
+    code_gen.enable_debug_info("debug_info.sk");
+    code_gen.gen_program(hir)?;
+    code_gen.verify()?;
+    let ir = code_gen.module.print_to_string().to_string();
+    assert!(ir.contains("!DISubprogram(name: \"A#foo\""));
+    assert!(ir.contains("!DILocalVariable(name: \"x\""));
+    assert!(ir.contains("!DILocation(line: 4,"));
+    Ok(())
+}
+
+#[test]
+fn test_panic() -> Result<(), Box<dyn std::error::Error>> {
+    let src = "class A\n  def foo(n: Int) -> Int\n    10 / n\n  end\nend\nputchar(72)\nA.new.foo(0)";
+    let output = run(&gen(src, "panic.sk")?, "panic")?;
+    assert_eq!(output.status.code(), Some(shiika::error::PANIC_EXIT_STATUS));
+    assert_eq!(String::from_utf8(output.stdout)?, "H");
+    assert_eq!(String::from_utf8(output.stderr)?,
+               "panic: division by zero\n  at A#foo (panic.sk:3:5)\n  at main (panic.sk:7:1)\n");
+    Ok(())
+}
+
+#[test]
+fn test_shift_out_of_range() -> Result<(), Box<dyn std::error::Error>> {
+    let src = "x = 1 << 6\nputchar(x + 8)\nn = 33\nputchar(x >> n)";
+    let output = run(&gen(src, "shift.sk")?, "shift")?;
+    assert_eq!(output.status.code(), Some(shiika::error::PANIC_EXIT_STATUS));
+    assert_eq!(String::from_utf8(output.stdout)?, "H");
+    assert!(String::from_utf8(output.stderr)?.starts_with("panic: shift count out of range\n"));
+    Ok(())
+}
+
+#[test]
+fn test_extern() -> Result<(), Box<dyn std::error::Error>> {
+    let src = "
+extern def abs(x: Int) -> Int
+putchar(abs(0 - 72))";
+    let output = run(&gen(src, "extern.sk")?, "extern")?;
+    assert_eq!(String::from_utf8(output.stdout)?, "H");
+    Ok(())
+}
+
+#[test]
+fn test_optional_and_logical_not() -> Result<(), Box<dyn std::error::Error>> {
+    let src = "
+class A
+  def find(n: Int) -> Int?
+    if 0 < n
+      n
+    else
+      nil
+    end
+  end
+
+  def twice(n: Int) -> Int
+    x = find(n)
+    if x != nil
+      x * 2
+    else
+      0
+    end
+  end
+end
+putchar(A.new.twice(36) + A.new.twice(0))
+flag = 2 < 1
+if !flag
+  putchar(105)
+end
+if 1 != 2
+  putchar(33)
+end";
+    let output = run(&gen(src, "optional.sk")?, "optional")?;
+    assert_eq!(String::from_utf8(output.stdout)?, "Hi!");
+    Ok(())
+}
+
+#[test]
+fn test_if_with_void_branch() -> Result<(), Box<dyn std::error::Error>> {
+    let src = "
+if 1 < 2 then putchar(72) else 1.0 end
+if 2 < 1 then 1 else putchar(105) end";
+    let output = run(&gen(src, "void_if.sk")?, "void_if")?;
+    assert_eq!(String::from_utf8(output.stdout)?, "Hi");
+    Ok(())
+}
+
+#[test]
+fn test_extern_conflicting_with_runtime() -> Result<(), Box<dyn std::error::Error>> {
+    let src = "
+extern def putchar(c: Int) -> Void
+putchar(72)";
+    let ast = shiika::parser::Parser::parse(src)?;
+    let stdlib = shiika::stdlib::Stdlib::create();
+    let hir = shiika::hir::Hir::from_ast(ast, stdlib)?;
+    let mut code_gen = CodeGen::new();
+    let err = code_gen.gen_program(hir).unwrap_err();
+    assert!(err.msg.contains("extern def putchar does not match"), "{}", err.msg);
+    Ok(())
+}
+
+#[test]
+fn test_union_and_intersection() -> Result<(), Box<dyn std::error::Error>> {
+    let src = "
+interface Named
+  def name_ord -> Int
+end
+
+class A : Named
+  def name_ord -> Int
+    72
+  end
+
+  def other -> Int
+    105
+  end
+end
+
+class B
+  def pick(n: Int) -> Int | Float
+    if n < 0
+      n.to_f
+    else
+      n
+    end
+  end
+
+  def ord(n: Int) -> Int
+    x = pick(n)
+    if x is Int
+      x
+    else
+      x.abs.to_i
+    end
+  end
+
+  def to_float(n: Int) -> Float
+    pick(n) as Float
+  end
+
+  def show(x: Named & A) -> Void
+    putchar(x.name_ord)
+    putchar(x.other)
+    putchar(via(x) - 39)
+  end
+
+  def via(n: Named) -> Int
+    n.name_ord
+  end
+end
+putchar(B.new.ord(72))
+putchar(B.new.ord(0 - 105))
+putchar(B.new.to_float(0 - 33).abs.to_i)
+B.new.show(A.new)";
+    let output = run(&gen(src, "union.sk")?, "union")?;
+    assert_eq!(String::from_utf8(output.stdout)?, "Hi!Hi!");
+    Ok(())
+}
+
+#[test]
+fn test_default_and_keyword_args() -> Result<(), Box<dyn std::error::Error>> {
+    let class_a = "
+class A
+  def base -> Int
+    0
+  end
+
+  def f(x: Int, y: Int = x + 1, z: Int = base) -> Int
+    x + y + z
+  end
+end
+";
+    let cases = [
+        // All the arguments are given positionally
+        ("positional", "putchar(A.new.f(1, 35, 36))", "H"),
+        // `y` is omitted and `z` is given by name
+        ("keyword", "putchar(A.new.f(35, z: 34))", "i"),
+        // Both are omitted
+        ("omitted", "putchar(A.new.f(16))", "!"),
+        // Both are given by name
+        ("keywords", "putchar(A.new.f(1, y: 32, z: 0))", "!"),
+    ];
+    for (name, main, expected) in &cases {
+        let src = format!("{}{}", class_a, main);
+        let output = run(&gen(&src, "default.sk")?, &format!("default_{}", name))?;
+        assert!(output.status.success(), "{}: {:?}", name, output.status);
+        assert_eq!(String::from_utf8(output.stdout)?, *expected, "{}", name);
+    }
+    Ok(())
+}
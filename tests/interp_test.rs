@@ -0,0 +1,299 @@
+use shiika::interp::{Interpreter, Value};
+use shiika::stdlib::Stdlib;
+
+fn run(src: &str) -> Result<(Value, String), Box<dyn std::error::Error>> {
+    let ast = shiika::parser::Parser::parse(src)?;
+    let hir = shiika::hir::Hir::from_ast(ast, Stdlib::create())?;
+    let mut out = vec![];
+    let value = Interpreter::new(&hir, &mut out).run()?;
+    Ok((value, String::from_utf8(out)?))
+}
+
+#[test]
+fn test_putchar() -> Result<(), Box<dyn std::error::Error>> {
+    let (_, out) = run("
+class A; def foo; end; end
+putchar 72
+putchar 100 + 5")?;
+    assert_eq!(out, "Hi");
+    Ok(())
+}
+
+#[test]
+fn test_method_call() -> Result<(), Box<dyn std::error::Error>> {
+    let (value, _) = run("
+      class A
+        def fib(n: Int) -> Int
+          if n < 3
+            1
+          else
+            fib(n - 1) + fib(n - 2)
+          end
+        end
+      end
+      A.new.fib(10)
+    ")?;
+    assert_eq!(value, Value::Int(55));
+    Ok(())
+}
+
+#[test]
+fn test_while() -> Result<(), Box<dyn std::error::Error>> {
+    let (value, _) = run("
+      var i = 0
+      var sum = 0.0
+      while i < 4
+        sum = sum + i.to_f
+        i = i + 1
+      end
+      sum
+    ")?;
+    assert_eq!(value, Value::Float(6.0));
+    Ok(())
+}
+
+#[test]
+fn test_prelude_methods() -> Result<(), Box<dyn std::error::Error>> {
+    let (value, _) = run("
+      x = 0 - 3
+      x.abs + x.max(2)
+    ")?;
+    assert_eq!(value, Value::Int(5));
+    let (value, _) = run("
+      y = 1.5
+      y.clamp(0.0, 1.0)
+    ")?;
+    assert_eq!(value, Value::Float(1.0));
+    Ok(())
+}
+
+#[test]
+fn test_extern_is_not_supported() {
+    let err = run("extern def abs(x: Int) -> Int\nabs(1)").unwrap_err();
+    assert_eq!(err.to_string(), "cannot call extern function `abs' in the interpreter");
+}
+
+#[test]
+fn test_panic() -> Result<(), Box<dyn std::error::Error>> {
+    let src = "class A\n  def foo(n: Int) -> Int\n    10 / n\n  end\nend\nputchar(72)\nA.new.foo(0)";
+    let ast = shiika::parser::Parser::parse_file(src, "panic.sk")?;
+    let hir = shiika::hir::Hir::from_ast(ast, Stdlib::create())?;
+    let mut out = vec![];
+    let err = Interpreter::new(&hir, &mut out).run().unwrap_err();
+    assert_eq!(err.msg, "division by zero");
+    match err.details {
+        shiika::error::ErrorDetails::Panic { trace } => {
+            assert_eq!(trace, vec!["A#foo (panic.sk:3:5)", "main (panic.sk:7:1)"]);
+        },
+        details => panic!("unexpected error: {:?}", details),
+    }
+    assert_eq!(out, b"H");
+
+    let err = run("panic(\"oops\")").unwrap_err();
+    assert_eq!(err.to_string(), "oops");
+    let err = run("2147483647 + 1").unwrap_err();
+    assert_eq!(err.to_string(), "integer overflow");
+    let err = run("1 << 33").unwrap_err();
+    assert_eq!(err.to_string(), "shift count out of range");
+    let err = run("1 >> (0 - 1)").unwrap_err();
+    assert_eq!(err.to_string(), "shift count out of range");
+    Ok(())
+}
+
+
+#[test]
+fn test_optional() -> Result<(), Box<dyn std::error::Error>> {
+    let (value, _) = run("
+      class A
+        def find(n: Int) -> Int?
+          if 0 < n
+            n
+          else
+            nil
+          end
+        end
+        def twice(n: Int) -> Int
+          x = find(n)
+          if x != nil
+            x * 2
+          else
+            0
+          end
+        end
+      end
+      A.new.twice(3) + A.new.twice(0)
+    ")?;
+    assert_eq!(value, Value::Int(6));
+    Ok(())
+}
+
+#[test]
+fn test_logical_not() -> Result<(), Box<dyn std::error::Error>> {
+    let (value, _) = run("
+      flag = 1 < 2
+      !flag
+    ")?;
+    assert_eq!(value, Value::Bool(false));
+    let (value, _) = run("1 != 2")?;
+    assert_eq!(value, Value::Bool(true));
+    Ok(())
+}
+
+#[test]
+fn test_union() -> Result<(), Box<dyn std::error::Error>> {
+    let (value, _) = run("
+      class A
+        def pick(n: Int) -> Int | Float
+          if n < 0
+            n.to_f
+          else
+            n
+          end
+        end
+        def abs(n: Int) -> Float
+          x = pick(n)
+          if x is Int
+            x.to_f
+          else
+            0.0 - x
+          end
+        end
+      end
+      A.new.abs(3) + A.new.abs(0 - 4)
+    ")?;
+    assert_eq!(value, Value::Float(7.0));
+    Ok(())
+}
+
+#[test]
+fn test_interface() -> Result<(), Box<dyn std::error::Error>> {
+    let (value, _) = run("
+      interface Shape
+        def area -> Int
+      end
+      class Square : Shape
+        def area -> Int
+          4
+        end
+      end
+      class Circle : Shape
+        def area -> Int
+          3
+        end
+      end
+      class A
+        def total(a: Shape, b: Shape) -> Int
+          a.area + b.area
+        end
+      end
+      A.new.total(Square.new, Circle.new)
+    ")?;
+    assert_eq!(value, Value::Int(7));
+    Ok(())
+}
+
+#[test]
+fn test_generic_method() -> Result<(), Box<dyn std::error::Error>> {
+    let (value, _) = run("
+      class A
+        def first<T>(a: T, b: T) -> T
+          a
+        end
+        def or_else<T>(x: T?, default: T) -> T
+          if x != nil
+            x
+          else
+            default
+          end
+        end
+        def maybe(n: Int) -> Int?
+          if n > 0
+            n
+          else
+            nil
+          end
+        end
+      end
+      a = A.new
+      a.first(3, 4) + a.or_else(a.maybe(0), 10) + a.or_else(a.maybe(5), 10)
+    ")?;
+    assert_eq!(value, Value::Int(18));
+    Ok(())
+}
+
+#[test]
+fn test_bounded_type_parameter() -> Result<(), Box<dyn std::error::Error>> {
+    let (value, _) = run("
+      interface Sized
+        def size -> Int
+      end
+      class Cup : Sized
+        def size -> Int
+          3
+        end
+      end
+      class Bag : Sized
+        def size -> Int
+          5
+        end
+      end
+      class A
+        def size_of<T : Sized>(x: T) -> Int
+          x.size
+        end
+        def twice<T : Sized>(x: T) -> Int
+          size_of(x) + size_of(x)
+        end
+      end
+      a = A.new
+      a.size_of(Bag.new) + a.twice(Cup.new)
+    ")?;
+    assert_eq!(value, Value::Int(11));
+    Ok(())
+}
+
+#[test]
+fn test_annotation_and_cast() -> Result<(), Box<dyn std::error::Error>> {
+    let (value, _) = run("
+      interface Shape
+        def area -> Int
+      end
+      class Square : Shape
+        def area -> Int
+          4
+        end
+        def side -> Int
+          2
+        end
+      end
+      var s: Shape = Square.new
+      let n: Int | String = 3
+      var o: Object = 1
+      o = Square.new
+      (s as Square).side + (n as Int) + s.area
+    ")?;
+    assert_eq!(value, Value::Int(9));
+
+    let err = run("let x: Int | String = \"a\"\nx as Int").unwrap_err();
+    assert_eq!(err.to_string(), "invalid cast");
+    Ok(())
+}
+
+#[test]
+fn test_default_and_keyword_args() -> Result<(), Box<dyn std::error::Error>> {
+    let (value, _) = run("
+      class A
+        def base -> Int
+          100
+        end
+        def f(x: Int, y: Int = x + 1, z: Int = base) -> Int
+          x + y + z
+        end
+      end
+      a = A.new
+      a.f(1) + a.f(1, 2, 3) * 1000 + a.f(1, z: 5) * 1000000
+    ")?;
+    // 103, 6 and 8
+    assert_eq!(value, Value::Int(8006103));
+    Ok(())
+}
@@ -0,0 +1,64 @@
+use std::fs;
+use std::path::PathBuf;
+use shiika::loader;
+
+/// Create a directory for the test and write the files into it
+fn setup(name: &str, files: &[(&str, &str)]) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("shiika_loader_test_{}", name));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    for (filename, src) in files {
+        fs::write(dir.join(filename), src).unwrap();
+    }
+    dir
+}
+
+#[test]
+fn test_require() {
+    let dir = setup("require", &[
+        ("main.sk", "require \"a\"\nA.new.foo\n"),
+        ("a.sk", "require \"b.sk\"\nclass A\n  def foo -> Int\n    B.new.bar\n  end\nend\n"),
+        ("b.sk", "class B\n  def bar -> Int\n    1\n  end\nend\n"),
+    ]);
+    let program = loader::load(&dir.join("main.sk")).unwrap();
+    assert_eq!(program.toplevel_defs.len(), 2);
+    assert_eq!(program.exprs.len(), 1);
+    assert!(program.requires.is_empty());
+}
+
+#[test]
+fn test_require_once() {
+    let dir = setup("require_once", &[
+        ("main.sk", "require \"a\"\nrequire \"b\"\nA.new\n"),
+        ("a.sk", "require \"c\"\nclass A\nend\n"),
+        ("b.sk", "require \"c\"\nclass B\nend\n"),
+        ("c.sk", "class C\nend\n"),
+    ]);
+    let program = loader::load(&dir.join("main.sk")).unwrap();
+    assert_eq!(program.toplevel_defs.len(), 3);
+}
+
+#[test]
+fn test_circular_require() {
+    let dir = setup("circular_require", &[
+        ("main.sk", "require \"a\"\n"),
+        ("a.sk", "require \"b\"\n"),
+        ("b.sk", "require \"a\"\n"),
+    ]);
+    let err = loader::load(&dir.join("main.sk")).unwrap_err();
+    let a = dir.join("a.sk").canonicalize().unwrap();
+    let b = dir.join("b.sk").canonicalize().unwrap();
+    assert_eq!(err.msg, format!("circular require: {} -> {} -> {}", a.display(), b.display(), a.display()));
+}
+
+#[test]
+fn test_missing_file() {
+    let dir = setup("missing_file", &[
+        ("main.sk", "require \"a\"\n"),
+        ("a.sk", "\n\nrequire \"nothing\"\n"),
+    ]);
+    let err = loader::load(&dir.join("main.sk")).unwrap_err();
+    assert_eq!(err.msg, "cannot load such file -- nothing");
+    let a = dir.join("a.sk").canonicalize().unwrap();
+    assert_eq!(err.to_string(), format!("{}:3:1: cannot load such file -- nothing", a.display()));
+}
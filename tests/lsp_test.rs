@@ -0,0 +1,116 @@
+use serde_json::{json, Value};
+
+const URI: &str = "file:///a.sk";
+const SRC: &str = "class A
+  def foo -> Int
+    1 + 2
+  end
+end
+A.new.foo
+";
+
+/// Run the server with the scripted messages and return the output messages
+fn run_session(msgs: Vec<Value>) -> Vec<Value> {
+    let mut input = String::new();
+    for msg in msgs {
+        let body = msg.to_string();
+        input.push_str(&format!("Content-Length: {}\r\n\r\n{}", body.len(), body));
+    }
+    let mut output = vec![];
+    shiika::lsp::run(&mut std::io::Cursor::new(input.into_bytes()), &mut output).unwrap();
+
+    let output = String::from_utf8(output).unwrap();
+    output.split("Content-Length: ").skip(1).map(|s| {
+        let body = s.splitn(2, "\r\n\r\n").nth(1).unwrap();
+        serde_json::from_str(body).unwrap()
+    }).collect()
+}
+
+fn open(text: &str) -> Value {
+    json!({ "jsonrpc": "2.0", "method": "textDocument/didOpen", "params": {
+        "textDocument": { "uri": URI, "languageId": "shiika", "version": 1, "text": text }
+    }})
+}
+
+fn request(id: i64, method: &str, line: usize, character: usize) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": {
+        "textDocument": { "uri": URI },
+        "position": { "line": line, "character": character },
+    }})
+}
+
+fn response(msgs: &[Value], id: i64) -> &Value {
+    &msgs.iter().find(|msg| msg["id"] == json!(id)).unwrap()["result"]
+}
+
+#[test]
+fn test_initialize_and_shutdown() {
+    let msgs = run_session(vec![
+        json!({ "jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {} }),
+        json!({ "jsonrpc": "2.0", "id": 2, "method": "shutdown" }),
+        json!({ "jsonrpc": "2.0", "method": "exit" }),
+    ]);
+    assert_eq!(response(&msgs, 1)["capabilities"]["hoverProvider"], json!(true));
+    assert_eq!(response(&msgs, 2), &Value::Null);
+}
+
+#[test]
+fn test_diagnostics() {
+    let msgs = run_session(vec![open("if 1 then 2 else 3 end\n")]);
+    assert_eq!(msgs[0]["method"], json!("textDocument/publishDiagnostics"));
+    let diag = &msgs[0]["params"]["diagnostics"][0];
+    assert_eq!(diag["code"], json!("type_error"));
+    assert_eq!(diag["range"]["start"], json!({ "line": 0, "character": 0 }));
+}
+
+#[test]
+fn test_hover() {
+    let msgs = run_session(vec![open(SRC), request(1, "textDocument/hover", 5, 7)]);
+    assert!(msgs[0]["params"]["diagnostics"].as_array().unwrap().is_empty());
+    assert_eq!(response(&msgs, 1)["contents"]["value"], json!("Int"));
+}
+
+#[test]
+fn test_definition() {
+    let msgs = run_session(vec![
+        open(SRC),
+        request(1, "textDocument/definition", 5, 7),
+        request(2, "textDocument/definition", 5, 0),
+    ]);
+    assert_eq!(response(&msgs, 1)["range"]["start"], json!({ "line": 1, "character": 6 }));
+    assert_eq!(response(&msgs, 2)["range"]["start"], json!({ "line": 0, "character": 6 }));
+}
+
+#[test]
+fn test_completion() {
+    let change = json!({ "jsonrpc": "2.0", "method": "textDocument/didChange", "params": {
+        "textDocument": { "uri": URI, "version": 2 },
+        "contentChanges": [{ "text": SRC.replace("A.new.foo", "A.new.") }],
+    }});
+    let msgs = run_session(vec![open(SRC), change, request(1, "textDocument/completion", 5, 6)]);
+    let labels = response(&msgs, 1).as_array().unwrap().iter()
+        .map(|item| item["label"].as_str().unwrap().to_string())
+        .collect::<Vec<_>>();
+    assert!(labels.contains(&"foo".to_string()));
+}
+
+#[test]
+fn test_percent_encoded_uri() {
+    let dir = std::env::temp_dir().join("shiika lsp test");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let src = "require \"b\"\nB.new\nif 1 then 2 else 3 end\n";
+    std::fs::write(dir.join("a.sk"), src).unwrap();
+    std::fs::write(dir.join("b.sk"), "class B\nend\n").unwrap();
+    let uri = format!("file://{}/a.sk", dir.to_string_lossy().replace(' ', "%20"));
+    let msgs = run_session(vec![
+        json!({ "jsonrpc": "2.0", "method": "textDocument/didOpen", "params": {
+            "textDocument": { "uri": uri, "languageId": "shiika", "version": 1, "text": src }
+        }}),
+    ]);
+    // `b.sk` is found and the error is located in the document itself
+    let diags = msgs[0]["params"]["diagnostics"].as_array().unwrap();
+    assert_eq!(diags.len(), 1);
+    assert_eq!(diags[0]["code"], json!("type_error"));
+    assert_eq!(diags[0]["range"]["start"], json!({ "line": 2, "character": 0 }));
+}
@@ -0,0 +1,59 @@
+use shiika::hir::*;
+use shiika::hir::opt::ConstValue;
+use shiika::interp::{Interpreter, Value};
+use shiika::names::*;
+use shiika::stdlib::Stdlib;
+
+fn optimized(src: &str) -> Result<Hir, Box<dyn std::error::Error>> {
+    let ast = shiika::parser::Parser::parse(src)?;
+    let mut hir = Hir::from_ast(ast, Stdlib::create())?;
+    opt::optimize(&mut hir);
+    Ok(hir)
+}
+
+#[test]
+fn test_fold_arithmetic() -> Result<(), Box<dyn std::error::Error>> {
+    let hir = optimized("(1 + 2) << 3")?;
+    assert_eq!(hir.main_exprs.exprs.last().unwrap(), &Hir::decimal_literal(24));
+    let hir = optimized("1.5.to_i.to_f - 0.5")?;
+    assert_eq!(hir.main_exprs.exprs.last().unwrap(), &Hir::float_literal(0.5));
+    Ok(())
+}
+
+#[test]
+fn test_fold_if() -> Result<(), Box<dyn std::error::Error>> {
+    let hir = optimized("if 1 < 2 then 3 else 4 end")?;
+    assert_eq!(hir.main_exprs.exprs.last().unwrap(), &Hir::decimal_literal(3));
+    Ok(())
+}
+
+#[test]
+fn test_const_values() -> Result<(), Box<dyn std::error::Error>> {
+    let hir = optimized("
+      A = 1 + 1
+      B = A + 1
+      B
+    ")?;
+    let fullname = ConstFullname("::B".to_string());
+    assert_eq!(hir.const_values.get(&fullname), Some(&ConstValue::Int(3)));
+    assert_eq!(hir.main_exprs.exprs.last().unwrap(), &Hir::decimal_literal(3));
+
+    let mut out = vec![];
+    assert_eq!(Interpreter::new(&hir, &mut out).run()?, Value::Int(3));
+    Ok(())
+}
+
+#[test]
+fn test_no_fold_on_panic() -> Result<(), Box<dyn std::error::Error>> {
+    let hir = optimized("2147483647 + 1")?;
+    assert_ne!(hir.main_exprs.exprs.last().unwrap().node, Hir::decimal_literal(-2147483648).node);
+    let hir = optimized("(7 * 3) / 0")?;
+    match &hir.main_exprs.exprs.last().unwrap().node {
+        HirExpressionBase::HirMethodCall { receiver_expr, .. } => {
+            assert_eq!(**receiver_expr, Hir::decimal_literal(21));
+        },
+        node => panic!("unexpected node: {:?}", node),
+    }
+    Ok(())
+}
+
@@ -0,0 +1,48 @@
+use shiika::error::ErrorDetails;
+use shiika::repl::Repl;
+
+#[test]
+fn test_panic() -> Result<(), Box<dyn std::error::Error>> {
+    let mut repl = Repl::new();
+    repl.eval("class A\n  def foo(n: Int) -> Int\n    10 / n\n  end\nend")?;
+    let err = repl.eval("A.new.foo(0)").unwrap_err();
+    assert_eq!(err.msg, "division by zero");
+    match err.details {
+        ErrorDetails::Panic { trace } => {
+            assert_eq!(trace.len(), 2);
+            assert!(trace[0].starts_with("A#foo "));
+            assert!(trace[1].starts_with("main "));
+        },
+        details => panic!("unexpected error: {:?}", details),
+    }
+
+    // The shell survives the panics
+    let err = repl.eval("panic(\"oops\")").unwrap_err();
+    assert_eq!(err.msg, "oops");
+    assert_eq!(repl.eval("A.new.foo(5)")?, "2 : Int");
+    Ok(())
+}
+
+#[test]
+fn test_redefinition() -> Result<(), Box<dyn std::error::Error>> {
+    let mut repl = Repl::new();
+    repl.eval("var x = 1")?;
+    assert_eq!(repl.eval("x = x + 1")?, "2 : Int");
+    assert_eq!(repl.eval("x")?, "2 : Int");
+    assert_eq!(repl.eval("x = x + 1")?, "3 : Int");
+    repl.eval("s = \"hello\"")?;
+    assert_eq!(repl.eval("s")?, "\"hello\" : String");
+    Ok(())
+}
+
+#[test]
+fn test_side_effecting_assignment() -> Result<(), Box<dyn std::error::Error>> {
+    let mut repl = Repl::new();
+    repl.eval("extern def rand() -> Int")?;
+    repl.eval("x = rand()")?;
+    repl.eval("A = rand()")?;
+    // The right hand sides are not evaluated again
+    assert_eq!(repl.eval("x")?, repl.eval("x")?);
+    assert_eq!(repl.eval("A")?, repl.eval("A")?);
+    Ok(())
+}
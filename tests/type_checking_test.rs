@@ -13,7 +13,7 @@ fn test_discarding_return_value() -> Result<(), Box<dyn std::error::Error>> {
       end
     ";
     let ast = shiika::parser::Parser::parse(src)?;
-    let hir = shiika::hir::Hir::from_ast(ast, Stdlib::empty())?;
+    let hir = shiika::hir::Hir::from_ast(ast, Stdlib::create())?;
     println!("sk_methods: {:?}", hir.sk_methods);
     let method = &hir.sk_methods.get(&ClassFullname("A".to_string())).unwrap()[0];
     assert_eq!(method.signature.ret_ty, ty::raw("Void"));
@@ -23,8 +23,216 @@ fn test_discarding_return_value() -> Result<(), Box<dyn std::error::Error>> {
             exprs: vec![ HirExpression {
                 ty: ty::raw("Int"),
                 node: HirExpressionBase::HirDecimalLiteral { value: 42 },
+                locs: None,
             }],
         }
     });
     Ok(())
 }
+
+#[test]
+fn test_method_call_on_optional() -> Result<(), Box<dyn std::error::Error>> {
+    let src = "
+      class A
+        def foo(x: Int?) -> Int
+          x.abs
+        end
+      end
+    ";
+    let ast = shiika::parser::Parser::parse(src)?;
+    let err = shiika::hir::Hir::from_ast(ast, Stdlib::create()).unwrap_err();
+    assert!(err.msg.contains("which may be nil"), "{}", err.msg);
+    Ok(())
+}
+
+#[test]
+fn test_if_with_void_branch() -> Result<(), Box<dyn std::error::Error>> {
+    for src in &["if 1 < 2 then 1.0 else putchar(65) end", "if 1 < 2 then putchar(65) else 1 end"] {
+        let ast = shiika::parser::Parser::parse(src)?;
+        let hir = shiika::hir::Hir::from_ast(ast, Stdlib::create())?;
+        assert_eq!(hir.main_exprs.ty, ty::raw("Void"), "{}", src);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_logical_not_on_non_bool() -> Result<(), Box<dyn std::error::Error>> {
+    let ast = shiika::parser::Parser::parse("!1")?;
+    let err = shiika::hir::Hir::from_ast(ast, Stdlib::create()).unwrap_err();
+    assert!(err.msg.contains("operand of ! must be bool"), "{}", err.msg);
+    Ok(())
+}
+
+#[test]
+fn test_union_conformance() {
+    let int_or_str = ty::union(vec![ty::raw("Int"), ty::raw("String")]);
+    assert_eq!(int_or_str.fullname.0, "Int | String");
+    assert!(ty::raw("Int").conforms_to(&int_or_str));
+    assert!(!ty::raw("Float").conforms_to(&int_or_str));
+    assert!(!int_or_str.conforms_to(&ty::raw("Int")));
+    let str_or_int = ty::union(vec![ty::raw("String"), ty::union(vec![ty::raw("Int")])]);
+    assert!(int_or_str.equals_to(&str_or_int));
+    assert!(int_or_str.conforms_to(&ty::union(vec![ty::raw("Int"), ty::raw("Float"), ty::raw("String")])));
+
+    let a_and_b = ty::intersection(vec![ty::raw("A"), ty::raw("B")]);
+    assert!(a_and_b.conforms_to(&ty::raw("A")));
+    assert!(!ty::raw("A").conforms_to(&a_and_b));
+}
+
+#[test]
+fn test_method_call_on_union() -> Result<(), Box<dyn std::error::Error>> {
+    let src = "
+      class A
+        def foo(x: Int | Float) -> Int
+          x.abs
+        end
+      end
+    ";
+    let ast = shiika::parser::Parser::parse(src)?;
+    let err = shiika::hir::Hir::from_ast(ast, Stdlib::create()).unwrap_err();
+    assert!(err.msg.contains("narrow it with `is'"), "{}", err.msg);
+    Ok(())
+}
+
+#[test]
+fn test_interface_conformance() -> Result<(), Box<dyn std::error::Error>> {
+    let src = "
+      interface Shape
+        def area -> Int
+      end
+      class Square : Shape
+      end
+    ";
+    let ast = shiika::parser::Parser::parse(src)?;
+    let err = shiika::hir::Hir::from_ast(ast, Stdlib::create()).unwrap_err();
+    assert_eq!(err.msg, "Square does not implement Shape#area");
+
+    let src = "
+      interface Shape
+        def area -> Int
+      end
+      class Circle : Shape
+        def area -> Float
+          3.14
+        end
+      end
+    ";
+    let ast = shiika::parser::Parser::parse(src)?;
+    let err = shiika::hir::Hir::from_ast(ast, Stdlib::create()).unwrap_err();
+    assert!(err.msg.contains("does not match Shape#area"), "{}", err.msg);
+    Ok(())
+}
+
+#[test]
+fn test_generic_method_inference() -> Result<(), Box<dyn std::error::Error>> {
+    let src = "
+      class A
+        def first<T>(a: T, b: T) -> T
+          a
+        end
+      end
+      A.new.first(1, 2.0)
+    ";
+    let ast = shiika::parser::Parser::parse(src)?;
+    let err = shiika::hir::Hir::from_ast(ast, Stdlib::create()).unwrap_err();
+    assert_eq!(err.msg, "T of A#first is inferred as Int but got Float");
+
+    let src = "
+      class A
+        def make<T> -> T?
+          nil
+        end
+      end
+      A.new.make
+    ";
+    let ast = shiika::parser::Parser::parse(src)?;
+    let err = shiika::hir::Hir::from_ast(ast, Stdlib::create()).unwrap_err();
+    assert_eq!(err.msg, "cannot infer type parameter T of A#make");
+    Ok(())
+}
+
+#[test]
+fn test_type_parameter_bound() -> Result<(), Box<dyn std::error::Error>> {
+    let src = "
+      interface Sized
+        def size -> Int
+      end
+      class A
+        def size_of<T : Sized>(x: T) -> Int
+          x.size
+        end
+      end
+      A.new.size_of(1)
+    ";
+    let ast = shiika::parser::Parser::parse(src)?;
+    let err = shiika::hir::Hir::from_ast(ast, Stdlib::create()).unwrap_err();
+    assert_eq!(err.msg, "T of A#size_of must implement Sized but got Int");
+
+    let src = "
+      class A
+        def foo<T : Int>(x: T) -> T
+          x
+        end
+      end
+    ";
+    let ast = shiika::parser::Parser::parse(src)?;
+    let err = shiika::hir::Hir::from_ast(ast, Stdlib::create()).unwrap_err();
+    assert_eq!(err.msg, "bound of T must be an interface but got Int");
+    Ok(())
+}
+
+#[test]
+fn test_class_and_interface_of_same_name() -> Result<(), Box<dyn std::error::Error>> {
+    for src in &["interface Foo\nend\nclass Foo\nend", "class Foo\nend\ninterface Foo\nend"] {
+        let ast = shiika::parser::Parser::parse(src)?;
+        let err = shiika::hir::Hir::from_ast(ast, Stdlib::create()).unwrap_err();
+        assert_eq!(err.msg, "`Foo' is already defined", "{}", src);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_unknown_type() -> Result<(), Box<dyn std::error::Error>> {
+    let ast = shiika::parser::Parser::parse("1 as Foo")?;
+    let err = shiika::hir::Hir::from_ast(ast, Stdlib::create()).unwrap_err();
+    assert_eq!(err.msg, "unknown type `Foo'");
+
+    let ast = shiika::parser::Parser::parse("class A\n  def foo(x: Int | Bar) -> Int\n    1\n  end\nend")?;
+    let err = shiika::hir::Hir::from_ast(ast, Stdlib::create()).unwrap_err();
+    assert_eq!(err.msg, "unknown type `Bar'");
+    Ok(())
+}
+
+#[test]
+fn test_lvar_annotation_and_cast() -> Result<(), Box<dyn std::error::Error>> {
+    let ast = shiika::parser::Parser::parse("var x: Int = \"a\"")?;
+    let err = shiika::hir::Hir::from_ast(ast, Stdlib::create()).unwrap_err();
+    assert_eq!(err.msg, "variable x is declared as Int but got String");
+
+    let ast = shiika::parser::Parser::parse("let o: Object = 1\no as Int")?;
+    let err = shiika::hir::Hir::from_ast(ast, Stdlib::create()).unwrap_err();
+    assert_eq!(err.msg, "cannot cast Object to Int");
+    Ok(())
+}
+
+#[test]
+fn test_default_and_keyword_args() -> Result<(), Box<dyn std::error::Error>> {
+    let src = "class A\n  def f(x: Int, y: Int = 1) -> Int\n    x + y\n  end\nend\n";
+    let cases = vec![
+        ("A.new.f", "missing argument `x' of A#f"),
+        ("A.new.f(1, 2, 3)", "A#f takes 2 args but got 3"),
+        ("A.new.f(1, z: 2)", "A#f has no parameter named `z'"),
+        ("A.new.f(1, x: 2)", "argument `x' of A#f is given more than once"),
+        ("A.new.f(y: 1, 2)", "positional argument after keyword arguments"),
+    ];
+    for (call, msg) in cases {
+        let ast = shiika::parser::Parser::parse(&(src.to_string() + call))?;
+        let err = shiika::hir::Hir::from_ast(ast, Stdlib::create()).unwrap_err();
+        assert_eq!(err.msg, msg);
+    }
+
+    let ast = shiika::parser::Parser::parse("class A\n  def f(x: Int = \"a\")\n  end\nend")?;
+    let err = shiika::hir::Hir::from_ast(ast, Stdlib::create()).unwrap_err();
+    assert_eq!(err.msg, "default value of `x' of A#f should be Int but got String");
+    Ok(())
+}